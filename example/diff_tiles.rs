@@ -0,0 +1,42 @@
+use papercut::{TileChange, diff_heatmap, diff_tiles, load_tile_source};
+use pico_args::Arguments;
+use std::path::PathBuf;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = Arguments::from_env();
+
+    // Parse arguments
+    let old: PathBuf = args.value_from_str("--old")?;
+    let new: PathBuf = args.value_from_str("--new")?;
+    let threshold: u8 = args.opt_value_from_str("--threshold")?.unwrap_or(0);
+    let heatmap: Option<PathBuf> = args.opt_value_from_str("--heatmap")?;
+
+    // Compare the tile sets
+    let old_tiles = load_tile_source(&old)?;
+    let new_tiles = load_tile_source(&new)?;
+    let diffs = diff_tiles(&old_tiles, &new_tiles, threshold);
+
+    let mut unchanged = 0;
+    for diff in &diffs {
+        match diff.change {
+            TileChange::Unchanged => unchanged += 1,
+            TileChange::Changed => println!(
+                "changed {:?} max delta {} PSNR {:.2} dB SSIM {:.4}",
+                diff.position, diff.max_delta, diff.psnr, diff.ssim
+            ),
+            change => println!(
+                "{} {:?}",
+                format!("{:?}", change).to_lowercase(),
+                diff.position
+            ),
+        }
+    }
+    println!("{} of {} tiles unchanged", unchanged, diffs.len());
+
+    // Render the heatmap
+    if let Some(path) = heatmap {
+        diff_heatmap(&old_tiles, &new_tiles)?.save(&path)?;
+    }
+
+    Ok(())
+}
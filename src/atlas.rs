@@ -0,0 +1,646 @@
+//! The `atlas` module is the inverse of slicing: it packs many images into one
+//! or more texture atlas pages. A packing layout engine decides where each image
+//! goes, and the pages are then composited with `join`.
+//!
+//! # Features
+//!
+//! - **Bin Packing**: MaxRects (best short side fit) or skyline (bottom-left).
+//! - **Rotation**: Images may be turned 90 degrees when that packs tighter.
+//! - **Padding**: A gap of empty pixels between neighbouring images.
+//! - **Extrusion**: Edge pixels repeated outwards to stop texture bleeding.
+//! - **Power of Two**: Page sizes can be rounded up to powers of two for older GPUs.
+//! - **Multiple Pages**: Images that do not fit on a page spill onto the next one.
+//!
+//! # Example Usage
+//!
+//! ```no_run
+//! use papercut::{open_images, pack_atlas, AtlasOptions};
+//! use std::path::Path;
+//!
+//! let images = open_images(Path::new("./sprites")).unwrap();
+//! let atlas = pack_atlas(&images, &AtlasOptions::default()).unwrap();
+//! for (i, page) in atlas.pages.iter().enumerate() {
+//!     page.image.save(format!("atlas_{}.png", i)).unwrap();
+//! }
+//! ```
+
+use image::DynamicImage;
+
+use crate::join;
+use crate::padding::pad_tile;
+use crate::tile::Tile;
+
+/// Bin packing strategy used by `pack_layout`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackingAlgorithm {
+    /// Keeps a list of maximal free rectangles and picks the best short side fit.
+    MaxRects,
+    /// Tracks the skyline of placed rectangles and places each as low as possible.
+    Skyline,
+}
+
+/// Options for `pack_layout` and `pack_atlas`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasOptions {
+    /// Maximum width of a page in pixels.
+    pub max_width: u32,
+    /// Maximum height of a page in pixels.
+    pub max_height: u32,
+    /// Empty pixels left between neighbouring images.
+    pub padding: u32,
+    /// Pixels by which each image's border is extruded before packing, so that
+    /// bilinear sampling does not bleed in neighbours. Packed tiles record the
+    /// original content in `Tile::inner`.
+    pub extrude: u32,
+    /// Whether images may be rotated 90 degrees clockwise.
+    pub allow_rotation: bool,
+    /// Whether page sizes are rounded up to powers of two.
+    pub power_of_two: bool,
+    /// The packing strategy.
+    pub algorithm: PackingAlgorithm,
+}
+
+impl Default for AtlasOptions {
+    fn default() -> Self {
+        AtlasOptions {
+            max_width: 2048,
+            max_height: 2048,
+            padding: 2,
+            extrude: 0,
+            allow_rotation: false,
+            power_of_two: false,
+            algorithm: PackingAlgorithm::MaxRects,
+        }
+    }
+}
+
+/// Where one input rectangle ended up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    /// Index of the page the rectangle was placed on.
+    pub page: usize,
+    /// Left edge on the page.
+    pub x: u32,
+    /// Top edge on the page.
+    pub y: u32,
+    /// Whether the rectangle was rotated 90 degrees clockwise.
+    pub rotated: bool,
+}
+
+/// The result of `pack_layout`: one placement per input and the size of each page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    /// Placements, in the same order as the input sizes.
+    pub placements: Vec<Placement>,
+    /// Width and height of each page.
+    pub page_sizes: Vec<(u32, u32)>,
+}
+
+/// A single atlas page.
+#[derive(Debug, Clone)]
+pub struct AtlasPage {
+    /// The composited page.
+    pub image: DynamicImage,
+    /// The images on this page. `coords` is the position on the page and `number`
+    /// is the 1-based index of the image in the input.
+    pub tiles: Vec<Tile>,
+}
+
+/// The result of `pack_atlas`.
+#[derive(Debug, Clone)]
+pub struct Atlas {
+    /// The atlas pages.
+    pub pages: Vec<AtlasPage>,
+    /// Placements, in the same order as the input images.
+    pub placements: Vec<Placement>,
+}
+
+/// Compute where rectangles of the given sizes go on atlas pages.
+///
+/// Rectangles are packed largest first. Each page is filled as far as possible
+/// before the remaining rectangles move on to a new page.
+///
+/// # Errors
+///
+/// Returns an error if a rectangle does not fit on an empty page.
+pub fn pack_layout(sizes: &[(u32, u32)], options: &AtlasOptions) -> Result<Layout, String> {
+    let (max_w, max_h) = page_limit(options);
+    let pad = options.padding;
+
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| {
+        let (w, h) = sizes[i];
+        (std::cmp::Reverse(w.max(h)), std::cmp::Reverse(w.min(h)), i)
+    });
+
+    let mut placements = vec![None; sizes.len()];
+    let mut page_sizes = Vec::new();
+    while !order.is_empty() {
+        let page = page_sizes.len();
+        // Every rectangle is padded on its right and bottom; the page grows by the
+        // same amount so the last column and row need no trailing gap.
+        let mut bin: Box<dyn Bin> = match options.algorithm {
+            PackingAlgorithm::MaxRects => Box::new(MaxRects::new(max_w + pad, max_h + pad)),
+            PackingAlgorithm::Skyline => Box::new(Skyline::new(max_w + pad, max_h + pad)),
+        };
+        let mut extent = (0, 0);
+        let mut remaining = Vec::new();
+        for &i in &order {
+            let (w, h) = sizes[i];
+            match bin.insert(w + pad, h + pad, options.allow_rotation) {
+                Some((x, y, rotated)) => {
+                    let (w, h) = if rotated { (h, w) } else { (w, h) };
+                    extent = (extent.0.max(x + w), extent.1.max(y + h));
+                    placements[i] = Some(Placement {
+                        page,
+                        x,
+                        y,
+                        rotated,
+                    });
+                }
+                None => remaining.push(i),
+            }
+        }
+        if remaining.len() == order.len() {
+            let (w, h) = sizes[order[0]];
+            return Err(format!(
+                "Image of {}x{} does not fit on a {}x{} page.",
+                w, h, max_w, max_h
+            ));
+        }
+        if options.power_of_two {
+            extent = (extent.0.next_power_of_two(), extent.1.next_power_of_two());
+        }
+        page_sizes.push(extent);
+        order = remaining;
+    }
+
+    Ok(Layout {
+        placements: placements.into_iter().map(Option::unwrap).collect(),
+        page_sizes,
+    })
+}
+
+/// Pack images into atlas pages.
+///
+/// # Errors
+///
+/// Returns an error if an image does not fit on an empty page.
+pub fn pack_atlas(images: &[DynamicImage], options: &AtlasOptions) -> Result<Atlas, String> {
+    let e = options.extrude;
+    let sizes: Vec<(u32, u32)> = images
+        .iter()
+        .map(|im| (im.width() + 2 * e, im.height() + 2 * e))
+        .collect();
+    let layout = pack_layout(&sizes, options)?;
+
+    let mut pages: Vec<Vec<Tile>> = vec![Vec::new(); layout.page_sizes.len()];
+    for (i, (image, placement)) in images.iter().zip(&layout.placements).enumerate() {
+        let image = if e > 0 {
+            let tile = Tile::new(image.clone(), 0, (0, 0), (0, 0), None);
+            pad_tile(&tile, e, None).image
+        } else {
+            image.clone()
+        };
+        let image = if placement.rotated {
+            image.rotate90()
+        } else {
+            image
+        };
+        let tiles = &mut pages[placement.page];
+        tiles.push(Tile::new(
+            image,
+            i as i32 + 1,
+            (tiles.len() as i32 + 1, placement.page as i32 + 1),
+            (placement.x as i32, placement.y as i32),
+            None,
+        ));
+    }
+
+    let pages = pages
+        .into_iter()
+        .zip(&layout.page_sizes)
+        .map(|(mut tiles, &(width, height))| {
+            let image = join(&tiles, width, height)?;
+            if e > 0 {
+                for tile in tiles.iter_mut() {
+                    let (w, h) = (tile.image.width(), tile.image.height());
+                    tile.inner = Some((e, e, w - 2 * e, h - 2 * e));
+                }
+            }
+            Ok(AtlasPage { image, tiles })
+        })
+        .collect::<Result<_, String>>()?;
+
+    Ok(Atlas {
+        pages,
+        placements: layout.placements,
+    })
+}
+
+/// Largest page size allowed by `options`.
+fn page_limit(options: &AtlasOptions) -> (u32, u32) {
+    let floor_pot = |v: u32| {
+        if v == 0 {
+            0
+        } else {
+            1 << (31 - v.leading_zeros())
+        }
+    };
+    if options.power_of_two {
+        (floor_pot(options.max_width), floor_pot(options.max_height))
+    } else {
+        (options.max_width, options.max_height)
+    }
+}
+
+/// A packing strategy for a single page.
+trait Bin {
+    /// Places a `width x height` rectangle, returning its position and whether it
+    /// was rotated, or `None` if it does not fit.
+    fn insert(&mut self, width: u32, height: u32, allow_rotation: bool)
+    -> Option<(u32, u32, bool)>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rect {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+impl Rect {
+    fn contains(&self, other: &Rect) -> bool {
+        other.x >= self.x
+            && other.y >= self.y
+            && other.x + other.w <= self.x + self.w
+            && other.y + other.h <= self.y + self.h
+    }
+
+    fn intersects(&self, other: &Rect) -> bool {
+        self.x < other.x + other.w
+            && other.x < self.x + self.w
+            && self.y < other.y + other.h
+            && other.y < self.y + self.h
+    }
+}
+
+struct MaxRects {
+    free: Vec<Rect>,
+}
+
+impl MaxRects {
+    fn new(width: u32, height: u32) -> Self {
+        MaxRects {
+            free: vec![Rect {
+                x: 0,
+                y: 0,
+                w: width,
+                h: height,
+            }],
+        }
+    }
+
+    fn place(&mut self, used: Rect) {
+        let mut split = Vec::new();
+        self.free.retain(|free| {
+            if !free.intersects(&used) {
+                return true;
+            }
+            if used.x > free.x {
+                split.push(Rect {
+                    w: used.x - free.x,
+                    ..*free
+                });
+            }
+            if used.x + used.w < free.x + free.w {
+                let x = used.x + used.w;
+                split.push(Rect {
+                    x,
+                    w: free.x + free.w - x,
+                    ..*free
+                });
+            }
+            if used.y > free.y {
+                split.push(Rect {
+                    h: used.y - free.y,
+                    ..*free
+                });
+            }
+            if used.y + used.h < free.y + free.h {
+                let y = used.y + used.h;
+                split.push(Rect {
+                    y,
+                    h: free.y + free.h - y,
+                    ..*free
+                });
+            }
+            false
+        });
+        self.free.extend(split);
+
+        // Drop free rectangles contained in another one.
+        let mut i = 0;
+        while i < self.free.len() {
+            let redundant = (0..self.free.len()).any(|j| {
+                j != i
+                    && self.free[j].contains(&self.free[i])
+                    && (self.free[j] != self.free[i] || j < i)
+            });
+            if redundant {
+                self.free.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+impl Bin for MaxRects {
+    fn insert(
+        &mut self,
+        width: u32,
+        height: u32,
+        allow_rotation: bool,
+    ) -> Option<(u32, u32, bool)> {
+        let mut best: Option<((u32, u32), Rect, bool)> = None;
+        for free in &self.free {
+            for rotated in [false, true] {
+                if rotated && !allow_rotation {
+                    continue;
+                }
+                let (w, h) = if rotated {
+                    (height, width)
+                } else {
+                    (width, height)
+                };
+                if w > free.w || h > free.h {
+                    continue;
+                }
+                let (a, b) = (free.w - w, free.h - h);
+                let score = (a.min(b), a.max(b));
+                if best.as_ref().is_none_or(|(s, _, _)| score < *s) {
+                    best = Some((
+                        score,
+                        Rect {
+                            x: free.x,
+                            y: free.y,
+                            w,
+                            h,
+                        },
+                        rotated,
+                    ));
+                }
+            }
+        }
+        let (_, used, rotated) = best?;
+        self.place(used);
+        Some((used.x, used.y, rotated))
+    }
+}
+
+struct Skyline {
+    width: u32,
+    height: u32,
+    /// Segments `(x, y, width)` from left to right.
+    segments: Vec<(u32, u32, u32)>,
+}
+
+impl Skyline {
+    fn new(width: u32, height: u32) -> Self {
+        Skyline {
+            width,
+            height,
+            segments: vec![(0, 0, width)],
+        }
+    }
+
+    /// Lowest `y` at which a rectangle of `width` starting at segment `index` fits.
+    fn fit(&self, index: usize, width: u32, height: u32) -> Option<u32> {
+        let x = self.segments[index].0;
+        if x + width > self.width {
+            return None;
+        }
+        let mut y = 0;
+        let mut covered = 0;
+        for &(_, sy, sw) in &self.segments[index..] {
+            y = y.max(sy);
+            covered += sw;
+            if covered >= width {
+                break;
+            }
+        }
+        (y + height <= self.height).then_some(y)
+    }
+
+    /// Raises the skyline to `y` over `width` pixels starting at segment `index`.
+    fn add(&mut self, index: usize, x: u32, y: u32, width: u32) {
+        self.segments.insert(index, (x, y, width));
+        let right = x + width;
+        let i = index + 1;
+        while i < self.segments.len() {
+            let (sx, sy, sw) = self.segments[i];
+            if sx >= right {
+                break;
+            }
+            if sx + sw <= right {
+                self.segments.remove(i);
+            } else {
+                self.segments[i] = (right, sy, sx + sw - right);
+                break;
+            }
+        }
+        // Merge neighbouring segments of equal height.
+        let mut i = 0;
+        while i + 1 < self.segments.len() {
+            if self.segments[i].1 == self.segments[i + 1].1 {
+                self.segments[i].2 += self.segments[i + 1].2;
+                self.segments.remove(i + 1);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+impl Bin for Skyline {
+    fn insert(
+        &mut self,
+        width: u32,
+        height: u32,
+        allow_rotation: bool,
+    ) -> Option<(u32, u32, bool)> {
+        let mut best: Option<((u32, u32), usize, u32, bool)> = None;
+        for index in 0..self.segments.len() {
+            for rotated in [false, true] {
+                if rotated && !allow_rotation {
+                    continue;
+                }
+                let (w, h) = if rotated {
+                    (height, width)
+                } else {
+                    (width, height)
+                };
+                if let Some(y) = self.fit(index, w, h) {
+                    let score = (y + h, self.segments[index].0);
+                    if best.as_ref().is_none_or(|(s, ..)| score < *s) {
+                        best = Some((score, index, y, rotated));
+                    }
+                }
+            }
+        }
+        let (_, index, y, rotated) = best?;
+        let x = self.segments[index].0;
+        let (w, h) = if rotated {
+            (height, width)
+        } else {
+            (width, height)
+        };
+        self.add(index, x, y + h, w);
+        Some((x, y, rotated))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Rgba, RgbaImage};
+
+    fn sizes() -> Vec<(u32, u32)> {
+        vec![
+            (30, 20),
+            (10, 40),
+            (25, 25),
+            (5, 5),
+            (40, 10),
+            (12, 18),
+            (7, 30),
+            (16, 16),
+        ]
+    }
+
+    fn assert_valid(layout: &Layout, sizes: &[(u32, u32)], options: &AtlasOptions) {
+        let rects: Vec<(usize, Rect)> = layout
+            .placements
+            .iter()
+            .zip(sizes)
+            .map(|(p, &(w, h))| {
+                let (w, h) = if p.rotated { (h, w) } else { (w, h) };
+                (
+                    p.page,
+                    Rect {
+                        x: p.x,
+                        y: p.y,
+                        w: w + options.padding,
+                        h: h + options.padding,
+                    },
+                )
+            })
+            .collect();
+        for (i, (page, a)) in rects.iter().enumerate() {
+            let (pw, ph) = layout.page_sizes[*page];
+            assert!(a.x + a.w - options.padding <= pw && a.y + a.h - options.padding <= ph);
+            for (other_page, b) in &rects[i + 1..] {
+                assert!(
+                    page != other_page || !a.intersects(b),
+                    "{:?} overlaps {:?}",
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_pack_layout_algorithms() {
+        for algorithm in [PackingAlgorithm::MaxRects, PackingAlgorithm::Skyline] {
+            for allow_rotation in [false, true] {
+                let options = AtlasOptions {
+                    max_width: 64,
+                    max_height: 64,
+                    padding: 1,
+                    allow_rotation,
+                    algorithm,
+                    ..AtlasOptions::default()
+                };
+                let layout = pack_layout(&sizes(), &options).unwrap();
+                assert_valid(&layout, &sizes(), &options);
+            }
+        }
+    }
+
+    #[test]
+    fn test_pack_layout_pages_and_power_of_two() {
+        let options = AtlasOptions {
+            max_width: 50,
+            max_height: 50,
+            padding: 0,
+            power_of_two: true,
+            ..AtlasOptions::default()
+        };
+        let sizes = vec![(32, 32); 5];
+        let layout = pack_layout(&sizes, &options).unwrap();
+        // The limit is rounded down to 32x32, so every image needs its own page.
+        assert_eq!(layout.page_sizes, vec![(32, 32); 5]);
+        assert!(pack_layout(&[(40, 10)], &options).is_err());
+    }
+
+    #[test]
+    fn test_pack_atlas_places_pixels() {
+        let images: Vec<DynamicImage> = sizes()
+            .iter()
+            .enumerate()
+            .map(|(i, &(w, h))| {
+                DynamicImage::ImageRgba8(RgbaImage::from_fn(w, h, |x, y| {
+                    Rgba([i as u8 * 20, x as u8, y as u8, 255])
+                }))
+            })
+            .collect();
+        let options = AtlasOptions {
+            max_width: 64,
+            max_height: 64,
+            allow_rotation: true,
+            ..AtlasOptions::default()
+        };
+        let atlas = pack_atlas(&images, &options).unwrap();
+        for page in &atlas.pages {
+            for tile in &page.tiles {
+                let (x, y) = (tile.coords.0 as u32, tile.coords.1 as u32);
+                let placement = atlas.placements[tile.number as usize - 1];
+                let original = &images[tile.number as usize - 1];
+                let expected = if placement.rotated {
+                    original.rotate90()
+                } else {
+                    original.clone()
+                };
+                let crop = page
+                    .image
+                    .crop_imm(x, y, expected.width(), expected.height());
+                assert_eq!(crop.to_rgba8(), expected.to_rgba8());
+            }
+        }
+    }
+
+    #[test]
+    fn test_pack_atlas_extrudes_edges() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 3, |x, y| {
+            Rgba([x as u8 * 50, y as u8 * 50, 9, 255])
+        }));
+        let options = AtlasOptions {
+            extrude: 2,
+            ..AtlasOptions::default()
+        };
+        let atlas = pack_atlas(&[image.clone(), image.clone()], &options).unwrap();
+        let tile = &atlas.pages[0].tiles[1];
+        assert_eq!(tile.inner, Some((2, 2, 4, 3)));
+        let (x, y) = (tile.coords.0 as u32, tile.coords.1 as u32);
+        let page = &atlas.pages[0].image;
+        assert_eq!(page.get_pixel(x, y), image.get_pixel(0, 0));
+        assert_eq!(page.get_pixel(x + 7, y + 6), image.get_pixel(3, 2));
+        assert_eq!(
+            page.crop_imm(x + 2, y + 2, 4, 3).to_rgba8(),
+            image.to_rgba8()
+        );
+    }
+}
@@ -0,0 +1,648 @@
+//! The `dataset` module tiles training data for detection and segmentation
+//! models. An image, its label masks and its annotations are cut with the same
+//! overlapping grid, and every annotation is clipped to each tile it touches and
+//! moved into the tile's own coordinates.
+//!
+//! # Features
+//!
+//! - **Synchronized Masks**: Masks are cut with the image's grid and never
+//!   resampled, so class ids stay exact.
+//! - **Annotation Splitting**: Boxes and polygons are clipped to each tile; those
+//!   left with too little of their area are dropped.
+//! - **COCO and YOLO**: Read annotations from either format and write one
+//!   annotation file per tile.
+//!
+//! # Example Usage
+//!
+//! ```no_run
+//! use papercut::{load_coco, save_dataset, slice_dataset, AnnotationFormat, DatasetOptions};
+//! use std::path::Path;
+//!
+//! let (annotations, categories) = load_coco(Path::new("instances.json"), "aerial.png").unwrap();
+//! let options = DatasetOptions {
+//!     tile_width: 640,
+//!     tile_height: 640,
+//!     overlap: 64,
+//!     ..DatasetOptions::default()
+//! };
+//! let mut tiles = slice_dataset("aerial.png", &["aerial_mask.png"], &annotations, &options).unwrap();
+//! save_dataset(&mut tiles, Path::new("out"), "aerial", AnnotationFormat::Yolo, &categories).unwrap();
+//! ```
+
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+
+use crate::tile::Tile;
+
+/// An object in an image, in pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    /// The category or class id.
+    pub category: u32,
+    /// Bounding box as `(x, y, width, height)`.
+    pub bbox: (f64, f64, f64, f64),
+    /// Outline polygons, empty for box-only annotations.
+    pub polygons: Vec<Vec<(f64, f64)>>,
+}
+
+/// A named category, as listed in a COCO file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Category {
+    pub id: u32,
+    pub name: String,
+}
+
+/// The annotation file format written by `save_dataset`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnnotationFormat {
+    /// A COCO JSON file per tile.
+    Coco,
+    /// A YOLO text file per tile with one normalized box per line.
+    Yolo,
+    /// A YOLO segmentation text file per tile with one normalized polygon per line.
+    /// Box-only annotations are written as their four corners.
+    YoloSegment,
+}
+
+/// Options for `tile_dataset`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DatasetOptions {
+    /// Width of each tile in pixels.
+    pub tile_width: u32,
+    /// Height of each tile in pixels.
+    pub tile_height: u32,
+    /// Pixels shared by neighbouring tiles.
+    pub overlap: u32,
+    /// Smallest fraction of an annotation's area a tile must contain to keep it.
+    pub min_visibility: f64,
+    /// Whether to leave out tiles without annotations.
+    pub skip_empty: bool,
+}
+
+impl Default for DatasetOptions {
+    fn default() -> Self {
+        DatasetOptions {
+            tile_width: 512,
+            tile_height: 512,
+            overlap: 0,
+            min_visibility: 0.3,
+            skip_empty: false,
+        }
+    }
+}
+
+/// One tile of a dataset.
+#[derive(Debug, Clone)]
+pub struct DatasetTile {
+    /// The image tile.
+    pub tile: Tile,
+    /// The matching part of each mask, in the order the masks were given.
+    pub masks: Vec<DynamicImage>,
+    /// Annotations in the tile's coordinates.
+    pub annotations: Vec<Annotation>,
+}
+
+#[derive(Deserialize)]
+struct CocoFile {
+    images: Vec<CocoImage>,
+    annotations: Vec<CocoAnnotation>,
+    #[serde(default)]
+    categories: Vec<Category>,
+}
+
+#[derive(Deserialize)]
+struct CocoImage {
+    id: u64,
+    file_name: String,
+}
+
+#[derive(Deserialize)]
+struct CocoAnnotation {
+    image_id: u64,
+    category_id: u32,
+    bbox: [f64; 4],
+    #[serde(default)]
+    segmentation: serde_json::Value,
+}
+
+/// Load the annotations of one image from a COCO file.
+///
+/// Only polygon segmentations are read; run-length encoded masks are left out
+/// and the annotation keeps its box.
+///
+/// # Errors
+///
+/// Returns an error if the file can not be read or parsed, or does not list
+/// `file_name`.
+pub fn load_coco(
+    path: &Path,
+    file_name: &str,
+) -> Result<(Vec<Annotation>, Vec<Category>), Box<dyn std::error::Error>> {
+    let coco: CocoFile = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let image = coco
+        .images
+        .iter()
+        .find(|i| i.file_name == file_name || Path::new(&i.file_name).ends_with(file_name))
+        .ok_or_else(|| format!("{} does not list {}", path.display(), file_name))?;
+    let annotations = coco
+        .annotations
+        .into_iter()
+        .filter(|a| a.image_id == image.id)
+        .map(|a| {
+            let polygons = a
+                .segmentation
+                .as_array()
+                .map(|list| {
+                    list.iter()
+                        .filter_map(|p| p.as_array())
+                        .map(|p| {
+                            let values: Vec<f64> = p.iter().filter_map(|v| v.as_f64()).collect();
+                            values.chunks_exact(2).map(|c| (c[0], c[1])).collect()
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            Annotation {
+                category: a.category_id,
+                bbox: (a.bbox[0], a.bbox[1], a.bbox[2], a.bbox[3]),
+                polygons,
+            }
+        })
+        .collect();
+    Ok((annotations, coco.categories))
+}
+
+/// Load YOLO annotations for an image of `width x height`. Lines with four
+/// values after the class are boxes, longer lines are polygons.
+///
+/// # Errors
+///
+/// Returns an error if the file can not be read or a line is malformed.
+pub fn load_yolo(
+    path: &Path,
+    width: u32,
+    height: u32,
+) -> Result<Vec<Annotation>, Box<dyn std::error::Error>> {
+    let (w, h) = (width as f64, height as f64);
+    let mut annotations = Vec::new();
+    for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let mut fields = line.split_whitespace();
+        let Some(class) = fields.next() else {
+            continue;
+        };
+        let category: u32 = class.parse()?;
+        let values = fields.map(str::parse).collect::<Result<Vec<f64>, _>>()?;
+        let annotation = match values.len() {
+            4 => Annotation {
+                category,
+                bbox: (
+                    (values[0] - values[2] / 2.0) * w,
+                    (values[1] - values[3] / 2.0) * h,
+                    values[2] * w,
+                    values[3] * h,
+                ),
+                polygons: Vec::new(),
+            },
+            n if n >= 6 && n.is_multiple_of(2) => {
+                let polygon: Vec<(f64, f64)> = values
+                    .chunks_exact(2)
+                    .map(|c| (c[0] * w, c[1] * h))
+                    .collect();
+                Annotation {
+                    category,
+                    bbox: bounds(&polygon),
+                    polygons: vec![polygon],
+                }
+            }
+            _ => return Err(format!("{}:{}: malformed YOLO line", path.display(), i + 1).into()),
+        };
+        annotations.push(annotation);
+    }
+    Ok(annotations)
+}
+
+/// Cut an image file, its mask files and its annotations into dataset tiles.
+///
+/// # Arguments
+///
+/// * `filename` - The filename of the image.
+/// * `masks` - Filenames of label masks the same size as the image.
+/// * `annotations` - The image's annotations in pixels.
+/// * `options` - Tile size, overlap and filtering.
+pub fn slice_dataset(
+    filename: &str,
+    masks: &[&str],
+    annotations: &[Annotation],
+    options: &DatasetOptions,
+) -> Result<Vec<DatasetTile>, String> {
+    let open = |name: &str| {
+        image::open(Path::new(name)).map_err(|_| format!("can not open image {}", name))
+    };
+    let im = open(filename)?;
+    let masks = masks
+        .iter()
+        .map(|name| open(name))
+        .collect::<Result<Vec<_>, _>>()?;
+    tile_dataset(&im, &masks, annotations, options)
+}
+
+/// Cut an in-memory image, its masks and its annotations into dataset tiles.
+///
+/// Tiles are `tile_width x tile_height` and step by the size minus `overlap`.
+/// The last row and column are moved back to end at the image border, so every
+/// tile is full size unless the image is smaller than a tile. Masks are cropped
+/// without resampling.
+///
+/// # Errors
+///
+/// Returns an error if the tile size is zero, the overlap is not smaller than
+/// the tile, or a mask's size differs from the image's.
+pub fn tile_dataset(
+    image: &DynamicImage,
+    masks: &[DynamicImage],
+    annotations: &[Annotation],
+    options: &DatasetOptions,
+) -> Result<Vec<DatasetTile>, String> {
+    let (width, height) = (image.width(), image.height());
+    if options.tile_width == 0 || options.tile_height == 0 {
+        return Err("Dataset tiles must be at least 1x1.".to_string());
+    }
+    if options.overlap >= options.tile_width.min(options.tile_height) {
+        return Err("The overlap must be smaller than the tiles.".to_string());
+    }
+    if let Some(mask) = masks
+        .iter()
+        .find(|m| (m.width(), m.height()) != (width, height))
+    {
+        return Err(format!(
+            "A {}x{} mask does not match the {}x{} image.",
+            mask.width(),
+            mask.height(),
+            width,
+            height
+        ));
+    }
+
+    let xs = window_starts(width, options.tile_width, options.overlap);
+    let ys = window_starts(height, options.tile_height, options.overlap);
+    let (tw, th) = (
+        options.tile_width.min(width),
+        options.tile_height.min(height),
+    );
+
+    let mut tiles = Vec::new();
+    for (row, &y) in ys.iter().enumerate() {
+        for (column, &x) in xs.iter().enumerate() {
+            let rect = (x as f64, y as f64, tw as f64, th as f64);
+            let clipped: Vec<Annotation> = annotations
+                .iter()
+                .filter_map(|a| clip_annotation(a, rect, options.min_visibility))
+                .collect();
+            if options.skip_empty && clipped.is_empty() {
+                continue;
+            }
+            tiles.push(DatasetTile {
+                tile: Tile::new(
+                    image.crop_imm(x, y, tw, th),
+                    (row * xs.len() + column) as i32 + 1,
+                    (column as i32 + 1, row as i32 + 1),
+                    (x as i32, y as i32),
+                    None,
+                ),
+                masks: masks.iter().map(|m| m.crop_imm(x, y, tw, th)).collect(),
+                annotations: clipped,
+            });
+        }
+    }
+    Ok(tiles)
+}
+
+/// Write dataset tiles under `directory`.
+///
+/// Images go to `images/`, the first mask to `masks/`, further masks to
+/// `masks_1/`, `masks_2/` and so on, and annotations to `labels/`. All files of
+/// a tile share the name `{prefix}_{column}_{row}`. `categories` names the
+/// categories in COCO files and is not used for YOLO.
+///
+/// # Errors
+///
+/// Returns an error if a directory or file can not be written.
+pub fn save_dataset(
+    tiles: &mut [DatasetTile],
+    directory: &Path,
+    prefix: &str,
+    format: AnnotationFormat,
+    categories: &[Category],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let labels = directory.join("labels");
+    fs::create_dir_all(directory.join("images"))?;
+    fs::create_dir_all(&labels)?;
+
+    for entry in tiles.iter_mut() {
+        let filename =
+            entry
+                .tile
+                .generate_filename(Some(&directory.join("images")), prefix, "png", true);
+        entry.tile.save(Some(filename.clone()), "png")?;
+        let stem = entry.tile.basename().unwrap_or_default();
+
+        for (i, mask) in entry.masks.iter().enumerate() {
+            let masks = match i {
+                0 => directory.join("masks"),
+                i => directory.join(format!("masks_{}", i)),
+            };
+            fs::create_dir_all(&masks)?;
+            mask.save(masks.join(format!("{}.png", stem)))?;
+        }
+
+        let (w, h) = (
+            entry.tile.image.width() as f64,
+            entry.tile.image.height() as f64,
+        );
+        match format {
+            AnnotationFormat::Yolo | AnnotationFormat::YoloSegment => {
+                let mut text = String::new();
+                for a in &entry.annotations {
+                    let (x, y, bw, bh) = a.bbox;
+                    if format == AnnotationFormat::Yolo {
+                        text.push_str(&format!(
+                            "{} {:.6} {:.6} {:.6} {:.6}\n",
+                            a.category,
+                            (x + bw / 2.0) / w,
+                            (y + bh / 2.0) / h,
+                            bw / w,
+                            bh / h
+                        ));
+                        continue;
+                    }
+                    let corners = vec![(x, y), (x + bw, y), (x + bw, y + bh), (x, y + bh)];
+                    let polygons = if a.polygons.is_empty() {
+                        vec![corners]
+                    } else {
+                        a.polygons.clone()
+                    };
+                    for polygon in polygons {
+                        text.push_str(&a.category.to_string());
+                        for (px, py) in polygon {
+                            text.push_str(&format!(" {:.6} {:.6}", px / w, py / h));
+                        }
+                        text.push('\n');
+                    }
+                }
+                fs::write(labels.join(format!("{}.txt", stem)), text)?;
+            }
+            AnnotationFormat::Coco => {
+                let annotations: Vec<_> = entry
+                    .annotations
+                    .iter()
+                    .enumerate()
+                    .map(|(i, a)| {
+                        let segmentation: Vec<Vec<f64>> = a
+                            .polygons
+                            .iter()
+                            .map(|p| p.iter().flat_map(|&(x, y)| [x, y]).collect())
+                            .collect();
+                        json!({
+                            "id": i + 1,
+                            "image_id": 1,
+                            "category_id": a.category,
+                            "bbox": [a.bbox.0, a.bbox.1, a.bbox.2, a.bbox.3],
+                            "area": annotation_area(a),
+                            "segmentation": segmentation,
+                            "iscrowd": 0,
+                        })
+                    })
+                    .collect();
+                let coco = json!({
+                    "images": [{
+                        "id": 1,
+                        "file_name": filename.file_name().and_then(|f| f.to_str()),
+                        "width": w as u32,
+                        "height": h as u32,
+                    }],
+                    "annotations": annotations,
+                    "categories": categories,
+                });
+                fs::write(
+                    labels.join(format!("{}.json", stem)),
+                    serde_json::to_string_pretty(&coco)?,
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Start positions of windows of `size` stepping by `size - overlap`, with the
+/// last one moved back to end at `length`.
+fn window_starts(length: u32, size: u32, overlap: u32) -> Vec<u32> {
+    if length <= size {
+        return vec![0];
+    }
+    let step = size - overlap;
+    let mut starts: Vec<u32> = (0..length - size).step_by(step as usize).collect();
+    starts.push(length - size);
+    starts
+}
+
+/// Clips an annotation to `rect` and moves it into the rectangle's coordinates,
+/// or returns `None` if less than `min_visibility` of its area remains.
+fn clip_annotation(
+    annotation: &Annotation,
+    rect: (f64, f64, f64, f64),
+    min_visibility: f64,
+) -> Option<Annotation> {
+    let (rx, ry, rw, rh) = rect;
+    let original = annotation_area(annotation);
+    let (polygons, bbox, area) = if annotation.polygons.is_empty() {
+        let (x, y, w, h) = annotation.bbox;
+        let (x0, y0) = (x.max(rx), y.max(ry));
+        let (x1, y1) = ((x + w).min(rx + rw), (y + h).min(ry + rh));
+        if x1 <= x0 || y1 <= y0 {
+            return None;
+        }
+        let area = (x1 - x0) * (y1 - y0);
+        (Vec::new(), (x0, y0, x1 - x0, y1 - y0), area)
+    } else {
+        let polygons: Vec<Vec<(f64, f64)>> = annotation
+            .polygons
+            .iter()
+            .map(|p| clip_polygon(p, rect))
+            .filter(|p| p.len() >= 3)
+            .collect();
+        let points: Vec<(f64, f64)> = polygons.iter().flatten().copied().collect();
+        if points.is_empty() {
+            return None;
+        }
+        let area = polygons.iter().map(|p| polygon_area(p)).sum();
+        (polygons, bounds(&points), area)
+    };
+    if original > 0.0 && area / original < min_visibility {
+        return None;
+    }
+    Some(Annotation {
+        category: annotation.category,
+        bbox: (bbox.0 - rx, bbox.1 - ry, bbox.2, bbox.3),
+        polygons: polygons
+            .into_iter()
+            .map(|p| p.into_iter().map(|(x, y)| (x - rx, y - ry)).collect())
+            .collect(),
+    })
+}
+
+/// Clips a polygon to a rectangle with the Sutherland-Hodgman algorithm.
+fn clip_polygon(polygon: &[(f64, f64)], rect: (f64, f64, f64, f64)) -> Vec<(f64, f64)> {
+    let (x0, y0, x1, y1) = (rect.0, rect.1, rect.0 + rect.2, rect.1 + rect.3);
+    // Each edge as the signed distance of a point inside it.
+    let edges: [&dyn Fn((f64, f64)) -> f64; 4] =
+        [&|p| p.0 - x0, &|p| x1 - p.0, &|p| p.1 - y0, &|p| y1 - p.1];
+    let mut output = polygon.to_vec();
+    for inside in edges {
+        let input = std::mem::take(&mut output);
+        for (i, &current) in input.iter().enumerate() {
+            let previous = input[(i + input.len() - 1) % input.len()];
+            let (dc, dp) = (inside(current), inside(previous));
+            if (dc >= 0.0) != (dp >= 0.0) {
+                let t = dp / (dp - dc);
+                output.push((
+                    previous.0 + t * (current.0 - previous.0),
+                    previous.1 + t * (current.1 - previous.1),
+                ));
+            }
+            if dc >= 0.0 {
+                output.push(current);
+            }
+        }
+    }
+    output
+}
+
+/// Area of a polygon by the shoelace formula.
+fn polygon_area(polygon: &[(f64, f64)]) -> f64 {
+    let n = polygon.len();
+    let twice: f64 = (0..n)
+        .map(|i| {
+            let (a, b) = (polygon[i], polygon[(i + 1) % n]);
+            a.0 * b.1 - b.0 * a.1
+        })
+        .sum();
+    twice.abs() / 2.0
+}
+
+/// Area of an annotation's polygons, or of its box if it has none.
+fn annotation_area(annotation: &Annotation) -> f64 {
+    if annotation.polygons.is_empty() {
+        annotation.bbox.2 * annotation.bbox.3
+    } else {
+        annotation.polygons.iter().map(|p| polygon_area(p)).sum()
+    }
+}
+
+/// Bounding box of points as `(x, y, width, height)`.
+fn bounds(points: &[(f64, f64)]) -> (f64, f64, f64, f64) {
+    let (mut x0, mut y0) = (f64::INFINITY, f64::INFINITY);
+    let (mut x1, mut y1) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for &(x, y) in points {
+        (x0, y0, x1, y1) = (x0.min(x), y0.min(y), x1.max(x), y1.max(y));
+    }
+    (x0, y0, x1 - x0, y1 - y0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, GrayImage, Luma, Rgba, RgbaImage};
+
+    fn options() -> DatasetOptions {
+        DatasetOptions {
+            tile_width: 60,
+            tile_height: 60,
+            overlap: 20,
+            min_visibility: 0.3,
+            skip_empty: false,
+        }
+    }
+
+    #[test]
+    fn test_grid_and_masks() {
+        assert_eq!(window_starts(100, 60, 20), vec![0, 40]);
+        assert_eq!(window_starts(150, 60, 20), vec![0, 40, 80, 90]);
+        assert_eq!(window_starts(50, 60, 20), vec![0]);
+
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(100, 80, Rgba([9, 9, 9, 255])));
+        let mask = DynamicImage::ImageLuma8(GrayImage::from_fn(100, 80, |x, _| Luma([x as u8])));
+        let tiles = tile_dataset(&image, &[mask], &[], &options()).unwrap();
+        assert_eq!(tiles.len(), 4);
+        let last = &tiles[3];
+        assert_eq!(last.tile.coords, (40, 20));
+        assert_eq!(last.tile.position, (2, 2));
+        // Mask values are copied, not interpolated.
+        assert_eq!(last.masks[0].get_pixel(5, 5)[0], 45);
+
+        let small = DynamicImage::ImageLuma8(GrayImage::new(10, 10));
+        assert!(tile_dataset(&image, &[small], &[], &options()).is_err());
+    }
+
+    #[test]
+    fn test_annotation_clipping() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(100, 60));
+        let annotations = vec![
+            // A box across the cut at x = 40..60, mostly in the first tile.
+            Annotation {
+                category: 1,
+                bbox: (10.0, 10.0, 40.0, 20.0),
+                polygons: Vec::new(),
+            },
+            // A triangle whose right part reaches into the second tile.
+            Annotation {
+                category: 2,
+                bbox: (50.0, 30.0, 40.0, 20.0),
+                polygons: vec![vec![(50.0, 30.0), (90.0, 30.0), (50.0, 50.0)]],
+            },
+        ];
+        let tiles = tile_dataset(&image, &[], &annotations, &options()).unwrap();
+        assert_eq!(tiles.len(), 2);
+
+        let first = &tiles[0].annotations;
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[0].bbox, (10.0, 10.0, 40.0, 20.0));
+        // The triangle clipped at x = 60 keeps 7/16 of its area.
+        assert_eq!(first[1].bbox, (50.0, 30.0, 10.0, 20.0));
+
+        // In the second tile, at x = 40, the box keeps a quarter and is dropped.
+        let second = &tiles[1].annotations;
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].category, 2);
+        assert_eq!(second[0].bbox, (10.0, 30.0, 40.0, 20.0));
+        assert_eq!(second[0].polygons[0][0], (10.0, 30.0));
+    }
+
+    #[test]
+    fn test_yolo_round_trip() {
+        let directory = std::env::temp_dir().join("papercut_dataset_test");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let labels = directory.join("source.txt");
+        fs::write(&labels, "3 0.375 0.5 0.25 0.5\n").unwrap();
+        let annotations = load_yolo(&labels, 120, 60).unwrap();
+        assert_eq!(annotations[0].bbox, (30.0, 15.0, 30.0, 30.0));
+
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(120, 60));
+        let options = DatasetOptions {
+            overlap: 0,
+            skip_empty: true,
+            ..options()
+        };
+        let mut tiles = tile_dataset(&image, &[], &annotations, &options).unwrap();
+        save_dataset(&mut tiles, &directory, "set", AnnotationFormat::Yolo, &[]).unwrap();
+        let text = fs::read_to_string(directory.join("labels/set_01_01.txt")).unwrap();
+        assert!(directory.join("images/set_01_01.png").exists());
+        fs::remove_dir_all(&directory).unwrap();
+        // The box ends at the cut, so the second tile is empty and skipped.
+        assert_eq!(tiles.len(), 1);
+        assert_eq!(text, "3 0.750000 0.500000 0.500000 0.500000\n");
+    }
+}
@@ -0,0 +1,407 @@
+//! The `diff` module compares two slicing runs of an image. Tiles are paired by
+//! grid position and each pair is measured, so QA can see which tiles were
+//! added, removed or changed and by how much.
+//!
+//! # Features
+//!
+//! - **Tile Sources**: Read tile sets from a manifest or from a directory of
+//!   saved tiles.
+//! - **Metrics**: Largest channel difference, PSNR and SSIM for every pair.
+//! - **Heatmap**: Join the tiles into one image with changed pixels in red,
+//!   added tiles in green and removed tiles in blue.
+//!
+//! # Example Usage
+//!
+//! ```no_run
+//! use papercut::{diff_heatmap, diff_tiles, load_tile_source, TileChange};
+//! use std::path::Path;
+//!
+//! let old = load_tile_source(Path::new("v1/map.json")).unwrap();
+//! let new = load_tile_source(Path::new("v2")).unwrap();
+//! for diff in diff_tiles(&old, &new, 0) {
+//!     if diff.change != TileChange::Unchanged {
+//!         println!("{:?} {:?} PSNR {:.1}", diff.position, diff.change, diff.psnr);
+//!     }
+//! }
+//! diff_heatmap(&old, &new).unwrap().save("heatmap.png").unwrap();
+//! ```
+
+use image::{DynamicImage, GrayImage, Rgba, RgbaImage};
+use std::path::Path;
+
+use crate::join;
+use crate::manifest::Manifest;
+use crate::stream::scan_tile_directory;
+use crate::tile::Tile;
+
+/// Side of the square windows SSIM is averaged over.
+const SSIM_WINDOW: u32 = 8;
+
+/// How a grid position differs between two tile sets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileChange {
+    /// Only the new set has a tile here.
+    Added,
+    /// Only the old set has a tile here.
+    Removed,
+    /// Both sets have a tile here and their pixels differ.
+    Changed,
+    /// Both sets have a tile here with the same pixels, within the threshold.
+    Unchanged,
+}
+
+/// The comparison of one grid position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileDiff {
+    /// The grid position shared by the pair.
+    pub position: (i32, i32),
+    /// The tile's pixel coordinates, taken from the new tile when there is one.
+    pub coords: (i32, i32),
+    /// What changed.
+    pub change: TileChange,
+    /// Largest difference of any channel of any pixel.
+    pub max_delta: u8,
+    /// Peak signal-to-noise ratio in decibels, infinite for identical tiles.
+    pub psnr: f64,
+    /// Mean structural similarity of the brightness, 1 for identical tiles.
+    pub ssim: f64,
+}
+
+/// Read a tile set from a manifest file or a directory of saved tiles.
+///
+/// Directories are read like `TileSource::Directory`, so tiles of uneven size
+/// are placed correctly as long as each column shares a width and each row a
+/// height. Positions are 1-based, as in manifests.
+///
+/// # Errors
+///
+/// Returns an error if the manifest or a tile can not be read, or a directory's
+/// tiles do not form a grid.
+pub fn load_tile_source(path: &Path) -> Result<Vec<Tile>, Box<dyn std::error::Error>> {
+    if path.is_file() {
+        return Manifest::load(path)?.open_tiles();
+    }
+
+    let mut tiles = Vec::new();
+    for (i, found) in scan_tile_directory(path)?.into_iter().enumerate() {
+        tiles.push(Tile::new(
+            image::open(&found.path)?,
+            i as i32 + 1,
+            (found.position.0 as i32 + 1, found.position.1 as i32 + 1),
+            (found.coords.0 as i32, found.coords.1 as i32),
+            Some(found.path),
+        ));
+    }
+    Ok(tiles)
+}
+
+/// Compare two tile sets by grid position.
+///
+/// Pairs whose largest channel difference is at most `threshold` count as
+/// unchanged. Pairs of different sizes count as changed with the worst scores.
+///
+/// # Returns
+///
+/// One entry per position in either set: the new set's positions in order,
+/// then the removed ones.
+pub fn diff_tiles(old: &[Tile], new: &[Tile], threshold: u8) -> Vec<TileDiff> {
+    let mut diffs: Vec<TileDiff> = new
+        .iter()
+        .map(
+            |tile| match old.iter().find(|o| o.position == tile.position) {
+                Some(previous) => compare(previous, tile, threshold),
+                None => TileDiff {
+                    position: tile.position,
+                    coords: tile.coords,
+                    change: TileChange::Added,
+                    max_delta: 0,
+                    psnr: 0.0,
+                    ssim: 0.0,
+                },
+            },
+        )
+        .collect();
+    for tile in old {
+        if !new.iter().any(|n| n.position == tile.position) {
+            diffs.push(TileDiff {
+                position: tile.position,
+                coords: tile.coords,
+                change: TileChange::Removed,
+                max_delta: 0,
+                psnr: 0.0,
+                ssim: 0.0,
+            });
+        }
+    }
+    diffs
+}
+
+/// Compare the tile sets at `old` and `new`, each a manifest or a directory.
+///
+/// # Errors
+///
+/// Returns an error if either source can not be read.
+pub fn diff_sources(
+    old: &Path,
+    new: &Path,
+    threshold: u8,
+) -> Result<Vec<TileDiff>, Box<dyn std::error::Error>> {
+    Ok(diff_tiles(
+        &load_tile_source(old)?,
+        &load_tile_source(new)?,
+        threshold,
+    ))
+}
+
+/// Render the differences as one image with `join`. Tiles are shown dimmed in
+/// grey, with each changed pixel in red as strong as its difference. Added
+/// tiles are tinted green and removed ones blue.
+///
+/// # Errors
+///
+/// Returns an error if the tiles can not be joined.
+pub fn diff_heatmap(old: &[Tile], new: &[Tile]) -> Result<DynamicImage, String> {
+    let mut heat = Vec::with_capacity(new.len());
+    for tile in new {
+        let rgba = tile.image.to_rgba8();
+        let previous = old
+            .iter()
+            .find(|o| o.position == tile.position)
+            .map(|o| o.image.to_rgba8());
+        let image = RgbaImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+            let pixel = rgba.get_pixel(x, y);
+            match &previous {
+                None => tint(pixel, [0, 160, 0]),
+                Some(previous) if previous.dimensions() != rgba.dimensions() => {
+                    Rgba([255, 0, 0, 255])
+                }
+                Some(previous) => {
+                    let delta = pixel_delta(previous.get_pixel(x, y), pixel);
+                    let grey = dim(pixel);
+                    let red = grey as u16 + delta as u16 * (255 - grey as u16) / 255;
+                    let other = grey as u16 * (255 - delta as u16) / 255;
+                    Rgba([red as u8, other as u8, other as u8, 255])
+                }
+            }
+        });
+        heat.push(heat_tile(tile, image));
+    }
+    for tile in old {
+        if !new.iter().any(|n| n.position == tile.position) {
+            let rgba = tile.image.to_rgba8();
+            let image = RgbaImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+                tint(rgba.get_pixel(x, y), [0, 0, 160])
+            });
+            heat.push(heat_tile(tile, image));
+        }
+    }
+    join(&heat, 0, 0)
+}
+
+/// Compares two tiles at the same position.
+fn compare(old: &Tile, new: &Tile, threshold: u8) -> TileDiff {
+    let mut diff = TileDiff {
+        position: new.position,
+        coords: new.coords,
+        change: TileChange::Changed,
+        max_delta: u8::MAX,
+        psnr: 0.0,
+        ssim: 0.0,
+    };
+    let (a, b) = (old.image.to_rgba8(), new.image.to_rgba8());
+    if a.dimensions() != b.dimensions() {
+        return diff;
+    }
+
+    let mut squared = 0u64;
+    let mut max_delta = 0u8;
+    for (p, q) in a.as_raw().iter().zip(b.as_raw()) {
+        let delta = p.abs_diff(*q);
+        max_delta = max_delta.max(delta);
+        squared += delta as u64 * delta as u64;
+    }
+    let mse = squared as f64 / a.as_raw().len().max(1) as f64;
+    diff.max_delta = max_delta;
+    diff.psnr = if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (255.0 * 255.0 / mse).log10()
+    };
+    diff.ssim = ssim(&old.image.to_luma8(), &new.image.to_luma8());
+    if max_delta <= threshold {
+        diff.change = TileChange::Unchanged;
+    }
+    diff
+}
+
+/// Mean SSIM over non-overlapping windows of two images of the same size.
+fn ssim(a: &GrayImage, b: &GrayImage) -> f64 {
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+    let (width, height) = a.dimensions();
+    let (mut total, mut windows) = (0.0, 0);
+    for wy in (0..height).step_by(SSIM_WINDOW as usize) {
+        for wx in (0..width).step_by(SSIM_WINDOW as usize) {
+            let (w, h) = (SSIM_WINDOW.min(width - wx), SSIM_WINDOW.min(height - wy));
+            let n = (w * h) as f64;
+            let (mut sa, mut sb, mut saa, mut sbb, mut sab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in wy..wy + h {
+                for x in wx..wx + w {
+                    let (p, q) = (a.get_pixel(x, y)[0] as f64, b.get_pixel(x, y)[0] as f64);
+                    sa += p;
+                    sb += q;
+                    saa += p * p;
+                    sbb += q * q;
+                    sab += p * q;
+                }
+            }
+            let (ma, mb) = (sa / n, sb / n);
+            let (va, vb, cov) = (saa / n - ma * ma, sbb / n - mb * mb, sab / n - ma * mb);
+            total += ((2.0 * ma * mb + C1) * (2.0 * cov + C2))
+                / ((ma * ma + mb * mb + C1) * (va + vb + C2));
+            windows += 1;
+        }
+    }
+    if windows == 0 {
+        1.0
+    } else {
+        total / windows as f64
+    }
+}
+
+/// Largest channel difference between two pixels.
+fn pixel_delta(a: &Rgba<u8>, b: &Rgba<u8>) -> u8 {
+    a.0.iter()
+        .zip(b.0)
+        .map(|(p, q)| p.abs_diff(q))
+        .max()
+        .unwrap_or(0)
+}
+
+/// The pixel's brightness, dimmed so red marks stand out.
+fn dim(pixel: &Rgba<u8>) -> u8 {
+    let [r, g, b, _] = pixel.0;
+    ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 3000) as u8
+}
+
+/// The dimmed pixel with `color` added.
+fn tint(pixel: &Rgba<u8>, color: [u8; 3]) -> Rgba<u8> {
+    let grey = dim(pixel);
+    let [r, g, b] = color.map(|c| grey.saturating_add(c));
+    Rgba([r, g, b, 255])
+}
+
+/// A tile placed like `tile` showing `image`.
+fn heat_tile(tile: &Tile, image: RgbaImage) -> Tile {
+    Tile::new(
+        DynamicImage::ImageRgba8(image),
+        tile.number,
+        tile.position,
+        tile.coords,
+        None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{save_tiles, slice_image_by_size};
+    use image::GenericImageView;
+    use std::fs;
+
+    fn gradient(edit: bool) -> DynamicImage {
+        let mut image =
+            RgbaImage::from_fn(16, 16, |x, y| Rgba([x as u8 * 16, y as u8 * 16, 64, 255]));
+        if edit {
+            image.put_pixel(12, 3, Rgba([255, 255, 255, 255]));
+        }
+        DynamicImage::ImageRgba8(image)
+    }
+
+    #[test]
+    fn test_diff_tiles() {
+        let old = slice_image_by_size(&gradient(false), 8, 8).unwrap();
+        let mut new = slice_image_by_size(&gradient(true), 8, 8).unwrap();
+        new.pop();
+        let diffs = diff_tiles(&old, &new, 0);
+        let changes: Vec<_> = diffs.iter().map(|d| (d.position, d.change)).collect();
+        assert_eq!(
+            changes,
+            vec![
+                ((1, 1), TileChange::Unchanged),
+                ((2, 1), TileChange::Changed),
+                ((1, 2), TileChange::Unchanged),
+                ((2, 2), TileChange::Removed),
+            ]
+        );
+        assert_eq!((diffs[0].psnr, diffs[0].ssim), (f64::INFINITY, 1.0));
+        let changed = &diffs[1];
+        assert_eq!(changed.max_delta, 207);
+        assert!(changed.psnr > 20.0 && changed.psnr < 40.0);
+        assert!(changed.ssim < 1.0);
+        assert_eq!(diff_tiles(&old, &new, 207)[1].change, TileChange::Unchanged);
+
+        let heatmap = diff_heatmap(&old, &new).unwrap();
+        assert_eq!(heatmap.dimensions(), (16, 16));
+        let marked = heatmap.get_pixel(12, 3);
+        assert!(marked[0] > marked[1]);
+        let removed = heatmap.get_pixel(12, 12);
+        assert!(removed[2] > removed[0]);
+    }
+
+    #[test]
+    fn test_sources() {
+        let root = std::env::temp_dir().join("papercut_diff_test");
+        let _ = fs::remove_dir_all(&root);
+        let (v1, v2) = (root.join("v1"), root.join("v2"));
+        let mut old = slice_image_by_size(&gradient(false), 8, 4).unwrap();
+        save_tiles(&mut old, "map", Some(&v1), "png").unwrap();
+        Manifest::from_tiles(&old)
+            .save(&v1.join("map.json"))
+            .unwrap();
+        let mut new = slice_image_by_size(&gradient(true), 8, 4).unwrap();
+        save_tiles(&mut new, "map", Some(&v2), "png").unwrap();
+
+        let tiles = load_tile_source(&v2).unwrap();
+        assert_eq!(tiles.len(), 8);
+        assert_eq!((tiles[5].position, tiles[5].coords), ((2, 3), (8, 8)));
+        let diffs = diff_sources(&v1.join("map.json"), &v2, 0).unwrap();
+        let changed: Vec<_> = diffs
+            .iter()
+            .filter(|d| d.change != TileChange::Unchanged)
+            .map(|d| d.position)
+            .collect();
+        assert_eq!(changed, vec![(2, 1)]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_uneven_directory() {
+        let root = std::env::temp_dir().join("papercut_diff_uneven_test");
+        let _ = fs::remove_dir_all(&root);
+        let (v1, v2) = (root.join("v1"), root.join("v2"));
+        // 6x6 tiles of a 16x16 image leave 4-pixel edge tiles.
+        let mut old = slice_image_by_size(&gradient(false), 6, 6).unwrap();
+        save_tiles(&mut old, "map", Some(&v1), "png").unwrap();
+        let mut new = slice_image_by_size(&gradient(true), 6, 6).unwrap();
+        save_tiles(&mut new, "map", Some(&v2), "png").unwrap();
+
+        let old = load_tile_source(&v1).unwrap();
+        let new = load_tile_source(&v2).unwrap();
+        assert_eq!((new[8].position, new[8].coords), ((3, 3), (12, 12)));
+        let changed: Vec<_> = diff_tiles(&old, &new, 0)
+            .iter()
+            .filter(|d| d.change != TileChange::Unchanged)
+            .map(|d| d.position)
+            .collect();
+        assert_eq!(changed, vec![(3, 1)]);
+
+        let heatmap = diff_heatmap(&old, &new).unwrap();
+        assert_eq!(heatmap.dimensions(), (16, 16));
+        let marked = heatmap.get_pixel(12, 3);
+        assert!(marked[0] > marked[1]);
+        let unchanged = heatmap.get_pixel(15, 15);
+        assert_eq!(unchanged[0], unchanged[1]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
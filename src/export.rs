@@ -0,0 +1,441 @@
+//! The `export` module writes sprite sheet metadata for game engines. Instead of
+//! saving every tile as its own file like `save_tiles`, the sheet image is kept
+//! whole and the exported file describes where each frame lives in it.
+//!
+//! # Features
+//!
+//! - **TexturePacker**: JSON in both the hash and the array flavour.
+//! - **Godot**: A `.tres` `SpriteFrames` resource of atlas textures, or a `TileSet`
+//!   with one atlas source for uniform grids.
+//! - **Tiled**: A `.tsx` tileset, as a grid when the tiles are uniform and as
+//!   sub-rectangles of the sheet otherwise.
+//!
+//! # Example Usage
+//!
+//! ```no_run
+//! use papercut::{save_sheet, slice, SheetFormat};
+//! use std::path::Path;
+//!
+//! let tiles = slice("sheet.png", None, Some(8), Some(4), false).unwrap();
+//! // The sheet size is read from `sheet.png`
+//! save_sheet(
+//!     &tiles,
+//!     Path::new("sheet.png"),
+//!     Path::new("sheet.json"),
+//!     SheetFormat::TexturePackerHash,
+//! )
+//! .unwrap();
+//! ```
+
+use serde_json::{Map, Value, json};
+use std::fs;
+use std::path::Path;
+
+use crate::tile::Tile;
+use crate::utils::get_basename;
+
+/// Metadata formats understood by `export_sheet`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SheetFormat {
+    /// TexturePacker JSON with frames keyed by name.
+    TexturePackerHash,
+    /// TexturePacker JSON with frames in a list.
+    TexturePackerArray,
+    /// Godot 4 `SpriteFrames` resource with one animation named `default`.
+    GodotSpriteFrames,
+    /// Godot 4 `TileSet` resource. Requires tiles of equal size on a grid.
+    GodotTileSet,
+    /// Tiled `.tsx` tileset.
+    TiledTileset,
+}
+
+impl SheetFormat {
+    /// The file extension conventionally used for the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SheetFormat::TexturePackerHash | SheetFormat::TexturePackerArray => "json",
+            SheetFormat::GodotSpriteFrames | SheetFormat::GodotTileSet => "tres",
+            SheetFormat::TiledTileset => "tsx",
+        }
+    }
+}
+
+/// Serialize the frames of a sprite sheet.
+///
+/// # Arguments
+///
+/// * `tiles` - The frames, with `coords` giving their position on the sheet.
+/// * `image` - The sheet image as it should be referenced from the exported file,
+///   e.g. `sheet.png` or `res://sprites/sheet.png` for Godot.
+/// * `sheet_size` - Width and height of the sheet image. Frames need not reach its
+///   right and bottom edges, e.g. when `slice` leaves out remainder pixels.
+/// * `format` - The metadata format.
+///
+/// Frames are named like the files `save_tiles` would write, using the basename
+/// of `image` as prefix.
+///
+/// # Errors
+///
+/// Returns an error for `GodotTileSet` when the tiles do not form a uniform grid.
+pub fn export_sheet(
+    tiles: &[Tile],
+    image: &str,
+    sheet_size: (u32, u32),
+    format: SheetFormat,
+) -> Result<String, String> {
+    let prefix = get_basename(image);
+    let frames: Vec<Frame> = tiles
+        .iter()
+        .map(|tile| {
+            // Frames cover the content of padded tiles, not their padding
+            let (x, y, width, height) = tile.content_rect();
+            Frame {
+                name: tile
+                    .generate_filename(None, &prefix, "png", false)
+                    .to_string_lossy()
+                    .into_owned(),
+                x: x.max(0) as u32,
+                y: y.max(0) as u32,
+                width,
+                height,
+            }
+        })
+        .collect();
+
+    match format {
+        SheetFormat::TexturePackerHash | SheetFormat::TexturePackerArray => Ok(texture_packer(
+            &frames,
+            image,
+            sheet_size,
+            format == SheetFormat::TexturePackerHash,
+        )),
+        SheetFormat::GodotSpriteFrames => Ok(godot_sprite_frames(&frames, image)),
+        SheetFormat::GodotTileSet => {
+            let cell = uniform_grid(&frames).ok_or_else(|| {
+                "A Godot TileSet needs tiles of equal size on a grid.".to_string()
+            })?;
+            Ok(godot_tile_set(&frames, image, cell))
+        }
+        SheetFormat::TiledTileset => Ok(tiled_tileset(&frames, image, &prefix, sheet_size)),
+    }
+}
+
+/// Write sprite sheet metadata to `output`.
+///
+/// `image` is referenced relative to the directory of `output` when it lies below
+/// it, and as given otherwise. The sheet size is read from `image`.
+///
+/// # Errors
+///
+/// Returns an error if `image` can not be read, `export_sheet` fails or the file
+/// can not be written.
+pub fn save_sheet(
+    tiles: &[Tile],
+    image: &Path,
+    output: &Path,
+    format: SheetFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let base = output.parent().unwrap_or_else(|| Path::new(""));
+    let reference = image.strip_prefix(base).unwrap_or(image);
+    let reference: Vec<_> = reference
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    let size = image::image_dimensions(image)?;
+    let contents = export_sheet(tiles, &reference.join("/"), size, format)?;
+    fs::write(output, contents)?;
+    Ok(())
+}
+
+/// A named rectangle on the sheet.
+struct Frame {
+    name: String,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+/// The cell size if every frame has the same size and sits on a multiple of it.
+fn uniform_grid(frames: &[Frame]) -> Option<(u32, u32)> {
+    let first = frames.first()?;
+    let cell = (first.width, first.height);
+    frames
+        .iter()
+        .all(|f| {
+            (f.width, f.height) == cell && f.x.is_multiple_of(cell.0) && f.y.is_multiple_of(cell.1)
+        })
+        .then_some(cell)
+}
+
+fn texture_packer(frames: &[Frame], image: &str, size: (u32, u32), hash: bool) -> String {
+    let frame_json = |f: &Frame| {
+        json!({
+            "frame": { "x": f.x, "y": f.y, "w": f.width, "h": f.height },
+            "rotated": false,
+            "trimmed": false,
+            "spriteSourceSize": { "x": 0, "y": 0, "w": f.width, "h": f.height },
+            "sourceSize": { "w": f.width, "h": f.height },
+        })
+    };
+    let frames = if hash {
+        Value::Object(
+            frames
+                .iter()
+                .map(|f| (f.name.clone(), frame_json(f)))
+                .collect::<Map<_, _>>(),
+        )
+    } else {
+        Value::Array(
+            frames
+                .iter()
+                .map(|f| {
+                    let mut value = frame_json(f);
+                    value["filename"] = json!(f.name);
+                    value
+                })
+                .collect(),
+        )
+    };
+    let document = json!({
+        "frames": frames,
+        "meta": {
+            "app": "papercut",
+            "version": "1.0",
+            "image": image,
+            "format": "RGBA8888",
+            "size": { "w": size.0, "h": size.1 },
+            "scale": "1",
+        },
+    });
+    serde_json::to_string_pretty(&document).unwrap_or_default()
+}
+
+fn godot_sprite_frames(frames: &[Frame], image: &str) -> String {
+    let mut out = format!(
+        "[gd_resource type=\"SpriteFrames\" load_steps={} format=3]\n\n",
+        frames.len() + 2
+    );
+    out += &format!(
+        "[ext_resource type=\"Texture2D\" path=\"{}\" id=\"1\"]\n\n",
+        godot_escape(image)
+    );
+    for (i, f) in frames.iter().enumerate() {
+        out += &format!(
+            "[sub_resource type=\"AtlasTexture\" id=\"AtlasTexture_{}\"]\natlas = ExtResource(\"1\")\nregion = Rect2({}, {}, {}, {})\n\n",
+            i + 1,
+            f.x,
+            f.y,
+            f.width,
+            f.height
+        );
+    }
+    let entries: Vec<String> = (1..=frames.len())
+        .map(|i| {
+            format!(
+                "{{\n\"duration\": 1.0,\n\"texture\": SubResource(\"AtlasTexture_{}\")\n}}",
+                i
+            )
+        })
+        .collect();
+    out += &format!(
+        "[resource]\nanimations = [{{\n\"frames\": [{}],\n\"loop\": true,\n\"name\": &\"default\",\n\"speed\": 5.0\n}}]\n",
+        entries.join(", ")
+    );
+    out
+}
+
+fn godot_tile_set(frames: &[Frame], image: &str, cell: (u32, u32)) -> String {
+    let mut out = String::from("[gd_resource type=\"TileSet\" load_steps=3 format=3]\n\n");
+    out += &format!(
+        "[ext_resource type=\"Texture2D\" path=\"{}\" id=\"1\"]\n\n",
+        godot_escape(image)
+    );
+    out += &format!(
+        "[sub_resource type=\"TileSetAtlasSource\" id=\"TileSetAtlasSource_1\"]\ntexture = ExtResource(\"1\")\ntexture_region_size = Vector2i({}, {})\n",
+        cell.0, cell.1
+    );
+    for f in frames {
+        out += &format!("{}:{}/0 = 0\n", f.x / cell.0, f.y / cell.1);
+    }
+    out += &format!(
+        "\n[resource]\ntile_size = Vector2i({}, {})\nsources/0 = SubResource(\"TileSetAtlasSource_1\")\n",
+        cell.0, cell.1
+    );
+    out
+}
+
+fn tiled_tileset(frames: &[Frame], image: &str, name: &str, size: (u32, u32)) -> String {
+    let image_tag = format!(
+        "<image source=\"{}\" width=\"{}\" height=\"{}\"/>",
+        xml_escape(image),
+        size.0,
+        size.1
+    );
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    match uniform_grid(frames) {
+        Some((w, h)) => {
+            let columns = size.0 / w;
+            out += &format!(
+                "<tileset version=\"1.10\" name=\"{}\" tilewidth=\"{}\" tileheight=\"{}\" tilecount=\"{}\" columns=\"{}\">\n {}\n",
+                xml_escape(name),
+                w,
+                h,
+                columns * (size.1 / h),
+                columns,
+                image_tag
+            );
+        }
+        None => {
+            let max_w = frames.iter().map(|f| f.width).max().unwrap_or(0);
+            let max_h = frames.iter().map(|f| f.height).max().unwrap_or(0);
+            out += &format!(
+                "<tileset version=\"1.10\" name=\"{}\" tilewidth=\"{}\" tileheight=\"{}\" tilecount=\"{}\" columns=\"0\">\n <grid orientation=\"orthogonal\" width=\"1\" height=\"1\"/>\n",
+                xml_escape(name),
+                max_w,
+                max_h,
+                frames.len()
+            );
+            for (id, f) in frames.iter().enumerate() {
+                out += &format!(
+                    " <tile id=\"{}\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\">\n  {}\n </tile>\n",
+                    id, f.x, f.y, f.width, f.height, image_tag
+                );
+            }
+        }
+    }
+    out += "</tileset>\n";
+    out
+}
+
+/// Escapes `text` for a quoted Godot resource string.
+fn godot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, RgbaImage};
+
+    fn grid() -> Vec<Tile> {
+        (0..4)
+            .map(|i| {
+                Tile::new(
+                    DynamicImage::ImageRgba8(RgbaImage::new(16, 8)),
+                    i + 1,
+                    (i % 2 + 1, i / 2 + 1),
+                    ((i % 2) * 16, (i / 2) * 8),
+                    None,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_texture_packer_hash_and_array() {
+        let hash: Value = serde_json::from_str(
+            &export_sheet(
+                &grid(),
+                "sheet.png",
+                (32, 16),
+                SheetFormat::TexturePackerHash,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(hash["meta"]["image"], "sheet.png");
+        assert_eq!(hash["meta"]["size"], json!({ "w": 32, "h": 16 }));
+        assert_eq!(
+            hash["frames"]["sheet_01_02.png"]["frame"],
+            json!({ "x": 16, "y": 0, "w": 16, "h": 8 })
+        );
+
+        let array: Value = serde_json::from_str(
+            &export_sheet(
+                &grid(),
+                "sheet.png",
+                (32, 16),
+                SheetFormat::TexturePackerArray,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(array["frames"].as_array().unwrap().len(), 4);
+        assert_eq!(array["frames"][3]["filename"], "sheet_02_02.png");
+    }
+
+    #[test]
+    fn test_godot_resources() {
+        let frames = export_sheet(
+            &grid(),
+            "res://sheet.png",
+            (32, 16),
+            SheetFormat::GodotSpriteFrames,
+        )
+        .unwrap();
+        assert!(frames.contains("load_steps=6"));
+        assert!(frames.contains("region = Rect2(16, 8, 16, 8)"));
+
+        let tile_set = export_sheet(
+            &grid(),
+            "res://sheet.png",
+            (32, 16),
+            SheetFormat::GodotTileSet,
+        )
+        .unwrap();
+        assert!(tile_set.contains("texture_region_size = Vector2i(16, 8)"));
+        assert!(tile_set.contains("1:1/0 = 0"));
+
+        let mut uneven = grid();
+        uneven[0].image = DynamicImage::ImageRgba8(RgbaImage::new(10, 8));
+        assert!(export_sheet(&uneven, "sheet.png", (32, 16), SheetFormat::GodotTileSet).is_err());
+    }
+
+    #[test]
+    fn test_tiled_tileset() {
+        let tsx = export_sheet(&grid(), "a&b.png", (32, 16), SheetFormat::TiledTileset).unwrap();
+        assert!(tsx.contains("tilewidth=\"16\" tileheight=\"8\" tilecount=\"4\" columns=\"2\""));
+        assert!(tsx.contains("<image source=\"a&amp;b.png\" width=\"32\" height=\"16\"/>"));
+
+        let mut uneven = grid();
+        uneven[3].image = DynamicImage::ImageRgba8(RgbaImage::new(10, 8));
+        let tsx = export_sheet(&uneven, "sheet.png", (32, 16), SheetFormat::TiledTileset).unwrap();
+        assert!(tsx.contains("<tile id=\"3\" x=\"16\" y=\"8\" width=\"10\" height=\"8\">"));
+    }
+
+    #[test]
+    fn test_sheet_larger_than_frames() {
+        // A 35x19 sheet sliced 2x2 leaves 3 columns and 3 rows out.
+        let json: Value = serde_json::from_str(
+            &export_sheet(
+                &grid(),
+                "sheet.png",
+                (35, 19),
+                SheetFormat::TexturePackerArray,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(json["meta"]["size"], json!({ "w": 35, "h": 19 }));
+        let tsx = export_sheet(&grid(), "sheet.png", (35, 19), SheetFormat::TiledTileset).unwrap();
+        assert!(tsx.contains("width=\"35\" height=\"19\""));
+        assert!(tsx.contains("tilecount=\"4\" columns=\"2\""));
+
+        let godot = export_sheet(
+            &grid(),
+            "res://my \"sheet\".png",
+            (35, 19),
+            SheetFormat::GodotSpriteFrames,
+        )
+        .unwrap();
+        assert!(godot.contains("path=\"res://my \\\"sheet\\\".png\""));
+    }
+}
@@ -0,0 +1,240 @@
+//! The `grid` module slices images along explicit, possibly uneven cut lines.
+//! Where `slice` divides an image into equally sized cells, a non-uniform grid is
+//! described by the pixel positions of its cuts or by relative weights of its
+//! columns and rows, which suits forms, UI mockups and comic pages.
+//!
+//! # Example Usage
+//!
+//! ```rust
+//! use papercut::{get_combined_size, slice_image_with_cuts, Cuts};
+//! use image::{DynamicImage, RgbaImage};
+//!
+//! let image = DynamicImage::ImageRgba8(RgbaImage::new(100, 60));
+//! let tiles = slice_image_with_cuts(
+//!     &image,
+//!     &Cuts::Positions(vec![30, 80]),
+//!     &Cuts::Weights(vec![1.0, 2.0]),
+//! )
+//! .unwrap();
+//! assert_eq!(tiles.len(), 6);
+//! assert_eq!((tiles[0].image.width(), tiles[0].image.height()), (30, 20));
+//! assert_eq!(get_combined_size(&tiles), (100, 60));
+//! ```
+
+use image::DynamicImage;
+use std::path::Path;
+
+use crate::tile::Tile;
+use crate::{SPLIT_LIMIT, get_basename, save_tiles};
+
+/// How to divide one axis of an image.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cuts {
+    /// Pixel positions of the interior cut lines, strictly increasing.
+    /// `vec![30, 80]` on a 100 px axis gives the spans `0..30`, `30..80` and `80..100`.
+    Positions(Vec<u32>),
+    /// Relative size of each span. `vec![1.0, 2.0]` gives one third and two thirds.
+    Weights(Vec<f64>),
+}
+
+impl Cuts {
+    /// Resolves the cuts against an axis of `length` pixels. Returns the span
+    /// boundaries, starting with `0` and ending with `length`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the cuts are out of range, not increasing, produce an
+    /// empty span, or exceed the split limit.
+    pub fn boundaries(&self, length: u32) -> Result<Vec<u32>, String> {
+        let mut bounds = vec![0];
+        match self {
+            Cuts::Positions(positions) => {
+                bounds.extend(positions.iter().copied());
+            }
+            Cuts::Weights(weights) => {
+                if weights.iter().any(|w| !(w.is_finite() && *w > 0.0)) {
+                    return Err("Weights must be positive numbers.".to_string());
+                }
+                let total: f64 = weights.iter().sum();
+                let mut acc = 0.0;
+                for weight in weights.iter().take(weights.len().saturating_sub(1)) {
+                    acc += weight;
+                    bounds.push((acc / total * f64::from(length)).round() as u32);
+                }
+            }
+        }
+        bounds.push(length);
+
+        if bounds.len() - 1 > SPLIT_LIMIT as usize {
+            return Err(format!(
+                "Number of spans must be at most {} (you asked for {}).",
+                SPLIT_LIMIT,
+                bounds.len() - 1
+            ));
+        }
+        if bounds.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(format!(
+                "Cuts must be strictly increasing and inside 1..{} (got {:?}).",
+                length,
+                &bounds[1..bounds.len() - 1]
+            ));
+        }
+        Ok(bounds)
+    }
+}
+
+/// Split an image file along explicit cut lines.
+///
+/// # Arguments
+///
+/// * `filename` - The filename of the image to split.
+/// * `x_cuts` - How to divide the image horizontally into columns.
+/// * `y_cuts` - How to divide the image vertically into rows.
+/// * `save` - Whether or not to save tiles to disk.
+///
+/// # Returns
+///
+/// A vector of `Tile` instances in row order.
+pub fn slice_with_cuts(
+    filename: &str,
+    x_cuts: &Cuts,
+    y_cuts: &Cuts,
+    save: bool,
+) -> Result<Vec<Tile>, String> {
+    let im =
+        image::open(Path::new(filename)).map_err(|_| format!("can not open image {}", filename))?;
+    let mut tiles = slice_image_with_cuts(&im, x_cuts, y_cuts)?;
+
+    if save {
+        let prefix = get_basename(filename);
+        let directory = Path::new(filename)
+            .parent()
+            .unwrap_or_else(|| Path::new("."));
+        save_tiles(&mut tiles, &prefix, Some(directory), "png")
+            .map_err(|_| "can not save tiles")?;
+    }
+
+    Ok(tiles)
+}
+
+/// Split an in-memory image along explicit cut lines.
+///
+/// # Errors
+///
+/// Returns an error if either set of cuts is invalid for the image or the grid
+/// would be a single tile.
+pub fn slice_image_with_cuts(
+    image: &DynamicImage,
+    x_cuts: &Cuts,
+    y_cuts: &Cuts,
+) -> Result<Vec<Tile>, String> {
+    let xs = x_cuts.boundaries(image.width())?;
+    let ys = y_cuts.boundaries(image.height())?;
+    if xs.len() == 2 && ys.len() == 2 {
+        return Err("There is nothing to divide. You asked for the entire image.".to_string());
+    }
+    Ok(crop_cells(image, &xs, &ys))
+}
+
+/// Split an in-memory image into cells of a fixed pixel size.
+///
+/// Unlike `slice`, the number of tiles follows from the image size, so there is
+/// no limit on the number of rows and columns. Cells on the right and bottom
+/// edges are smaller when the image size is not a multiple of the cell size.
+///
+/// # Errors
+///
+/// Returns an error if the cell width or height is zero.
+pub fn slice_image_by_size(
+    image: &DynamicImage,
+    tile_width: u32,
+    tile_height: u32,
+) -> Result<Vec<Tile>, String> {
+    if tile_width == 0 || tile_height == 0 {
+        return Err("Tile width and height must be at least 1 pixel.".to_string());
+    }
+    let bounds = |length: u32, step: u32| -> Vec<u32> {
+        (0..length)
+            .step_by(step as usize)
+            .chain(std::iter::once(length))
+            .collect()
+    };
+    Ok(crop_cells(
+        image,
+        &bounds(image.width(), tile_width),
+        &bounds(image.height(), tile_height),
+    ))
+}
+
+/// Crops the cells between consecutive `xs` and `ys` boundaries, in row order.
+fn crop_cells(image: &DynamicImage, xs: &[u32], ys: &[u32]) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+    for (row, y) in ys.windows(2).enumerate() {
+        for (column, x) in xs.windows(2).enumerate() {
+            tiles.push(Tile::new(
+                image.crop_imm(x[0], y[0], x[1] - x[0], y[1] - y[0]),
+                tiles.len() as i32 + 1,
+                (column as i32 + 1, row as i32 + 1),
+                (x[0] as i32, y[0] as i32),
+                None,
+            ));
+        }
+    }
+    tiles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::join;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn test_boundaries() {
+        assert_eq!(
+            Cuts::Positions(vec![10, 25]).boundaries(40).unwrap(),
+            vec![0, 10, 25, 40]
+        );
+        assert_eq!(
+            Cuts::Weights(vec![1.0, 1.0, 2.0]).boundaries(100).unwrap(),
+            vec![0, 25, 50, 100]
+        );
+        assert_eq!(Cuts::Positions(vec![]).boundaries(7).unwrap(), vec![0, 7]);
+        assert!(Cuts::Positions(vec![25, 10]).boundaries(40).is_err());
+        assert!(Cuts::Positions(vec![40]).boundaries(40).is_err());
+        assert!(Cuts::Weights(vec![1.0, 0.0]).boundaries(40).is_err());
+    }
+
+    #[test]
+    fn test_uneven_tiles_join_back() {
+        let source = DynamicImage::ImageRgba8(RgbaImage::from_fn(50, 40, |x, y| {
+            Rgba([x as u8, y as u8, 0, 255])
+        }));
+        let tiles = slice_image_with_cuts(
+            &source,
+            &Cuts::Positions(vec![7, 31]),
+            &Cuts::Weights(vec![3.0, 1.0]),
+        )
+        .unwrap();
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(tiles[4].coords, (7, 30));
+        assert_eq!((tiles[4].image.width(), tiles[4].image.height()), (24, 10));
+
+        let joined = join(&tiles, 0, 0).unwrap();
+        assert_eq!(joined.to_rgba8(), source.to_rgba8());
+    }
+
+    #[test]
+    fn test_slice_image_by_size() {
+        let source = DynamicImage::ImageRgba8(RgbaImage::new(1000, 20));
+        let tiles = slice_image_by_size(&source, 8, 16).unwrap();
+        // 125 columns is more than `slice` allows.
+        assert_eq!(tiles.len(), 250);
+        assert_eq!(tiles[125].coords, (0, 16));
+        assert_eq!(
+            (tiles[125].image.width(), tiles[125].image.height()),
+            (8, 4)
+        );
+        assert!(slice_image_by_size(&source, 0, 16).is_err());
+    }
+}
@@ -0,0 +1,258 @@
+//! The `gutter` module provides content-aware slicing for comics and scanned
+//! documents. Instead of cutting at fixed intervals like `calc_columns_rows`, it
+//! looks for gutters: rows or columns of near-uniform colour between panels, and
+//! cuts there.
+//!
+//! # Features
+//!
+//! - **Gutter Detection**: A line is a gutter when the variance of its luma falls
+//!   below a tolerance.
+//! - **Recursive Cuts**: The page is split into horizontal bands, each band into
+//!   columns, and so on until no gutter is left (an XY-cut).
+//! - **Reading Order**: Panels are emitted top to bottom and left to right, or
+//!   right to left for manga.
+//!
+//! # Example Usage
+//!
+//! ```no_run
+//! use papercut::{slice_gutters, GutterOptions, ReadingOrder};
+//!
+//! let options = GutterOptions {
+//!     reading_order: ReadingOrder::RightToLeft,
+//!     ..GutterOptions::default()
+//! };
+//! let panels = slice_gutters("page.png", &options, false).unwrap();
+//! ```
+
+use image::{DynamicImage, GrayImage};
+use std::path::Path;
+
+use crate::tile::Tile;
+use crate::{get_basename, save_tiles};
+
+/// Direction panels are read in within a row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadingOrder {
+    /// Western comics: left to right.
+    LeftToRight,
+    /// Manga: right to left.
+    RightToLeft,
+}
+
+/// Options for `slice_panels`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GutterOptions {
+    /// Largest luma variance a row or column may have and still count as a gutter.
+    pub tolerance: f64,
+    /// Panels narrower or shorter than this many pixels are treated as noise and dropped.
+    pub min_panel_size: u32,
+    /// Order in which panels in the same row are emitted.
+    pub reading_order: ReadingOrder,
+}
+
+impl Default for GutterOptions {
+    fn default() -> Self {
+        GutterOptions {
+            tolerance: 25.0,
+            min_panel_size: 32,
+            reading_order: ReadingOrder::LeftToRight,
+        }
+    }
+}
+
+/// Split an image file into panels at its gutters.
+///
+/// # Arguments
+///
+/// * `filename` - The filename of the image to split.
+/// * `options` - Gutter tolerance, minimum panel size and reading order.
+/// * `save` - Whether or not to save tiles to disk.
+///
+/// # Returns
+///
+/// A vector of `Tile` instances in reading order.
+pub fn slice_gutters(
+    filename: &str,
+    options: &GutterOptions,
+    save: bool,
+) -> Result<Vec<Tile>, String> {
+    let im =
+        image::open(Path::new(filename)).map_err(|_| format!("can not open image {}", filename))?;
+    let mut tiles = slice_panels(&im, options);
+
+    if save {
+        let prefix = get_basename(filename);
+        let directory = Path::new(filename)
+            .parent()
+            .unwrap_or_else(|| Path::new("."));
+        save_tiles(&mut tiles, &prefix, Some(directory), "png")
+            .map_err(|_| "can not save tiles")?;
+    }
+
+    Ok(tiles)
+}
+
+/// Split an in-memory image into panels at its gutters.
+///
+/// Panels are cropped tightly to their content. A panel's `position` is its
+/// `(column, row)` in reading order, where a new row starts once a panel begins
+/// below every panel of the current row.
+pub fn slice_panels(image: &DynamicImage, options: &GutterOptions) -> Vec<Tile> {
+    let luma = image.to_luma8();
+    let mut panels = Vec::new();
+    cut(
+        &luma,
+        (0, 0, luma.width(), luma.height()),
+        options,
+        &mut panels,
+    );
+
+    let mut tiles: Vec<Tile> = Vec::with_capacity(panels.len());
+    let (mut row, mut column, mut row_bottom) = (0, 0, 0);
+    for (x, y, w, h) in panels {
+        if tiles.is_empty() || y >= row_bottom {
+            row += 1;
+            column = 0;
+            row_bottom = y + h;
+        } else {
+            row_bottom = row_bottom.max(y + h);
+        }
+        column += 1;
+        tiles.push(Tile::new(
+            image.crop_imm(x, y, w, h),
+            tiles.len() as i32 + 1,
+            (column, row),
+            (x as i32, y as i32),
+            None,
+        ));
+    }
+    tiles
+}
+
+/// Recursively cuts `region` at its gutters, pushing panels in reading order.
+fn cut(
+    luma: &GrayImage,
+    region: (u32, u32, u32, u32),
+    options: &GutterOptions,
+    panels: &mut Vec<(u32, u32, u32, u32)>,
+) {
+    let (x, y, w, h) = region;
+    let rows = content_runs(&line_variances(luma, region, true), options);
+    if rows.len() != 1 || rows[0] != (0, h) {
+        for (start, end) in rows {
+            cut(luma, (x, y + start, w, end - start), options, panels);
+        }
+        return;
+    }
+
+    let mut columns = content_runs(&line_variances(luma, region, false), options);
+    if columns.len() == 1 && columns[0] == (0, w) {
+        panels.push(region);
+        return;
+    }
+    if options.reading_order == ReadingOrder::RightToLeft {
+        columns.reverse();
+    }
+    for (start, end) in columns {
+        cut(luma, (x + start, y, end - start, h), options, panels);
+    }
+}
+
+/// Luma variance of every row (`rows == true`) or column of `region`.
+fn line_variances(luma: &GrayImage, region: (u32, u32, u32, u32), rows: bool) -> Vec<f64> {
+    let (x, y, w, h) = region;
+    let (lines, length) = if rows { (h, w) } else { (w, h) };
+    (0..lines)
+        .map(|line| {
+            let (mut sum, mut sum_sq) = (0.0, 0.0);
+            for i in 0..length {
+                let (px, py) = if rows {
+                    (x + i, y + line)
+                } else {
+                    (x + line, y + i)
+                };
+                let v = f64::from(luma.get_pixel(px, py).0[0]);
+                sum += v;
+                sum_sq += v * v;
+            }
+            let mean = sum / f64::from(length);
+            sum_sq / f64::from(length) - mean * mean
+        })
+        .collect()
+}
+
+/// Runs of non-gutter lines at least `min_panel_size` long, as `(start, end)`.
+fn content_runs(variances: &[f64], options: &GutterOptions) -> Vec<(u32, u32)> {
+    let mut runs = Vec::new();
+    let mut start = None;
+    for (i, &variance) in variances.iter().chain(std::iter::once(&0.0)).enumerate() {
+        match (start, variance > options.tolerance && i < variances.len()) {
+            (None, true) => start = Some(i as u32),
+            (Some(s), false) => {
+                if i as u32 - s >= options.min_panel_size {
+                    runs.push((s, i as u32));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    /// A white page with a full-width panel on top and two panels below it.
+    fn page() -> DynamicImage {
+        let panels = [(10, 10, 180, 60), (10, 80, 80, 110), (100, 80, 90, 110)];
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(200, 200, |x, y| {
+            let inside = panels
+                .iter()
+                .any(|&(px, py, w, h)| x >= px && x < px + w && y >= py && y < py + h);
+            if inside {
+                Rgba([((x * 7 + y * 13) % 256) as u8, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        }))
+    }
+
+    #[test]
+    fn test_slice_panels_left_to_right() {
+        let tiles = slice_panels(&page(), &GutterOptions::default());
+        let layout: Vec<_> = tiles
+            .iter()
+            .map(|t| (t.position, t.coords, t.image.width(), t.image.height()))
+            .collect();
+        assert_eq!(
+            layout,
+            vec![
+                ((1, 1), (10, 10), 180, 60),
+                ((1, 2), (10, 80), 80, 110),
+                ((2, 2), (100, 80), 90, 110),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_slice_panels_right_to_left() {
+        let options = GutterOptions {
+            reading_order: ReadingOrder::RightToLeft,
+            ..GutterOptions::default()
+        };
+        let tiles = slice_panels(&page(), &options);
+        let coords: Vec<_> = tiles.iter().map(|t| t.coords).collect();
+        assert_eq!(coords, vec![(10, 10), (100, 80), (10, 80)]);
+    }
+
+    #[test]
+    fn test_min_panel_size_drops_specks() {
+        let mut image = page().to_rgba8();
+        image.put_pixel(195, 195, Rgba([0, 0, 0, 255]));
+        let tiles = slice_panels(&DynamicImage::ImageRgba8(image), &GutterOptions::default());
+        assert_eq!(tiles.len(), 3);
+    }
+}
@@ -0,0 +1,272 @@
+//! The `incremental` module re-slices an edited image without rewriting tiles
+//! that did not change. Each tile's pixel checksum is kept in the manifest, and
+//! the next run re-encodes only the tiles whose checksum differs or which touch
+//! an edited region, reporting them so caches can be purged selectively.
+//!
+//! # Features
+//!
+//! - **Checksums**: A BLAKE3 hash of each tile's pixels, stored in the manifest
+//!   entry's `hash`.
+//! - **Dirty Rectangles**: Tiles outside a known edited region are kept without
+//!   being hashed.
+//! - **Change Report**: Lists the files written and the files no longer part of
+//!   the tile set.
+//!
+//! # Example Usage
+//!
+//! ```no_run
+//! use papercut::reslice;
+//! use std::path::Path;
+//!
+//! let report = reslice("world.png", Path::new("world.json"), None, Some(16), Some(16), None).unwrap();
+//! for file in &report.updated {
+//!     println!("purge {}", file.display());
+//! }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::manifest::{Manifest, ManifestEntry};
+use crate::store::{HashAlgorithm, HashInput, tile_hash};
+use crate::tile::Tile;
+use crate::{get_basename, slice};
+
+/// The outcome of an incremental save.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResliceReport {
+    /// Manifest of the new tile set, checksums included.
+    pub manifest: Manifest,
+    /// Files that were written.
+    pub updated: Vec<PathBuf>,
+    /// Files of the previous tile set that are no longer used.
+    pub removed: Vec<PathBuf>,
+    /// Number of tiles kept from the previous run.
+    pub unchanged: usize,
+}
+
+/// Re-slice an image like `slice`, saving only tiles that changed since the
+/// manifest at `manifest_path` was written. Tiles are saved next to the image
+/// and the manifest is rewritten. Without a previous manifest every tile is
+/// saved.
+///
+/// # Arguments
+///
+/// * `filename` - The image to slice.
+/// * `manifest_path` - The manifest of the previous run.
+/// * `number_tiles` - The number of tiles required.
+/// * `col` - Number of columns (optional).
+/// * `row` - Number of rows (optional).
+/// * `dirty` - The edited region `(x, y, width, height)`, if known.
+///
+/// # Errors
+///
+/// Returns an error if the image can not be sliced or a file can not be read or
+/// written.
+pub fn reslice(
+    filename: &str,
+    manifest_path: &Path,
+    number_tiles: Option<u32>,
+    col: Option<u32>,
+    row: Option<u32>,
+    dirty: Option<(u32, u32, u32, u32)>,
+) -> Result<ResliceReport, Box<dyn std::error::Error>> {
+    let mut tiles = slice(filename, number_tiles, col, row, false)?;
+    let previous = if manifest_path.exists() {
+        Manifest::load(manifest_path)?
+    } else {
+        Manifest::default()
+    };
+    let prefix = get_basename(filename);
+    let directory = Path::new(filename)
+        .parent()
+        .unwrap_or_else(|| Path::new("."));
+    let report = save_changed_tiles(
+        &mut tiles,
+        &previous,
+        &prefix,
+        Some(directory),
+        "png",
+        dirty,
+    )?;
+    report.manifest.save(manifest_path)?;
+    Ok(report)
+}
+
+/// Save the tiles that differ from `previous`, matched by grid position.
+///
+/// A tile is kept when the previous entry has the same coordinates and size,
+/// its file still exists, and either it lies outside `dirty` or its checksum is
+/// unchanged. Without `dirty` every tile is hashed. Kept tiles take their
+/// `filename` from the previous entry. Empty tiles marked with a `background`
+/// are compared by colour and never written.
+///
+/// # Arguments
+///
+/// * `tiles` - The new tiles.
+/// * `previous` - The manifest of the previous run.
+/// * `prefix` - Filename prefix of saved tiles.
+/// * `directory` - Directory to save tiles. Created if non-existent.
+/// * `format` - Format of the saved tiles.
+/// * `dirty` - The edited region `(x, y, width, height)`, if known.
+///
+/// # Errors
+///
+/// Returns an error if a tile can not be saved.
+pub fn save_changed_tiles(
+    tiles: &mut [Tile],
+    previous: &Manifest,
+    prefix: &str,
+    directory: Option<&Path>,
+    format: &str,
+    dirty: Option<(u32, u32, u32, u32)>,
+) -> Result<ResliceReport, Box<dyn std::error::Error>> {
+    let current_dir = std::env::current_dir()?;
+    let dir = directory.unwrap_or(current_dir.as_path());
+    fs::create_dir_all(dir)?;
+
+    let by_position: HashMap<(i32, i32), &ManifestEntry> = previous
+        .tiles
+        .iter()
+        .map(|entry| (entry.position, entry))
+        .collect();
+    let mut report = ResliceReport::default();
+    let mut hashes = Vec::with_capacity(tiles.len());
+    for tile in tiles.iter_mut() {
+        let old = by_position.get(&tile.position).copied().filter(|e| {
+            e.coords == tile.coords && e.size == (tile.image.width(), tile.image.height())
+        });
+
+        if tile.background.is_some() {
+            if let Some(old) = old
+                && old.background.is_none()
+                && let Some(file) = &old.filename
+            {
+                report.removed.push(file.clone());
+            }
+            hashes.push(None);
+            continue;
+        }
+
+        let kept = old.filter(|e| e.filename.as_ref().is_some_and(|f| f.exists()));
+        let clean = kept.is_some() && dirty.is_some_and(|rect| !overlaps(tile, rect));
+        let hash = match kept {
+            Some(old) if clean && old.hash.is_some() => old.hash.clone(),
+            _ => Some(tile_hash(
+                tile,
+                HashAlgorithm::Blake3,
+                HashInput::Pixels,
+                format,
+            )?),
+        };
+        match kept {
+            Some(old) if clean || old.hash == hash => {
+                tile.filename = old.filename.clone();
+                report.unchanged += 1;
+            }
+            _ => {
+                let filename = tile.generate_filename(Some(dir), prefix, format, true);
+                tile.save(Some(filename.clone()), format)?;
+                report.updated.push(filename);
+            }
+        }
+        hashes.push(hash);
+    }
+
+    let positions: HashSet<(i32, i32)> = tiles.iter().map(|t| t.position).collect();
+    for entry in &previous.tiles {
+        let gone = !positions.contains(&entry.position);
+        if gone && let Some(file) = &entry.filename {
+            report.removed.push(file.clone());
+        }
+    }
+
+    report.manifest = Manifest::from_tiles(tiles);
+    for (entry, hash) in report.manifest.tiles.iter_mut().zip(hashes) {
+        entry.hash = hash;
+    }
+    Ok(report)
+}
+
+/// Whether `tile` shares any pixel with the rectangle `(x, y, width, height)`.
+fn overlaps(tile: &Tile, (x, y, width, height): (u32, u32, u32, u32)) -> bool {
+    let (tx, ty) = (tile.coords.0 as i64, tile.coords.1 as i64);
+    let (tw, th) = (tile.image.width() as i64, tile.image.height() as i64);
+    let (x, y, width, height) = (x as i64, y as i64, width as i64, height as i64);
+    tx < x + width && x < tx + tw && ty < y + height && y < ty + th
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::slice_image_by_size;
+    use image::{DynamicImage, Rgba, RgbaImage};
+
+    fn image(edit: Option<(u32, u32)>) -> DynamicImage {
+        let mut image = RgbaImage::from_fn(8, 8, |x, y| Rgba([x as u8 * 30, y as u8 * 30, 0, 255]));
+        if let Some((x, y)) = edit {
+            image.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+        }
+        DynamicImage::ImageRgba8(image)
+    }
+
+    #[test]
+    fn test_only_changed_tiles_are_saved() {
+        let directory = std::env::temp_dir().join("papercut_incremental_test");
+        let _ = fs::remove_dir_all(&directory);
+        let save = |image: &DynamicImage, previous: &Manifest, dirty| {
+            let mut tiles = slice_image_by_size(image, 4, 4).unwrap();
+            save_changed_tiles(&mut tiles, previous, "map", Some(&directory), "png", dirty).unwrap()
+        };
+
+        let first = save(&image(None), &Manifest::default(), None);
+        assert_eq!((first.updated.len(), first.unchanged), (4, 0));
+        assert!(first.manifest.tiles.iter().all(|e| e.hash.is_some()));
+
+        // One pixel in the bottom-right tile changes.
+        let second = save(&image(Some((6, 5))), &first.manifest, None);
+        assert_eq!(second.updated, vec![directory.join("map_02_02.png")]);
+        assert_eq!(second.unchanged, 3);
+        assert_ne!(second.manifest.tiles[3].hash, first.manifest.tiles[3].hash);
+        assert_eq!(second.manifest.tiles[0].hash, first.manifest.tiles[0].hash);
+
+        // A dirty rectangle skips hashing elsewhere, so an edit outside it is missed.
+        let third = save(&image(Some((1, 1))), &second.manifest, Some((4, 0, 4, 4)));
+        assert!(third.updated.is_empty());
+        assert_eq!(third.unchanged, 4);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_removed_tiles() {
+        let directory = std::env::temp_dir().join("papercut_incremental_removed_test");
+        let _ = fs::remove_dir_all(&directory);
+        let mut tiles = slice_image_by_size(&image(None), 4, 4).unwrap();
+        let first = save_changed_tiles(
+            &mut tiles,
+            &Manifest::default(),
+            "map",
+            Some(&directory),
+            "png",
+            None,
+        )
+        .unwrap();
+        let mut tiles = slice_image_by_size(&image(None), 8, 4).unwrap();
+        let second = save_changed_tiles(
+            &mut tiles,
+            &first.manifest,
+            "map",
+            Some(&directory),
+            "png",
+            None,
+        )
+        .unwrap();
+        // The new tiles are wider, so none match and the second column is gone.
+        assert_eq!(second.updated.len(), 2);
+        assert_eq!(second.removed.len(), 2);
+        assert!(overlaps(&tiles[0], (7, 3, 1, 1)));
+        assert!(!overlaps(&tiles[0], (0, 4, 8, 4)));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,384 @@
+//! The `inference` module merges per-tile model outputs back into one map for
+//! the whole image. It is the counterpart of `join` for predictions: values are
+//! floats or class ids rather than 8-bit colours, and overlapping tiles are
+//! blended instead of pasted over each other.
+//!
+//! # Features
+//!
+//! - **Score Blending**: Average float maps such as class probabilities, with
+//!   each tile weighted by a uniform, linear or Gaussian window so tile centers
+//!   count more than their borders.
+//! - **Class Voting**: Merge class id masks by weighted vote.
+//!
+//! # Example Usage
+//!
+//! ```no_run
+//! use papercut::{stitch_scores, BlendWindow, DatasetOptions, ScoreMap, tile_dataset};
+//!
+//! let image = image::open("scan.png").unwrap();
+//! let options = DatasetOptions { overlap: 128, ..DatasetOptions::default() };
+//! let tiles = tile_dataset(&image, &[], &[], &options).unwrap();
+//! let predictions: Vec<ScoreMap> = tiles
+//!     .iter()
+//!     .map(|t| ScoreMap::new(t.tile.image.width(), t.tile.image.height(), 3))
+//!     .collect();
+//! let pairs: Vec<_> = tiles.iter().map(|t| &t.tile).zip(&predictions).collect();
+//! let merged = stitch_scores(&pairs, image.width(), image.height(), BlendWindow::Gaussian(0.125)).unwrap();
+//! let classes = merged.argmax();
+//! ```
+
+use image::{GrayImage, Luma};
+
+use crate::tile::Tile;
+
+/// A map of `channels` float values per pixel, stored row by row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoreMap {
+    pub width: u32,
+    pub height: u32,
+    pub channels: u32,
+    pub data: Vec<f32>,
+}
+
+impl ScoreMap {
+    /// A map of zeros.
+    pub fn new(width: u32, height: u32, channels: u32) -> Self {
+        ScoreMap {
+            width,
+            height,
+            channels,
+            data: vec![0.0; width as usize * height as usize * channels as usize],
+        }
+    }
+
+    /// Wraps existing values.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `data` does not hold `width * height * channels` values.
+    pub fn from_vec(
+        width: u32,
+        height: u32,
+        channels: u32,
+        data: Vec<f32>,
+    ) -> Result<Self, String> {
+        if data.len() != width as usize * height as usize * channels as usize {
+            return Err(format!(
+                "{} values do not make a {}x{} map with {} channels.",
+                data.len(),
+                width,
+                height,
+                channels
+            ));
+        }
+        Ok(ScoreMap {
+            width,
+            height,
+            channels,
+            data,
+        })
+    }
+
+    /// The values of the pixel at `(x, y)`.
+    pub fn pixel(&self, x: u32, y: u32) -> &[f32] {
+        let start = (y as usize * self.width as usize + x as usize) * self.channels as usize;
+        &self.data[start..start + self.channels as usize]
+    }
+
+    /// The channel with the highest value at each pixel.
+    pub fn argmax(&self) -> ClassMap {
+        let data = self
+            .data
+            .chunks_exact(self.channels.max(1) as usize)
+            .map(|values| {
+                values
+                    .iter()
+                    .enumerate()
+                    .fold((0, f32::NEG_INFINITY), |best, (i, &v)| {
+                        if v > best.1 { (i as u32, v) } else { best }
+                    })
+                    .0
+            })
+            .collect();
+        ClassMap {
+            width: self.width,
+            height: self.height,
+            data,
+        }
+    }
+}
+
+/// A map of one class id per pixel, stored row by row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassMap {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u32>,
+}
+
+impl ClassMap {
+    /// Reads class ids from a grayscale mask.
+    pub fn from_image(mask: &GrayImage) -> Self {
+        ClassMap {
+            width: mask.width(),
+            height: mask.height(),
+            data: mask.pixels().map(|p| p[0] as u32).collect(),
+        }
+    }
+
+    /// The class id at `(x, y)`.
+    pub fn get(&self, x: u32, y: u32) -> u32 {
+        self.data[y as usize * self.width as usize + x as usize]
+    }
+
+    /// The map as a grayscale mask. Ids above 255 are clamped.
+    pub fn to_image(&self) -> GrayImage {
+        GrayImage::from_fn(self.width, self.height, |x, y| {
+            Luma([self.get(x, y).min(255) as u8])
+        })
+    }
+}
+
+/// How much each pixel of a tile counts when overlapping tiles are merged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlendWindow {
+    /// Every pixel counts the same.
+    Uniform,
+    /// Weight falls linearly from the tile center to its edges.
+    Linear,
+    /// Gaussian weight with the standard deviation as a fraction of the tile size.
+    Gaussian(f64),
+}
+
+impl BlendWindow {
+    /// Weights along one axis of a tile `length` pixels long. Never zero, so
+    /// pixels covered by a single tile keep its value.
+    fn weights(&self, length: u32) -> Vec<f32> {
+        let center = length as f64 / 2.0;
+        (0..length)
+            .map(|i| {
+                let d = i as f64 + 0.5 - center;
+                let weight = match *self {
+                    BlendWindow::Uniform => 1.0,
+                    BlendWindow::Linear => 1.0 - d.abs() / center,
+                    BlendWindow::Gaussian(sigma) => {
+                        let s = (sigma * length as f64).max(f64::EPSILON);
+                        (-d * d / (2.0 * s * s)).exp()
+                    }
+                };
+                weight.max(1e-6) as f32
+            })
+            .collect()
+    }
+}
+
+/// Merge per-tile score maps into one map of `width x height`.
+///
+/// Each prediction covers its tile's image, placed at the tile's `coords`;
+/// parts outside the map, such as padding, are ignored. Where tiles overlap the
+/// result is the window-weighted mean. Pixels no tile covers are zero. If
+/// `width` or `height` is zero the size is the bounding box of the tiles.
+///
+/// # Errors
+///
+/// Returns an error if a prediction's size differs from its tile's or the
+/// predictions disagree on the number of channels.
+pub fn stitch_scores(
+    pairs: &[(&Tile, &ScoreMap)],
+    width: u32,
+    height: u32,
+    window: BlendWindow,
+) -> Result<ScoreMap, String> {
+    check_sizes(pairs.iter().map(|(t, p)| (*t, (p.width, p.height))))?;
+    let channels = pairs.first().map_or(1, |(_, p)| p.channels);
+    if pairs.iter().any(|(_, p)| p.channels != channels) {
+        return Err("Predictions must all have the same number of channels.".to_string());
+    }
+    let (width, height) = output_size(pairs.iter().map(|(t, _)| *t), width, height);
+
+    let mut sums = ScoreMap::new(width, height, channels);
+    let mut weights = vec![0.0f32; width as usize * height as usize];
+    for (tile, prediction) in pairs {
+        for_each_pixel(tile, width, height, window, |x, y, index, weight| {
+            weights[index] += weight;
+            let source = prediction.pixel(x, y);
+            let start = index * channels as usize;
+            for (sum, value) in sums.data[start..start + channels as usize]
+                .iter_mut()
+                .zip(source)
+            {
+                *sum += weight * value;
+            }
+        });
+    }
+    for (index, &weight) in weights.iter().enumerate() {
+        if weight > 0.0 {
+            let start = index * channels as usize;
+            for value in &mut sums.data[start..start + channels as usize] {
+                *value /= weight;
+            }
+        }
+    }
+    Ok(sums)
+}
+
+/// Merge per-tile class maps into one map of `width x height` by weighted vote.
+///
+/// Tiles are placed as in `stitch_scores`. Each pixel takes the class with the
+/// largest summed window weight; ties go to the lower id, and pixels without
+/// votes are class 0. Ids of `classes` or more, such as an ignore label of 255,
+/// do not vote.
+///
+/// # Errors
+///
+/// Returns an error if a prediction's size differs from its tile's or
+/// `classes` is zero.
+pub fn stitch_classes(
+    pairs: &[(&Tile, &ClassMap)],
+    width: u32,
+    height: u32,
+    classes: u32,
+    window: BlendWindow,
+) -> Result<ClassMap, String> {
+    check_sizes(pairs.iter().map(|(t, p)| (*t, (p.width, p.height))))?;
+    if classes == 0 {
+        return Err("There must be at least one class.".to_string());
+    }
+    let (width, height) = output_size(pairs.iter().map(|(t, _)| *t), width, height);
+
+    let mut votes = ScoreMap::new(width, height, classes);
+    for (tile, prediction) in pairs {
+        for_each_pixel(tile, width, height, window, |x, y, index, weight| {
+            let class = prediction.get(x, y);
+            if class < classes {
+                votes.data[index * classes as usize + class as usize] += weight;
+            }
+        });
+    }
+    Ok(votes.argmax())
+}
+
+fn check_sizes<'a>(pairs: impl Iterator<Item = (&'a Tile, (u32, u32))>) -> Result<(), String> {
+    for (tile, size) in pairs {
+        let tile_size = (tile.image.width(), tile.image.height());
+        if size != tile_size {
+            return Err(format!(
+                "A {}x{} prediction does not match tile {} of {}x{}.",
+                size.0, size.1, tile.number, tile_size.0, tile_size.1
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn output_size<'a>(tiles: impl Iterator<Item = &'a Tile>, width: u32, height: u32) -> (u32, u32) {
+    if width > 0 && height > 0 {
+        return (width, height);
+    }
+    tiles.fold((0, 0), |(w, h), tile| {
+        (
+            w.max((tile.coords.0 + tile.image.width() as i32).max(0) as u32),
+            h.max((tile.coords.1 + tile.image.height() as i32).max(0) as u32),
+        )
+    })
+}
+
+/// Calls `f(x, y, index, weight)` for each pixel of `tile` inside the output,
+/// with `(x, y)` in the tile and `index` the pixel's position in the output.
+fn for_each_pixel(
+    tile: &Tile,
+    width: u32,
+    height: u32,
+    window: BlendWindow,
+    mut f: impl FnMut(u32, u32, usize, f32),
+) {
+    let (w, h) = (tile.image.width(), tile.image.height());
+    let (wx, wy) = (window.weights(w), window.weights(h));
+    for y in 0..h {
+        let oy = tile.coords.1 as i64 + y as i64;
+        if oy < 0 || oy >= height as i64 {
+            continue;
+        }
+        for x in 0..w {
+            let ox = tile.coords.0 as i64 + x as i64;
+            if ox < 0 || ox >= width as i64 {
+                continue;
+            }
+            let index = oy as usize * width as usize + ox as usize;
+            f(x, y, index, wx[x as usize] * wy[y as usize]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, RgbaImage};
+
+    fn tile(x: i32, width: u32) -> Tile {
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(width, 1));
+        Tile::new(image, 1, (1, 1), (x, 0), None)
+    }
+
+    #[test]
+    fn test_score_blending() {
+        // Two 4 px tiles overlapping by 2 px, predicting 0 and 1.
+        let (left, right) = (tile(0, 4), tile(2, 4));
+        let zeros = ScoreMap::from_vec(4, 1, 1, vec![0.0; 4]).unwrap();
+        let ones = ScoreMap::from_vec(4, 1, 1, vec![1.0; 4]).unwrap();
+        let pairs = [(&left, &zeros), (&right, &ones)];
+
+        let uniform = stitch_scores(&pairs, 0, 0, BlendWindow::Uniform).unwrap();
+        assert_eq!(uniform.data, vec![0.0, 0.0, 0.5, 0.5, 1.0, 1.0]);
+
+        // Linear weights are 0.25 and 0.75: x = 2 is the left tile's inner pixel.
+        let linear = stitch_scores(&pairs, 0, 0, BlendWindow::Linear).unwrap();
+        assert_eq!(linear.data[2], 0.25);
+        assert_eq!(linear.data[3], 0.75);
+        assert_eq!(linear.data[5], 1.0);
+
+        let wrong = ScoreMap::new(3, 1, 1);
+        assert!(stitch_scores(&[(&left, &wrong)], 0, 0, BlendWindow::Uniform).is_err());
+    }
+
+    #[test]
+    fn test_class_voting_and_padding() {
+        // Three tiles over one pixel: two vote for class 2 and one for class 5.
+        let tiles = [tile(0, 1), tile(0, 1), tile(0, 1)];
+        let twos = ClassMap {
+            width: 1,
+            height: 1,
+            data: vec![2],
+        };
+        let five = ClassMap {
+            width: 1,
+            height: 1,
+            data: vec![5],
+        };
+        let pairs = [(&tiles[0], &twos), (&tiles[1], &five), (&tiles[2], &twos)];
+        let merged = stitch_classes(&pairs, 0, 0, 6, BlendWindow::Gaussian(0.125)).unwrap();
+        assert_eq!(merged.data, vec![2]);
+
+        // Ignore labels outside the class count do not vote.
+        let ignore = ClassMap {
+            width: 1,
+            height: 1,
+            data: vec![255],
+        };
+        let pairs = [
+            (&tiles[0], &ignore),
+            (&tiles[1], &ignore),
+            (&tiles[2], &five),
+        ];
+        let merged = stitch_classes(&pairs, 0, 0, 6, BlendWindow::Uniform).unwrap();
+        assert_eq!(merged.data, vec![5]);
+        assert!(stitch_classes(&pairs, 0, 0, 0, BlendWindow::Uniform).is_err());
+
+        // A tile padded by one pixel on the left is cut to the output.
+        let padded = tile(-1, 3);
+        let map = ScoreMap::from_vec(3, 1, 2, vec![9.0, 0.0, 0.0, 1.0, 1.0, 0.0]).unwrap();
+        let merged = stitch_scores(&[(&padded, &map)], 2, 1, BlendWindow::Linear).unwrap();
+        assert_eq!(merged.data, vec![0.0, 1.0, 1.0, 0.0]);
+        assert_eq!(merged.argmax().data, vec![1, 0]);
+    }
+}
@@ -0,0 +1,422 @@
+//! The `jigsaw` module cuts an image into classic interlocking puzzle pieces.
+//! Every inner edge of the grid gets a randomly shaped bezier tab that is shared
+//! by the two pieces it separates, so the pieces fit back together exactly.
+//!
+//! # Features
+//!
+//! - **Reproducible Shapes**: Tab directions and jitter come from a seeded generator;
+//!   the same seed always produces the same pieces.
+//! - **Alpha Masks**: Each piece is a `Tile` whose bounding box includes its tabs and
+//!   whose `mask` marks the pixels belonging to the piece.
+//! - **Reassembly**: `join` composites masked tiles, so the pieces rebuild the source.
+//!
+//! # Example Usage
+//!
+//! ```rust
+//! use papercut::{jigsaw_pieces, join, JigsawOptions};
+//! use image::{DynamicImage, RgbaImage};
+//!
+//! let image = DynamicImage::ImageRgba8(RgbaImage::new(120, 80));
+//! let pieces = jigsaw_pieces(&image, 3, 2, &JigsawOptions::default()).unwrap();
+//! assert_eq!(pieces.len(), 6);
+//! let rebuilt = join(&pieces, 120, 80).unwrap();
+//! ```
+
+use image::{DynamicImage, GrayImage, Luma, Rgba};
+use std::path::Path;
+
+use crate::resolve_grid;
+use crate::tile::Tile;
+
+/// Options controlling the shape of jigsaw pieces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JigsawOptions {
+    /// Seed for the tab generator. Equal seeds give identical pieces.
+    pub seed: u64,
+    /// Size of the tabs relative to the smaller side of a cell, typically `0.08..=0.12`.
+    pub tab_size: f64,
+    /// Random variation applied to tab placement and shape, relative to the cell size.
+    pub jitter: f64,
+}
+
+/// Largest `tab_size + jitter` for which tabs on neighbouring edges never touch.
+const MAX_TAB_SPAN: f64 = 0.15;
+
+impl JigsawOptions {
+    /// Check that the tabs stay clear of the cell corners and of each other.
+    fn validate(&self) -> Result<(), String> {
+        if !(self.tab_size >= 0.0 && self.jitter >= 0.0) {
+            return Err("Tab size and jitter must be non-negative.".to_string());
+        }
+        if self.tab_size + self.jitter > MAX_TAB_SPAN {
+            return Err(format!(
+                "Tab size plus jitter must not exceed {}.",
+                MAX_TAB_SPAN
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for JigsawOptions {
+    fn default() -> Self {
+        JigsawOptions {
+            seed: 0,
+            tab_size: 0.1,
+            jitter: 0.04,
+        }
+    }
+}
+
+/// Split an image file into jigsaw pieces.
+///
+/// The grid is chosen exactly like `slice`: either from `number_tiles` or from
+/// `col` and `row`.
+///
+/// # Arguments
+///
+/// * `filename` - The filename of the image to split.
+/// * `number_tiles` - The number of pieces required.
+/// * `col` - Number of columns (optional).
+/// * `row` - Number of rows (optional).
+/// * `options` - Shape options and seed for the tabs.
+///
+/// # Returns
+///
+/// A vector of masked `Tile` instances, one per grid cell.
+pub fn slice_jigsaw(
+    filename: &str,
+    number_tiles: Option<u32>,
+    col: Option<u32>,
+    row: Option<u32>,
+    options: &JigsawOptions,
+) -> Result<Vec<Tile>, String> {
+    let im =
+        image::open(Path::new(filename)).map_err(|_| format!("can not open image {}", filename))?;
+    let (columns, rows) = resolve_grid(number_tiles, col, row)?;
+    jigsaw_pieces(&im, columns, rows, options)
+}
+
+/// Cut an in-memory image into `columns x rows` jigsaw pieces.
+///
+/// Each piece's `coords` is the top-left corner of its bounding box in the source
+/// image, which includes any tabs reaching into neighbouring cells. Pixels outside
+/// the piece are transparent in `image` and zero in `mask`.
+///
+/// # Errors
+///
+/// Returns an error if the grid is invalid, the cells are too small for tabs, or
+/// `tab_size` or `jitter` is negative or together they exceed `0.15`.
+pub fn jigsaw_pieces(
+    image: &DynamicImage,
+    columns: u32,
+    rows: u32,
+    options: &JigsawOptions,
+) -> Result<Vec<Tile>, String> {
+    crate::validate_image_col_row(columns, rows)?;
+    options.validate()?;
+    let (tile_w, tile_h) = (image.width() / columns, image.height() / rows);
+    if tile_w < 4 || tile_h < 4 {
+        return Err("The pieces are too small to carry tabs.".to_string());
+    }
+
+    let scale = f64::from(tile_w.min(tile_h));
+    let reach = ((3.0 * options.tab_size + options.jitter) * scale).ceil() as u32 + 1;
+    let mut rng = SplitMix64(options.seed);
+
+    // Vertical edges separate column c from c + 1, horizontal edges row r from r + 1.
+    let vertical: Vec<Vec<EdgeShape>> = (0..rows)
+        .map(|_| {
+            (1..columns)
+                .map(|_| EdgeShape::random(&mut rng, options))
+                .collect()
+        })
+        .collect();
+    let horizontal: Vec<Vec<EdgeShape>> = (1..rows)
+        .map(|_| {
+            (0..columns)
+                .map(|_| EdgeShape::random(&mut rng, options))
+                .collect()
+        })
+        .collect();
+
+    let source = image.to_rgba8();
+    let mut pieces = Vec::new();
+    for row in 0..rows {
+        for column in 0..columns {
+            let (x0, y0) = (f64::from(column * tile_w), f64::from(row * tile_h));
+            let (x1, y1) = (x0 + f64::from(tile_w), y0 + f64::from(tile_h));
+
+            let (r, c) = (row as usize, column as usize);
+            let top = if row == 0 {
+                vec![(x0, y0), (x1, y0)]
+            } else {
+                horizontal[r - 1][c].trace((x0, y0), (x1, y0), scale)
+            };
+            let right = if column + 1 == columns {
+                vec![(x1, y0), (x1, y1)]
+            } else {
+                vertical[r][c].trace((x1, y0), (x1, y1), scale)
+            };
+            let bottom = if row + 1 == rows {
+                vec![(x0, y1), (x1, y1)]
+            } else {
+                horizontal[r][c].trace((x0, y1), (x1, y1), scale)
+            };
+            let left = if column == 0 {
+                vec![(x0, y0), (x0, y1)]
+            } else {
+                vertical[r][c - 1].trace((x0, y0), (x0, y1), scale)
+            };
+
+            let mut outline = top;
+            outline.extend(right);
+            outline.extend(bottom.into_iter().rev());
+            outline.extend(left.into_iter().rev());
+
+            let bx = (column * tile_w).saturating_sub(if column > 0 { reach } else { 0 });
+            let by = (row * tile_h).saturating_sub(if row > 0 { reach } else { 0 });
+            let bx1 = ((column + 1) * tile_w + if column + 1 < columns { reach } else { 0 })
+                .min(columns * tile_w);
+            let by1 =
+                ((row + 1) * tile_h + if row + 1 < rows { reach } else { 0 }).min(rows * tile_h);
+
+            let mask = rasterize(&outline, (bx, by), (bx1 - bx, by1 - by));
+            let mut piece = image::RgbaImage::new(bx1 - bx, by1 - by);
+            for (x, y, pixel) in piece.enumerate_pixels_mut() {
+                if mask.get_pixel(x, y).0[0] > 0 {
+                    *pixel = *source.get_pixel(bx + x, by + y);
+                } else {
+                    *pixel = Rgba([0, 0, 0, 0]);
+                }
+            }
+
+            let mut tile = Tile::new(
+                DynamicImage::ImageRgba8(piece),
+                (row * columns + column + 1) as i32,
+                (column as i32 + 1, row as i32 + 1),
+                (bx as i32, by as i32),
+                None,
+            );
+            tile.mask = Some(mask);
+            pieces.push(tile);
+        }
+    }
+
+    Ok(pieces)
+}
+
+/// Random parameters of one shared edge.
+#[derive(Debug, Clone, Copy)]
+struct EdgeShape {
+    /// `1.0` if the tab points to the right of the direction of travel, `-1.0` otherwise.
+    flip: f64,
+    tab: f64,
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+    e: f64,
+}
+
+impl EdgeShape {
+    fn random(rng: &mut SplitMix64, options: &JigsawOptions) -> Self {
+        let mut jitter = || (rng.next_f64() * 2.0 - 1.0) * options.jitter;
+        EdgeShape {
+            a: jitter(),
+            b: jitter(),
+            c: jitter(),
+            d: jitter(),
+            e: jitter(),
+            tab: options.tab_size,
+            flip: if rng.next_f64() < 0.5 { -1.0 } else { 1.0 },
+        }
+    }
+
+    /// Samples the edge from `start` to `end` (axis aligned) in image coordinates.
+    /// The perpendicular extent of the tab is measured in units of `scale`.
+    fn trace(&self, start: (f64, f64), end: (f64, f64), scale: f64) -> Vec<(f64, f64)> {
+        let (t, a, b, c, d, e) = (self.tab, self.a, self.b, self.c, self.d, self.e);
+        let control = [
+            (0.0, 0.0),
+            (0.2, a),
+            (0.5 + b + d, -t + c),
+            (0.5 - t + b, t + c),
+            (0.5 - 2.0 * t + b - d, 3.0 * t + c),
+            (0.5 + 2.0 * t + b - d, 3.0 * t + c),
+            (0.5 + t + b, t + c),
+            (0.5 + b + d, -t + c),
+            (0.8, e),
+            (1.0, 0.0),
+        ];
+
+        let length = (end.0 - start.0).abs() + (end.1 - start.1).abs();
+        let (ux, uy) = ((end.0 - start.0) / length, (end.1 - start.1) / length);
+        let (nx, ny) = (-uy * self.flip, ux * self.flip);
+        let to_image = |(u, v): (f64, f64)| {
+            (
+                start.0 + ux * u * length + nx * v * scale,
+                start.1 + uy * u * length + ny * v * scale,
+            )
+        };
+
+        const STEPS: usize = 16;
+        let mut points = vec![to_image(control[0])];
+        for curve in control[..].windows(4).step_by(3) {
+            for step in 1..=STEPS {
+                let s = step as f64 / STEPS as f64;
+                let r = 1.0 - s;
+                let w = [r * r * r, 3.0 * r * r * s, 3.0 * r * s * s, s * s * s];
+                let u = (0..4).map(|i| w[i] * curve[i].0).sum::<f64>();
+                let v = (0..4).map(|i| w[i] * curve[i].1).sum::<f64>();
+                points.push(to_image((u, v)));
+            }
+        }
+        // The end points are exact so neighbouring edges meet at the grid corners.
+        *points.last_mut().unwrap() = end;
+        points[0] = start;
+        points
+    }
+}
+
+/// Fill a closed polygon into a mask of `size` whose top-left is at `origin`.
+///
+/// A pixel belongs to the polygon when its centre does, using a half-open
+/// even-odd rule. Crossings are always computed from the lower end of a segment,
+/// so two pieces sharing an edge split its pixels without gaps or overlap.
+fn rasterize(outline: &[(f64, f64)], origin: (u32, u32), size: (u32, u32)) -> GrayImage {
+    let mut mask = GrayImage::new(size.0, size.1);
+    let mut crossings = Vec::new();
+    for y in 0..size.1 {
+        let py = f64::from(origin.1 + y) + 0.5;
+        crossings.clear();
+        for i in 0..outline.len() {
+            let (mut p, mut q) = (outline[i], outline[(i + 1) % outline.len()]);
+            if p.1 > q.1 {
+                std::mem::swap(&mut p, &mut q);
+            }
+            if p.1 <= py && py < q.1 {
+                crossings.push(p.0 + (py - p.1) * (q.0 - p.0) / (q.1 - p.1));
+            }
+        }
+        crossings.sort_by(f64::total_cmp);
+        for span in crossings.chunks_exact(2) {
+            for x in 0..size.0 {
+                let px = f64::from(origin.0 + x) + 0.5;
+                if span[0] <= px && px < span[1] {
+                    mask.put_pixel(x, y, Luma([255]));
+                }
+            }
+        }
+    }
+    mask
+}
+
+/// Small deterministic generator so piece shapes are reproducible across platforms.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::join;
+    use image::RgbaImage;
+
+    fn gradient(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([x as u8, y as u8, (x * y) as u8, 255])
+        }))
+    }
+
+    #[test]
+    fn test_pieces_rebuild_source() {
+        let source = gradient(120, 90);
+        let pieces = jigsaw_pieces(&source, 4, 3, &JigsawOptions::default()).unwrap();
+        assert_eq!(pieces.len(), 12);
+
+        let covered: u32 = pieces
+            .iter()
+            .map(|p| {
+                p.mask
+                    .as_ref()
+                    .unwrap()
+                    .pixels()
+                    .filter(|m| m.0[0] > 0)
+                    .count() as u32
+            })
+            .sum();
+        assert_eq!(covered, 120 * 90);
+
+        let rebuilt = join(&pieces, 120, 90).unwrap();
+        assert_eq!(rebuilt.to_rgba8(), source.to_rgba8());
+    }
+
+    #[test]
+    fn test_pieces_include_tabs_in_bounding_box() {
+        let source = gradient(100, 100);
+        let pieces = jigsaw_pieces(&source, 2, 2, &JigsawOptions::default()).unwrap();
+        // Every piece has two inner edges, so its box grows past the 50x50 cell.
+        for piece in &pieces {
+            assert!(piece.image.width() > 50 && piece.image.height() > 50);
+        }
+    }
+
+    #[test]
+    fn test_same_seed_is_reproducible() {
+        let source = gradient(60, 60);
+        let options = JigsawOptions {
+            seed: 42,
+            ..JigsawOptions::default()
+        };
+        let first = jigsaw_pieces(&source, 3, 3, &options).unwrap();
+        let second = jigsaw_pieces(&source, 3, 3, &options).unwrap();
+        let other = jigsaw_pieces(&source, 3, 3, &JigsawOptions::default()).unwrap();
+        let masks = |pieces: &[Tile]| {
+            pieces
+                .iter()
+                .map(|p| p.mask.clone().unwrap().into_raw())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(masks(&first), masks(&second));
+        assert_ne!(masks(&first), masks(&other));
+    }
+
+    #[test]
+    fn test_tab_limits() {
+        let source = gradient(90, 90);
+        for (tab_size, jitter) in [(0.15, 0.0), (0.11, 0.04), (0.0, 0.15)] {
+            for seed in 0..20 {
+                let options = JigsawOptions {
+                    seed,
+                    tab_size,
+                    jitter,
+                };
+                let pieces = jigsaw_pieces(&source, 3, 3, &options).unwrap();
+                let rebuilt = join(&pieces, 90, 90).unwrap();
+                assert_eq!(rebuilt.to_rgba8(), source.to_rgba8());
+            }
+        }
+
+        for (tab_size, jitter) in [(-0.05, 0.0), (0.1, -0.01), (f64::NAN, 0.0), (0.12, 0.04)] {
+            let options = JigsawOptions {
+                seed: 0,
+                tab_size,
+                jitter,
+            };
+            assert!(jigsaw_pieces(&source, 3, 3, &options).is_err());
+        }
+    }
+}
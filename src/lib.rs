@@ -1,13 +1,65 @@
+pub mod atlas;
+pub mod dataset;
+pub mod diff;
+pub mod export;
+pub mod grid;
+pub mod gutter;
+pub mod incremental;
+pub mod inference;
+pub mod jigsaw;
+pub mod manifest;
+pub mod ninepatch;
+pub mod padding;
+pub mod pdf;
+pub mod poster;
+pub mod presets;
+pub mod sparse;
+pub mod sprite;
+pub mod stack;
+pub mod stats;
+pub mod stitch;
+pub mod store;
+pub mod stream;
+pub mod strip;
+pub mod tessellation;
 pub mod tile;
+pub mod tilemap;
 pub mod utils;
-
+pub mod videowall;
+
+pub use atlas::*;
+pub use dataset::*;
+pub use diff::*;
+pub use export::*;
+pub use grid::*;
+pub use gutter::*;
 use image::{DynamicImage, GenericImage, RgbaImage};
+pub use incremental::*;
+pub use inference::*;
+pub use jigsaw::*;
+pub use manifest::*;
+pub use ninepatch::*;
+pub use padding::*;
+pub use pdf::*;
+pub use poster::*;
+pub use presets::*;
+pub use sparse::*;
+pub use sprite::*;
+pub use stack::*;
+pub use stats::*;
 use std::{
     env, fs,
     path::{Path, PathBuf},
 };
+pub use stitch::*;
+pub use store::*;
+pub use stream::*;
+pub use strip::*;
+pub use tessellation::*;
 pub use tile::*;
+pub use tilemap::*;
 pub use utils::*;
+pub use videowall::*;
 
 const SPLIT_LIMIT: u32 = 99;
 
@@ -34,6 +86,12 @@ pub fn calc_columns_rows(n: u32) -> (u32, u32) {
 
 /// Calculate the combined size of tiles.
 ///
+/// Tiles are placed at their `coords`, so the combined size is the bounding box
+/// of all tiles and tiles may differ in size. Tiles without placement (all
+/// `coords` at the origin) are assumed to fill a `calc_columns_rows` grid in row
+/// order, each column as wide as its widest tile and each row as tall as its
+/// tallest one.
+///
 /// # Arguments
 ///
 /// * `tiles` - A slice of tiles, where each tile has an `image` with a `size`
@@ -42,12 +100,29 @@ pub fn calc_columns_rows(n: u32) -> (u32, u32) {
 ///
 /// A tuple `(width, height)` representing the combined size of the tiles.
 pub fn get_combined_size(tiles: &[tile::Tile]) -> (u32, u32) {
-    // TODO: Refactor calculating layout to avoid repetition.
+    if tiles
+        .iter()
+        .any(|tile| tile.content_rect().0 != 0 || tile.content_rect().1 != 0)
+    {
+        return tiles.iter().fold((0, 0), |(w, h), tile| {
+            let (x, y, width, height) = tile.content_rect();
+            (
+                w.max(x.max(0) as u32 + width),
+                h.max(y.max(0) as u32 + height),
+            )
+        });
+    }
+
     let (columns, rows) = calc_columns_rows(tiles.len() as u32);
-    (
-        tiles[0].image.width() * columns,
-        tiles[0].image.height() * rows,
-    )
+    let mut widths = vec![0; columns as usize];
+    let mut heights = vec![0; rows as usize];
+    for (i, tile) in tiles.iter().enumerate() {
+        let (column, row) = (i % columns as usize, i / columns as usize);
+        let (_, _, width, height) = tile.content_rect();
+        widths[column] = u32::max(widths[column], width);
+        heights[row] = u32::max(heights[row], height);
+    }
+    (widths.iter().sum(), heights.iter().sum())
 }
 
 /// Basic sanity checks prior to performing a split.
@@ -83,20 +158,43 @@ pub fn validate_image(number_tiles: u32) -> Result<u32, String> {
 ///
 /// Returns a `ValueError` if `col` or `row` is not an integer, is out of range, or if both are 1.
 pub fn validate_image_col_row(col: u32, row: u32) -> Result<(u32, u32), String> {
-    // Check if `col` and `row` are within the valid range
+    validate_grid(col, row)?;
+
+    // Check if both `col` and `row` are 1
+    if col == 1 && row == 1 {
+        return Err("There is nothing to divide. You asked for the entire image.".to_string());
+    }
+
+    Ok((col, row))
+}
+
+/// Checks that `col` and `row` are within the limits of `slice`, allowing a
+/// single tile for layouts where one page or slide is a valid result.
+pub(crate) fn validate_grid(col: u32, row: u32) -> Result<(u32, u32), String> {
     if col < 1 || row < 1 || col > SPLIT_LIMIT || row > SPLIT_LIMIT {
         return Err(format!(
             "Number of columns and rows must be between 1 and {} (you asked for rows: {} and col: {}).",
             SPLIT_LIMIT, row, col
         ));
     }
+    Ok((col, row))
+}
 
-    // Check if both `col` and `row` are 1
-    if col == 1 && row == 1 {
-        return Err("There is nothing to divide. You asked for the entire image.".to_string());
+/// Work out the grid used by `slice` from either a tile count or explicit
+/// columns and rows, validating whichever was given.
+pub(crate) fn resolve_grid(
+    number_tiles: Option<u32>,
+    col: Option<u32>,
+    row: Option<u32>,
+) -> Result<(u32, u32), String> {
+    if let Some(number_tiles) = number_tiles {
+        validate_image(number_tiles)?;
+        Ok(calc_columns_rows(number_tiles))
+    } else if let (Some(col), Some(row)) = (col, row) {
+        validate_image_col_row(col, row)
+    } else {
+        Err("Invalid tile configuration.".to_string())
     }
-
-    Ok((col, row))
 }
 
 /// Split an image into a specified number of tiles.
@@ -129,15 +227,7 @@ pub fn slice(
         .map_err(|_| format!("can not open image {}", full_path.to_str().unwrap()))?;
     let (im_w, im_h) = (im.width(), im.height());
 
-    let (columns, rows) = if let Some(number_tiles) = number_tiles {
-        validate_image(number_tiles)?;
-        calc_columns_rows(number_tiles)
-    } else if let (Some(col), Some(row)) = (col, row) {
-        validate_image_col_row(col, row)?;
-        (col, row)
-    } else {
-        return Err("Invalid tile configuration.".to_string());
-    };
+    let (columns, rows) = resolve_grid(number_tiles, col, row)?;
     let tile_w = im_w / columns;
     let tile_h = im_h / rows;
 
@@ -177,7 +267,8 @@ pub fn slice(
 }
 
 /// Write image files to disk. Create specified folder(s) if they
-/// don't exist. Returns a vector of `Tile` instances.
+/// don't exist. Returns a vector of `Tile` instances. Tiles marked empty by
+/// `mark_empty_tiles` are not written and keep no filename.
 ///
 /// # Arguments
 ///
@@ -198,6 +289,24 @@ pub fn save_tiles(
     prefix: &str,
     directory: Option<&Path>,
     format: &str,
+) -> Result<Vec<Tile>, Box<dyn std::error::Error>> {
+    save_tiles_named(tiles, prefix, directory, format, TileNaming::Grid)
+}
+
+/// Write image files to disk like `save_tiles`, naming them by `naming`.
+///
+/// With `TileNaming::Hash` the prefix is not used: tiles are named by the hash
+/// of their content, and a tile whose file already exists is not written again.
+///
+/// # Errors
+///
+/// Returns an error if saving any tile fails.
+pub fn save_tiles_named(
+    tiles: &mut [Tile],
+    prefix: &str,
+    directory: Option<&Path>,
+    format: &str,
+    naming: TileNaming,
 ) -> Result<Vec<Tile>, Box<dyn std::error::Error>> {
     let current_dir = env::current_dir()?;
     let dir = directory.unwrap_or(current_dir.as_path());
@@ -208,8 +317,17 @@ pub fn save_tiles(
     }
 
     for tile in tiles.iter_mut() {
-        let filename = tile.generate_filename(Some(dir), prefix, format, true);
-        tile.save(Some(filename), format)?;
+        // Empty tiles are recorded by their background colour instead
+        if tile.background.is_some() {
+            continue;
+        }
+        match naming {
+            TileNaming::Grid => {
+                let filename = tile.generate_filename(Some(dir), prefix, format, true);
+                tile.save(Some(filename), format)?;
+            }
+            TileNaming::Hash(algorithm) => tile.save_hashed(Some(dir), format, algorithm)?,
+        }
     }
 
     Ok(tiles.to_vec())
@@ -234,12 +352,11 @@ pub fn get_image_column_row(filename: &str) -> Result<(i32, i32), String> {
         .and_then(|s| s.to_str())
         .ok_or_else(|| "Invalid filename".to_string())?;
 
-    if let Some(last_part) = stem.get(stem.len().saturating_sub(5)..) {
-        if let Some((row, column)) = last_part.split_once('_') {
-            if let (Ok(row), Ok(column)) = (row.parse::<i32>(), column.parse::<i32>()) {
-                return Ok((column - 1, row - 1)); // Convert to zero-based indices
-            }
-        }
+    if let Some(last_part) = stem.get(stem.len().saturating_sub(5)..)
+        && let Some((row, column)) = last_part.split_once('_')
+        && let (Ok(row), Ok(column)) = (row.parse::<i32>(), column.parse::<i32>())
+    {
+        return Ok((column - 1, row - 1)); // Convert to zero-based indices
     }
 
     Err("Invalid filename format for extracting column and row".to_string())
@@ -294,6 +411,10 @@ pub fn open_images_in(directory: &Path) -> Result<Vec<Tile>, Box<dyn std::error:
 
 /// Combine tiles into a single image.
 ///
+/// Tiles carrying a `mask` are composited: only the pixels where the mask is
+/// non-zero are copied, so masked pieces with overlapping bounding boxes can be
+/// reassembled from their recorded `coords`.
+///
 /// # Arguments
 ///
 /// * `tiles` - A slice of `Tile` instances.
@@ -316,11 +437,29 @@ pub fn join(tiles: &[Tile], width: u32, height: u32) -> Result<DynamicImage, Str
     let mut target_image = DynamicImage::ImageRgba8(im);
     // Iterate over tiles and paste them into the combined image
     for tile in tiles {
-        let coords = (tile.coords.0 as u32, tile.coords.1 as u32);
-        let sub_image = tile.image.to_rgba8(); // Convert the tile image to RgbaImage
-        target_image
-            .copy_from(&sub_image, coords.0, coords.1)
-            .map_err(|_| "can not copy from tile")?
+        // Padding around `inner` is left out so padded tiles join like unpadded ones
+        let (x, y, _, _) = tile.content_rect();
+        let coords = (x as u32, y as u32);
+        let (ix, iy, iw, ih) =
+            tile.inner
+                .unwrap_or((0, 0, tile.image.width(), tile.image.height()));
+        let sub_image = tile.image.crop_imm(ix, iy, iw, ih).to_rgba8(); // Convert the tile image to RgbaImage
+        match &tile.mask {
+            Some(mask) => {
+                for (x, y, pixel) in sub_image.enumerate_pixels() {
+                    let (tx, ty) = (coords.0 + x, coords.1 + y);
+                    if mask.get_pixel(ix + x, iy + y).0[0] > 0
+                        && tx < combined_width
+                        && ty < combined_height
+                    {
+                        target_image.put_pixel(tx, ty, *pixel);
+                    }
+                }
+            }
+            None => target_image
+                .copy_from(&sub_image, coords.0, coords.1)
+                .map_err(|_| "can not copy from tile")?,
+        }
     }
 
     Ok(target_image)
@@ -382,6 +521,20 @@ mod tests {
         assert_eq!(combined_size, (100, 100));
     }
 
+    #[test]
+    fn test_get_combined_size_heterogeneous_tiles() {
+        let mut tiles = vec![
+            create_dummy_tile(30, 20, 1),
+            create_dummy_tile(70, 20, 2),
+            create_dummy_tile(30, 45, 3),
+            create_dummy_tile(70, 45, 4),
+        ];
+        tiles[1].coords = (30, 0);
+        tiles[2].coords = (0, 20);
+        tiles[3].coords = (30, 20);
+        assert_eq!(get_combined_size(&tiles), (100, 65));
+    }
+
     #[test]
     fn test_get_combined_size_large_number_of_tiles() {
         let tiles = (0..16)
@@ -390,4 +543,26 @@ mod tests {
         let combined_size = get_combined_size(&tiles);
         assert_eq!(combined_size, (40, 80));
     }
+
+    #[test]
+    fn test_save_tiles_named_by_hash() {
+        let dir = std::env::temp_dir().join("papercut_hash_naming_test");
+        let _ = fs::remove_dir_all(&dir);
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 4, |x, _| {
+            image::Rgba([(x / 4) as u8 * 200, 0, 0, 255])
+        }));
+        let mut tiles = slice_image_by_size(&image, 4, 2).unwrap();
+        let naming = TileNaming::Hash(HashAlgorithm::Sha256);
+        save_tiles_named(&mut tiles, "unused", Some(&dir), "png", naming).unwrap();
+
+        // Two distinct tiles, each repeated twice, give two files.
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        assert_eq!(tiles[0].filename, tiles[2].filename);
+        assert_ne!(tiles[0].filename, tiles[1].filename);
+        let expected = tiles[1]
+            .hashed_filename(Some(&dir), "png", HashAlgorithm::Sha256)
+            .unwrap();
+        assert_eq!(tiles[1].filename, Some(expected));
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
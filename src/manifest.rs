@@ -0,0 +1,240 @@
+//! The `manifest` module describes a saved tile set without holding any pixel data.
+//! A `Manifest` records the size of the source image and, for every tile, its number,
+//! grid position, pixel coordinates, size and the file it was written to.
+//!
+//! # Features
+//!
+//! - **Tile Set Description**: Build a manifest from a slice of `Tile`s.
+//! - **Persistence**: Save and load manifests as JSON.
+//! - **Reassembly**: Reopen the tiles listed in a manifest for `join`.
+//! - **Sparse Tile Sets**: Empty tiles are recorded by colour instead of saved.
+//!
+//! # Example Usage
+//!
+//! ```rust
+//! use papercut::{Manifest, Tile};
+//! use image::{DynamicImage, RgbaImage};
+//!
+//! let image = DynamicImage::ImageRgba8(RgbaImage::new(100, 100));
+//! let tiles = vec![Tile::new(image, 1, (1, 1), (0, 0), None)];
+//! let manifest = Manifest::from_tiles(&tiles);
+//! assert_eq!(manifest.size(), (100, 100));
+//! ```
+
+use image::{DynamicImage, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::tile::Tile;
+
+/// Metadata for a single tile in a `Manifest`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// The unique number assigned to the tile.
+    pub number: i32,
+    /// The row and column position of the tile in the grid.
+    pub position: (i32, i32),
+    /// The pixel coordinates of the tile in the original image.
+    pub coords: (i32, i32),
+    /// The width and height of the tile in pixels.
+    pub size: (u32, u32),
+    /// The file the tile was saved to, relative to the manifest when possible.
+    pub filename: Option<PathBuf>,
+    /// The content rectangle within a padded tile, see `Tile::inner`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inner: Option<(u32, u32, u32, u32)>,
+    /// Colour of an empty tile that was not saved, see `Tile::background`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background: Option<[u8; 4]>,
+    /// Content hash of a tile kept in a `TileStore`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+/// Describes a tile set: the size of the image it covers and where each tile lives.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Width of the combined image in pixels.
+    pub width: u32,
+    /// Height of the combined image in pixels.
+    pub height: u32,
+    /// The tiles making up the image.
+    pub tiles: Vec<ManifestEntry>,
+}
+
+impl ManifestEntry {
+    /// Creates an entry describing `tile`.
+    pub fn from_tile(tile: &Tile) -> Self {
+        ManifestEntry {
+            number: tile.number,
+            position: tile.position,
+            coords: tile.coords,
+            size: (tile.image.width(), tile.image.height()),
+            filename: tile.filename.clone(),
+            inner: tile.inner,
+            background: tile.background,
+            hash: None,
+        }
+    }
+
+    /// The tile's content in combined image coordinates, like `Tile::content_rect`.
+    pub fn content_rect(&self) -> (i32, i32, u32, u32) {
+        match self.inner {
+            Some((x, y, w, h)) => (self.coords.0 + x as i32, self.coords.1 + y as i32, w, h),
+            None => (self.coords.0, self.coords.1, self.size.0, self.size.1),
+        }
+    }
+}
+
+impl Manifest {
+    /// Builds a manifest from a slice of tiles. The image size is the bounding box
+    /// of all tiles.
+    pub fn from_tiles(tiles: &[Tile]) -> Self {
+        let entries: Vec<ManifestEntry> = tiles.iter().map(ManifestEntry::from_tile).collect();
+        let (width, height) = bounding_size(&entries);
+        Manifest {
+            width,
+            height,
+            tiles: entries,
+        }
+    }
+
+    /// Returns the size `(width, height)` of the combined image.
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Writes the manifest to `path` as JSON. Tile filenames inside the manifest's
+    /// directory are stored relative to it so the tile set can be moved as a whole.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the manifest can not be serialized or written.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut manifest = self.clone();
+        for entry in manifest.tiles.iter_mut() {
+            if let Some(filename) = &entry.filename
+                && let Ok(relative) = filename.strip_prefix(base)
+            {
+                entry.filename = Some(relative.to_path_buf());
+            }
+        }
+        fs::write(path, serde_json::to_string_pretty(&manifest)?)?;
+        Ok(())
+    }
+
+    /// Reads a manifest from `path`. Relative tile filenames are resolved against
+    /// the manifest's directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can not be read or is not a valid manifest.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut manifest: Manifest = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        for entry in manifest.tiles.iter_mut() {
+            if let Some(filename) = &entry.filename
+                && filename.is_relative()
+            {
+                entry.filename = Some(base.join(filename));
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Opens every tile listed in the manifest. Sparse entries become tiles filled
+    /// with their background colour.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an entry has no filename or its image fails to open.
+    pub fn open_tiles(&self) -> Result<Vec<Tile>, Box<dyn std::error::Error>> {
+        self.tiles
+            .iter()
+            .map(|entry| {
+                if let (None, Some(color)) = (&entry.filename, entry.background) {
+                    let image = RgbaImage::from_pixel(entry.size.0, entry.size.1, Rgba(color));
+                    let mut tile = Tile::new(
+                        DynamicImage::ImageRgba8(image),
+                        entry.number,
+                        entry.position,
+                        entry.coords,
+                        None,
+                    );
+                    tile.inner = entry.inner;
+                    tile.background = Some(color);
+                    return Ok(tile);
+                }
+                let filename = entry
+                    .filename
+                    .as_ref()
+                    .ok_or_else(|| format!("tile #{} has no filename", entry.number))?;
+                let image = image::open(filename)?;
+                let mut tile = Tile::new(
+                    image,
+                    entry.number,
+                    entry.position,
+                    entry.coords,
+                    Some(filename.clone()),
+                );
+                tile.inner = entry.inner;
+                Ok(tile)
+            })
+            .collect()
+    }
+}
+
+/// Size of the smallest image covering all `entries`.
+fn bounding_size(entries: &[ManifestEntry]) -> (u32, u32) {
+    entries.iter().fold((0, 0), |(w, h), entry| {
+        let (x, y, width, height) = entry.content_rect();
+        (
+            w.max(x.max(0) as u32 + width),
+            h.max(y.max(0) as u32 + height),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile_at(number: i32, coords: (i32, i32), size: (u32, u32)) -> Tile {
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(size.0, size.1));
+        Tile::new(image, number, (0, 0), coords, None)
+    }
+
+    #[test]
+    fn test_from_tiles_bounding_size() {
+        let tiles = vec![
+            tile_at(1, (0, 0), (10, 20)),
+            tile_at(2, (10, 0), (15, 20)),
+            tile_at(3, (0, 20), (10, 5)),
+        ];
+        let manifest = Manifest::from_tiles(&tiles);
+        assert_eq!(manifest.size(), (25, 25));
+        assert_eq!(manifest.tiles[1].size, (15, 20));
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let dir = Path::new("test_manifest_round_trip");
+        fs::create_dir_all(dir).unwrap();
+
+        let mut tiles = vec![tile_at(1, (0, 0), (4, 4))];
+        tiles[0].filename = Some(dir.join("tile_01_01.png"));
+        let manifest = Manifest::from_tiles(&tiles);
+        let path = dir.join("manifest.json");
+        manifest.save(&path).unwrap();
+
+        let stored = fs::read_to_string(&path).unwrap();
+        assert!(stored.contains("\"tile_01_01.png\""));
+
+        let loaded = Manifest::load(&path).unwrap();
+        assert_eq!(loaded, manifest);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
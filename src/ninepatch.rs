@@ -0,0 +1,325 @@
+//! The `ninepatch` module splits UI images into a 3x3 nine-patch. Corners keep
+//! their size, edges stretch along one axis and the center stretches along both,
+//! so a button or panel background can be drawn at any size.
+//!
+//! # Features
+//!
+//! - **Nine-Slice**: Cut an image at four insets into nine tiles with roles.
+//! - **Android Export**: Write `.9.png` files with the 1 pixel black marker border.
+//! - **Rendering**: Scale a nine-patch to an arbitrary size from its tiles.
+//!
+//! # Example Usage
+//!
+//! ```no_run
+//! use papercut::{slice_nine_patch, Insets};
+//! use std::path::Path;
+//!
+//! let patch = slice_nine_patch("button.png", Insets::uniform(12), false).unwrap();
+//! patch.render(300, 80).unwrap().save("button_wide.png").unwrap();
+//! patch.save_android(Path::new("button.9.png")).unwrap();
+//! ```
+
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::path::Path;
+
+use crate::tile::Tile;
+use crate::{get_basename, join, save_tiles};
+
+/// Distances in pixels from each side of the image to the stretchable region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Insets {
+    /// Width of the left column.
+    pub left: u32,
+    /// Height of the top row.
+    pub top: u32,
+    /// Width of the right column.
+    pub right: u32,
+    /// Height of the bottom row.
+    pub bottom: u32,
+}
+
+impl Insets {
+    /// The same inset on all four sides.
+    pub fn uniform(inset: u32) -> Self {
+        Insets {
+            left: inset,
+            top: inset,
+            right: inset,
+            bottom: inset,
+        }
+    }
+}
+
+/// How a nine-patch tile behaves when the patch is resized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchRole {
+    /// Never stretched.
+    Corner,
+    /// Top or bottom edge, stretched horizontally.
+    HorizontalEdge,
+    /// Left or right edge, stretched vertically.
+    VerticalEdge,
+    /// Stretched in both directions.
+    Center,
+}
+
+impl PatchRole {
+    /// The role of the tile at 1-based `(column, row)` in the 3x3 grid.
+    pub fn at(position: (i32, i32)) -> Self {
+        match (position.0 == 2, position.1 == 2) {
+            (false, false) => PatchRole::Corner,
+            (true, false) => PatchRole::HorizontalEdge,
+            (false, true) => PatchRole::VerticalEdge,
+            (true, true) => PatchRole::Center,
+        }
+    }
+}
+
+/// An image split into nine tiles, in row order.
+#[derive(Debug, Clone)]
+pub struct NinePatch {
+    /// The tiles, up to nine: a zero inset leaves out the corners and edge on
+    /// its side. Use `PatchRole::at(tile.position)` for their role.
+    pub tiles: Vec<Tile>,
+    /// The insets the image was cut at.
+    pub insets: Insets,
+    /// Width and height of the source image.
+    pub size: (u32, u32),
+}
+
+/// Split an image file into a nine-patch.
+///
+/// # Arguments
+///
+/// * `filename` - The filename of the image to split.
+/// * `insets` - Where to cut, measured from each side.
+/// * `save` - Whether or not to save the nine tiles to disk.
+pub fn slice_nine_patch(filename: &str, insets: Insets, save: bool) -> Result<NinePatch, String> {
+    let im =
+        image::open(Path::new(filename)).map_err(|_| format!("can not open image {}", filename))?;
+    let mut patch = nine_patch(&im, insets)?;
+
+    if save {
+        let prefix = get_basename(filename);
+        let directory = Path::new(filename)
+            .parent()
+            .unwrap_or_else(|| Path::new("."));
+        save_tiles(&mut patch.tiles, &prefix, Some(directory), "png")
+            .map_err(|_| "can not save tiles")?;
+    }
+
+    Ok(patch)
+}
+
+/// Split an in-memory image into a nine-patch.
+///
+/// Insets may be zero, e.g. `left` and `right` for a patch that only stretches
+/// vertically. The tiles of a zero-sized column or row are left out.
+///
+/// # Errors
+///
+/// Returns an error if the insets leave no center.
+pub fn nine_patch(image: &DynamicImage, insets: Insets) -> Result<NinePatch, String> {
+    let (width, height) = (image.width(), image.height());
+    if insets.left + insets.right >= width || insets.top + insets.bottom >= height {
+        return Err(format!(
+            "Insets {:?} leave no center in a {}x{} image.",
+            insets, width, height
+        ));
+    }
+    let xs = [0, insets.left, width - insets.right, width];
+    let ys = [0, insets.top, height - insets.bottom, height];
+    let mut tiles = Vec::with_capacity(9);
+    for row in 0..3 {
+        for column in 0..3 {
+            let (x, y) = (xs[column], ys[row]);
+            let (w, h) = (xs[column + 1] - x, ys[row + 1] - y);
+            if w == 0 || h == 0 {
+                continue;
+            }
+            tiles.push(Tile::new(
+                image.crop_imm(x, y, w, h),
+                tiles.len() as i32 + 1,
+                (column as i32 + 1, row as i32 + 1),
+                (x as i32, y as i32),
+                None,
+            ));
+        }
+    }
+    Ok(NinePatch {
+        tiles,
+        insets,
+        size: (width, height),
+    })
+}
+
+impl NinePatch {
+    /// Draw the nine-patch at `width x height`, stretching edges and center.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the size is smaller than the corners together.
+    pub fn render(&self, width: u32, height: u32) -> Result<DynamicImage, String> {
+        let Insets {
+            left,
+            top,
+            right,
+            bottom,
+        } = self.insets;
+        if width <= left + right || height <= top + bottom {
+            return Err(format!(
+                "A {}x{} nine-patch can not be drawn smaller than {}x{}.",
+                self.size.0,
+                self.size.1,
+                left + right + 1,
+                top + bottom + 1
+            ));
+        }
+        let xs = [0, left, width - right, width];
+        let ys = [0, top, height - bottom, height];
+
+        let tiles: Vec<Tile> = self
+            .tiles
+            .iter()
+            .map(|tile| {
+                let (column, row) = (tile.position.0 as usize, tile.position.1 as usize);
+                let (x, y) = (xs[column - 1], ys[row - 1]);
+                let (w, h) = (xs[column] - x, ys[row] - y);
+                let image = if (w, h) == (tile.image.width(), tile.image.height()) {
+                    tile.image.clone()
+                } else {
+                    tile.image.resize_exact(w, h, FilterType::Triangle)
+                };
+                Tile::new(
+                    image,
+                    tile.number,
+                    tile.position,
+                    (x as i32, y as i32),
+                    None,
+                )
+            })
+            .collect();
+        join(&tiles, width, height)
+    }
+
+    /// The image with Android's 1 pixel nine-patch border: black pixels on the top
+    /// and left edges mark the stretchable columns and rows.
+    pub fn to_android(&self) -> Result<DynamicImage, String> {
+        let (width, height) = self.size;
+        let image = join(&self.tiles, width, height)?;
+        let mut canvas = RgbaImage::new(width + 2, height + 2);
+        imageops::replace(&mut canvas, &image.to_rgba8(), 1, 1);
+        let black = Rgba([0, 0, 0, 255]);
+        for x in self.insets.left..width - self.insets.right {
+            canvas.put_pixel(x + 1, 0, black);
+        }
+        for y in self.insets.top..height - self.insets.bottom {
+            canvas.put_pixel(0, y + 1, black);
+        }
+        Ok(DynamicImage::ImageRgba8(canvas))
+    }
+
+    /// Save the image as an Android nine-patch. `path` should end in `.9.png`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the image can not be written.
+    pub fn save_android(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        self.to_android()?.save(path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+
+    /// A 10x8 frame: red 2px border, blue inside.
+    fn frame() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(10, 8, |x, y| {
+            if x < 2 || y < 2 || x >= 8 || y >= 6 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 255, 255])
+            }
+        }))
+    }
+
+    #[test]
+    fn test_nine_patch_roles() {
+        let patch = nine_patch(&frame(), Insets::uniform(2)).unwrap();
+        let roles: Vec<_> = patch
+            .tiles
+            .iter()
+            .map(|t| PatchRole::at(t.position))
+            .collect();
+        assert_eq!(roles[0], PatchRole::Corner);
+        assert_eq!(roles[1], PatchRole::HorizontalEdge);
+        assert_eq!(roles[3], PatchRole::VerticalEdge);
+        assert_eq!(roles[4], PatchRole::Center);
+        assert_eq!(roles[8], PatchRole::Corner);
+        assert!(nine_patch(&frame(), Insets::uniform(5)).is_err());
+    }
+
+    #[test]
+    fn test_render_keeps_border() {
+        let patch = nine_patch(&frame(), Insets::uniform(2)).unwrap();
+        let big = patch.render(40, 20).unwrap();
+        assert_eq!(big.dimensions(), (40, 20));
+        let expected = RgbaImage::from_fn(40, 20, |x, y| {
+            if x < 2 || y < 2 || x >= 38 || y >= 18 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 255, 255])
+            }
+        });
+        assert_eq!(big.to_rgba8(), expected);
+        assert_eq!(patch.render(10, 8).unwrap().to_rgba8(), frame().to_rgba8());
+        assert!(patch.render(4, 20).is_err());
+    }
+
+    #[test]
+    fn test_android_markers() {
+        let patch = nine_patch(&frame(), Insets::uniform(2)).unwrap();
+        let android = patch.to_android().unwrap();
+        assert_eq!(android.dimensions(), (12, 10));
+        let black = Rgba([0, 0, 0, 255]);
+        assert_eq!(android.get_pixel(3, 0), black);
+        assert_eq!(android.get_pixel(8, 0), black);
+        assert_eq!(android.get_pixel(9, 0), Rgba([0, 0, 0, 0]));
+        assert_eq!(android.get_pixel(0, 3), black);
+        assert_eq!(android.get_pixel(0, 7), Rgba([0, 0, 0, 0]));
+        assert_eq!(android.get_pixel(1, 1), Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_zero_insets() {
+        // Stretches vertically only: no left or right column.
+        let insets = Insets {
+            left: 0,
+            top: 2,
+            right: 0,
+            bottom: 2,
+        };
+        let patch = nine_patch(&frame(), insets).unwrap();
+        let positions: Vec<_> = patch.tiles.iter().map(|t| t.position).collect();
+        assert_eq!(positions, vec![(2, 1), (2, 2), (2, 3)]);
+        assert_eq!(patch.tiles[1].image.dimensions(), (10, 4));
+
+        let tall = patch.render(10, 20).unwrap();
+        assert_eq!(tall.get_pixel(5, 1), Rgba([255, 0, 0, 255]));
+        assert_eq!(tall.get_pixel(1, 10), Rgba([255, 0, 0, 255]));
+        assert_eq!(tall.get_pixel(5, 10), Rgba([0, 0, 255, 255]));
+        assert_eq!(tall.get_pixel(5, 18), Rgba([255, 0, 0, 255]));
+        assert_eq!(
+            patch.to_android().unwrap().get_pixel(1, 0),
+            Rgba([0, 0, 0, 255])
+        );
+
+        let none = nine_patch(&frame(), Insets::uniform(0)).unwrap();
+        assert_eq!(none.tiles.len(), 1);
+        assert_eq!(PatchRole::at(none.tiles[0].position), PatchRole::Center);
+    }
+}
@@ -0,0 +1,133 @@
+//! The `padding` module adds a border around tiles so that bilinear texture
+//! sampling near a tile's edge does not bleed in unrelated pixels. The original
+//! content is recorded in `Tile::inner`, which engines can use as UV bounds and
+//! `join` uses to leave the border out.
+//!
+//! # Features
+//!
+//! - **Extrusion**: Repeat each tile's outermost pixels outwards.
+//! - **Source Bleed**: Copy the real neighbouring pixels from the source image,
+//!   extruding only where the tile touches the image border.
+//!
+//! # Example Usage
+//!
+//! ```no_run
+//! use papercut::{pad_tiles, slice};
+//!
+//! let source = image::open("terrain.png").unwrap();
+//! let tiles = slice("terrain.png", Some(16), None, None, false).unwrap();
+//! let padded = pad_tiles(&tiles, 2, Some(&source));
+//! assert_eq!(padded[0].inner.unwrap().0, 2);
+//! ```
+
+use image::{DynamicImage, GenericImageView, GrayImage, Luma, RgbaImage};
+
+use crate::tile::Tile;
+
+/// Add a border of `amount` pixels around every tile.
+///
+/// Without a `source` the border repeats the tile's own edge pixels. With the
+/// image the tiles were cut from, the border holds the pixels surrounding the
+/// tile in that image, and the image's edge pixels are repeated where the tile
+/// touches its border.
+///
+/// The padded tile's `coords` move up and left by `amount`, and `inner` records
+/// where the original content sits, so `join` still rebuilds the source. Masks
+/// grow with transparent padding.
+pub fn pad_tiles(tiles: &[Tile], amount: u32, source: Option<&DynamicImage>) -> Vec<Tile> {
+    tiles
+        .iter()
+        .map(|tile| pad_tile(tile, amount, source))
+        .collect()
+}
+
+/// Add a border of `amount` pixels around a single tile. See `pad_tiles`.
+pub fn pad_tile(tile: &Tile, amount: u32, source: Option<&DynamicImage>) -> Tile {
+    // Pad around an existing content rectangle rather than around earlier padding
+    let (cx, cy, width, height) = tile.content_rect();
+    let (ix, iy, _, _) = tile.inner.unwrap_or((0, 0, width, height));
+    let content = tile.image.crop_imm(ix, iy, width, height).to_rgba8();
+    let pad = amount as i64;
+
+    let padded = RgbaImage::from_fn(width + 2 * amount, height + 2 * amount, |x, y| {
+        let (dx, dy) = (x as i64 - pad, y as i64 - pad);
+        match source {
+            Some(image) => {
+                let sx = (cx as i64 + dx).clamp(0, image.width() as i64 - 1);
+                let sy = (cy as i64 + dy).clamp(0, image.height() as i64 - 1);
+                image.get_pixel(sx as u32, sy as u32)
+            }
+            None => {
+                let tx = dx.clamp(0, width as i64 - 1);
+                let ty = dy.clamp(0, height as i64 - 1);
+                *content.get_pixel(tx as u32, ty as u32)
+            }
+        }
+    });
+
+    let mut padded_tile = Tile::new(
+        DynamicImage::ImageRgba8(padded),
+        tile.number,
+        tile.position,
+        (cx - amount as i32, cy - amount as i32),
+        tile.filename.clone(),
+    );
+    padded_tile.inner = Some((amount, amount, width, height));
+    padded_tile.mask = tile.mask.as_ref().map(|mask| {
+        GrayImage::from_fn(width + 2 * amount, height + 2 * amount, |x, y| {
+            let inside = x >= amount && y >= amount && x < amount + width && y < amount + height;
+            if inside {
+                *mask.get_pixel(ix + x - amount, iy + y - amount)
+            } else {
+                Luma([0])
+            }
+        })
+    });
+    padded_tile
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cuts, get_combined_size, join, slice_image_with_cuts};
+    use image::Rgba;
+
+    fn source() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 6, |x, y| {
+            Rgba([x as u8 * 30, y as u8 * 40, 0, 255])
+        }))
+    }
+
+    #[test]
+    fn test_extrude_repeats_edges() {
+        let tiles = slice_image_with_cuts(
+            &source(),
+            &Cuts::Positions(vec![4]),
+            &Cuts::Positions(vec![]),
+        )
+        .unwrap();
+        let padded = pad_tiles(&tiles, 2, None);
+        let right = &padded[1];
+        assert_eq!(right.coords, (2, -2));
+        assert_eq!(right.inner, Some((2, 2, 4, 6)));
+        assert_eq!((right.image.width(), right.image.height()), (8, 10));
+        // Left border of the right tile repeats its first column, x = 4.
+        assert_eq!(right.image.get_pixel(0, 0), source().get_pixel(4, 0));
+        assert_eq!(right.image.get_pixel(7, 9), source().get_pixel(7, 5));
+    }
+
+    #[test]
+    fn test_source_bleed_and_join() {
+        let tiles = slice_image_with_cuts(
+            &source(),
+            &Cuts::Positions(vec![4]),
+            &Cuts::Positions(vec![]),
+        )
+        .unwrap();
+        let padded = pad_tiles(&tiles, 1, Some(&source()));
+        // Left border of the right tile holds the real neighbour, x = 3.
+        assert_eq!(padded[1].image.get_pixel(0, 3), source().get_pixel(3, 2));
+        assert_eq!(get_combined_size(&padded), (8, 6));
+        assert_eq!(join(&padded, 0, 0).unwrap().to_rgba8(), source().to_rgba8());
+    }
+}
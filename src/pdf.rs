@@ -0,0 +1,614 @@
+//! The `pdf` module is a small pure-Rust PDF writer for raster pages. It knows
+//! just enough of the format to place images on pages: a catalog, a page tree,
+//! JPEG or Flate compressed image XObjects, Helvetica captions and content
+//! streams.
+//!
+//! # Features
+//!
+//! - **Tile Documents**: One tile per page, or several tiles laid out N-up on
+//!   each page with optional captions.
+//! - **Image Encoding**: JPEG, embedding tiles read from JPEG files unchanged,
+//!   or lossless Flate.
+//! - **Layout**: Paper size, orientation, margins, print resolution and page
+//!   order.
+//!
+//! # Example Usage
+//!
+//! ```rust
+//! use papercut::PdfWriter;
+//! use image::{DynamicImage, RgbImage};
+//!
+//! let mut pdf = PdfWriter::new();
+//! let page = DynamicImage::ImageRgb8(RgbImage::new(100, 50));
+//! pdf.add_image_page(&page, 200.0, 100.0).unwrap();
+//! assert!(pdf.to_bytes().starts_with(b"%PDF-1.4"));
+//! ```
+//!
+//! ```no_run
+//! use papercut::{save_tiles_pdf, slice, PaperSize, PdfOptions};
+//! use std::path::Path;
+//!
+//! let tiles = slice("image.png", Some(12), None, None, false).unwrap();
+//! let options = PdfOptions {
+//!     paper: Some(PaperSize::A4),
+//!     columns: 3,
+//!     rows: 4,
+//!     captions: true,
+//!     ..PdfOptions::default()
+//! };
+//! save_tiles_pdf(&tiles, &options, Path::new("contact_sheet.pdf")).unwrap();
+//! ```
+
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::{ColorType, DynamicImage, ImageDecoder, ImageReader, RgbImage};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::poster::{Orientation, PaperSize};
+use crate::tile::Tile;
+
+/// Points per inch, the PDF unit of length.
+pub const POINTS_PER_INCH: f64 = 72.0;
+
+/// Caption font size in points.
+const CAPTION_SIZE: f64 = 8.0;
+/// Space between tiles on an N-up page, in points.
+const GUTTER: f64 = 8.0;
+
+/// How images are compressed inside the PDF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdfImageEncoding {
+    /// JPEG at the given quality from 1 to 100. Tiles whose `filename` is a
+    /// JPEG file of the same size are embedded as-is without re-encoding.
+    Jpeg(u8),
+    /// Lossless Flate compression.
+    Flate,
+}
+
+/// The order tiles are placed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageOrder {
+    /// Left to right, then top to bottom.
+    RowMajor,
+    /// Top to bottom, then left to right.
+    ColumnMajor,
+}
+
+/// Options for `tiles_to_pdf`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PdfOptions {
+    /// Page size, or `None` to size each page to fit its tiles.
+    pub paper: Option<PaperSize>,
+    /// Orientation of `paper`.
+    pub orientation: Orientation,
+    /// Blank border on every side of the page, in millimetres.
+    pub margin_mm: f64,
+    /// Resolution tiles are printed at. Tiles that do not fit their cell are
+    /// scaled down.
+    pub dpi: f64,
+    /// Tiles across each page.
+    pub columns: u32,
+    /// Tiles down each page.
+    pub rows: u32,
+    /// Whether to print each tile's name below it.
+    pub captions: bool,
+    /// The order tiles fill the pages in.
+    pub order: PageOrder,
+    /// How tile images are compressed.
+    pub encoding: PdfImageEncoding,
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        PdfOptions {
+            paper: None,
+            orientation: Orientation::Portrait,
+            margin_mm: 10.0,
+            dpi: 150.0,
+            columns: 1,
+            rows: 1,
+            captions: false,
+            order: PageOrder::RowMajor,
+            encoding: PdfImageEncoding::Flate,
+        }
+    }
+}
+
+/// Lay out tiles on the pages of a new PDF document.
+///
+/// Tiles are sorted by `position` in `order` and placed `columns x rows` per
+/// page, each centered in its cell at `dpi`.
+///
+/// # Errors
+///
+/// Returns an error if there are no tiles, the options leave no room for them,
+/// or an image can not be encoded.
+pub fn tiles_to_pdf(
+    tiles: &[Tile],
+    options: &PdfOptions,
+) -> Result<PdfWriter, Box<dyn std::error::Error>> {
+    if tiles.is_empty() {
+        return Err("No tiles to write.".into());
+    }
+    if options.columns == 0 || options.rows == 0 {
+        return Err("PDF pages need at least one column and row.".into());
+    }
+    if !(options.dpi.is_finite() && options.dpi > 0.0) || options.margin_mm < 0.0 {
+        return Err("PDF resolution must be positive and margins not negative.".into());
+    }
+
+    let mut ordered: Vec<&Tile> = tiles.iter().collect();
+    match options.order {
+        PageOrder::RowMajor => ordered.sort_by_key(|t| (t.position.1, t.position.0)),
+        PageOrder::ColumnMajor => ordered.sort_by_key(|t| (t.position.0, t.position.1)),
+    }
+
+    let points = |px: u32| px as f64 / options.dpi * POINTS_PER_INCH;
+    let margin = options.margin_mm / 25.4 * POINTS_PER_INCH;
+    let caption = if options.captions {
+        CAPTION_SIZE + 4.0
+    } else {
+        0.0
+    };
+    let (columns, rows) = (options.columns as f64, options.rows as f64);
+
+    // Cell size for the image area, and the page size around the cells.
+    let (cell, page) = match options.paper {
+        Some(paper) => {
+            let (w, h) = paper.dimensions_mm();
+            let (w, h) = match options.orientation {
+                Orientation::Portrait => (w, h),
+                Orientation::Landscape => (h, w),
+            };
+            let page = (w / 25.4 * POINTS_PER_INCH, h / 25.4 * POINTS_PER_INCH);
+            let cell = (
+                (page.0 - 2.0 * margin - (columns - 1.0) * GUTTER) / columns,
+                (page.1 - 2.0 * margin - (rows - 1.0) * GUTTER) / rows - caption,
+            );
+            (cell, page)
+        }
+        None => {
+            let cell = ordered.iter().fold((0.0f64, 0.0f64), |(w, h), t| {
+                (
+                    w.max(points(t.image.width())),
+                    h.max(points(t.image.height())),
+                )
+            });
+            let page = (
+                columns * cell.0 + (columns - 1.0) * GUTTER + 2.0 * margin,
+                rows * (cell.1 + caption) + (rows - 1.0) * GUTTER + 2.0 * margin,
+            );
+            (cell, page)
+        }
+    };
+    if cell.0 < 1.0 || cell.1 < 1.0 {
+        return Err("Margins and layout leave no room for tiles on the page.".into());
+    }
+
+    let mut pdf = PdfWriter::new();
+    let per_page = (options.columns * options.rows) as usize;
+    for chunk in ordered.chunks(per_page) {
+        let mut content = String::new();
+        let mut images = Vec::with_capacity(chunk.len());
+        for (i, tile) in chunk.iter().enumerate() {
+            let (column, row) = (
+                (i as u32 % options.columns) as f64,
+                (i as u32 / options.columns) as f64,
+            );
+            let (w, h) = (points(tile.image.width()), points(tile.image.height()));
+            let fit = (cell.0 / w).min(cell.1 / h).min(1.0);
+            let (w, h) = (w * fit, h * fit);
+            let left = margin + column * (cell.0 + GUTTER);
+            let top = margin + row * (cell.1 + caption + GUTTER);
+            let x = left + (cell.0 - w) / 2.0;
+            let y = page.1 - top - h;
+
+            images.push(pdf.add_tile_image(tile, options.encoding)?);
+            content.push_str(&format!(
+                "q {} 0 0 {} {} {} cm /Im{} Do Q\n",
+                num(w),
+                num(h),
+                num(x),
+                num(y),
+                i
+            ));
+            if options.captions {
+                let name = tile
+                    .basename()
+                    .unwrap_or_else(|| format!("Tile {}", tile.number));
+                content.push_str(&format!(
+                    "BT /F1 {} Tf {} {} Td ({}) Tj ET\n",
+                    num(CAPTION_SIZE),
+                    num(x),
+                    num(y - CAPTION_SIZE - 2.0),
+                    pdf_string(&name)
+                ));
+            }
+        }
+        pdf.add_page(page, &content, &images, options.captions);
+    }
+    Ok(pdf)
+}
+
+/// Write tiles to a PDF file. See `tiles_to_pdf`.
+///
+/// # Errors
+///
+/// Returns an error if the document can not be built or written.
+pub fn save_tiles_pdf(
+    tiles: &[Tile],
+    options: &PdfOptions,
+    path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    tiles_to_pdf(tiles, options)?.save(path)
+}
+
+/// Builds a PDF document page by page.
+#[derive(Debug, Clone)]
+pub struct PdfWriter {
+    /// Object bodies by id - 1. `None` for ids reserved but not yet written.
+    objects: Vec<Option<Vec<u8>>>,
+    /// Ids of the page objects, in order.
+    pages: Vec<usize>,
+    /// Id of the caption font, added with the first captioned page.
+    font: Option<usize>,
+}
+
+impl Default for PdfWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PdfWriter {
+    /// Creates an empty document.
+    pub fn new() -> Self {
+        let mut writer = PdfWriter {
+            objects: Vec::new(),
+            pages: Vec::new(),
+            font: None,
+        };
+        // Object 1 is the catalog and object 2 the page tree, written by `to_bytes`.
+        writer.reserve();
+        writer.reserve();
+        writer
+    }
+
+    /// Number of pages added so far.
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Adds a page of `width x height` points filled entirely by `image`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the image data can not be compressed.
+    pub fn add_image_page(
+        &mut self,
+        image: &DynamicImage,
+        width: f64,
+        height: f64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let image_id = self.add_image(image)?;
+        let content = format!("q {} 0 0 {} 0 0 cm /Im0 Do Q\n", num(width), num(height));
+        self.add_page((width, height), &content, &[image_id], false);
+        Ok(())
+    }
+
+    /// Serializes the document.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let kids: Vec<String> = self.pages.iter().map(|id| format!("{} 0 R", id)).collect();
+        let mut objects = self.objects.clone();
+        objects[0] = Some(b"<< /Type /Catalog /Pages 2 0 R >>".to_vec());
+        objects[1] = Some(
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                kids.join(" "),
+                self.pages.len()
+            )
+            .into_bytes(),
+        );
+
+        let mut out = b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec();
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (i, body) in objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+            out.extend_from_slice(body.as_deref().unwrap_or(b"null"));
+            out.extend_from_slice(b"\nendobj\n");
+        }
+        let xref = out.len();
+        out.extend_from_slice(
+            format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes(),
+        );
+        for offset in offsets {
+            out.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        out.extend_from_slice(
+            format!(
+                "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+                objects.len() + 1,
+                xref
+            )
+            .as_bytes(),
+        );
+        out
+    }
+
+    /// Writes the document to `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can not be written.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    /// Reserves an object id.
+    fn reserve(&mut self) -> usize {
+        self.objects.push(None);
+        self.objects.len()
+    }
+
+    /// Adds an object and returns its id.
+    fn add_object(&mut self, body: Vec<u8>) -> usize {
+        self.objects.push(Some(body));
+        self.objects.len()
+    }
+
+    /// Adds a stream object with the given extra dictionary entries.
+    fn add_stream(&mut self, dictionary: &str, data: &[u8]) -> usize {
+        let mut body =
+            format!("<< {} /Length {} >>\nstream\n", dictionary, data.len()).into_bytes();
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\nendstream");
+        self.add_object(body)
+    }
+
+    /// Adds `image` as a Flate compressed RGB image XObject.
+    fn add_image(&mut self, image: &DynamicImage) -> Result<usize, Box<dyn std::error::Error>> {
+        let rgb = over_white(image);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(rgb.as_raw())?;
+        let data = encoder.finish()?;
+        Ok(self.add_image_stream(rgb.dimensions(), "DeviceRGB", "FlateDecode", &data))
+    }
+
+    /// Adds `image` as a DCT (JPEG) compressed RGB image XObject.
+    fn add_jpeg(
+        &mut self,
+        image: &DynamicImage,
+        quality: u8,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let rgb = over_white(image);
+        let mut data = Vec::new();
+        JpegEncoder::new_with_quality(&mut data, quality.clamp(1, 100)).encode_image(&rgb)?;
+        Ok(self.add_image_stream(rgb.dimensions(), "DeviceRGB", "DCTDecode", &data))
+    }
+
+    /// Adds a tile's image with `encoding`, passing JPEG files through unchanged.
+    fn add_tile_image(
+        &mut self,
+        tile: &Tile,
+        encoding: PdfImageEncoding,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        match encoding {
+            PdfImageEncoding::Flate => self.add_image(&tile.image),
+            PdfImageEncoding::Jpeg(quality) => match jpeg_source(tile) {
+                Some((size, color_space, data)) => {
+                    Ok(self.add_image_stream(size, color_space, "DCTDecode", &data))
+                }
+                None => self.add_jpeg(&tile.image, quality),
+            },
+        }
+    }
+
+    fn add_image_stream(
+        &mut self,
+        size: (u32, u32),
+        color_space: &str,
+        filter: &str,
+        data: &[u8],
+    ) -> usize {
+        self.add_stream(
+            &format!(
+                "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /{} /BitsPerComponent 8 /Filter /{}",
+                size.0, size.1, color_space, filter
+            ),
+            data,
+        )
+    }
+
+    /// Adds a page whose content stream refers to `images` as `/Im0`, `/Im1`, ...
+    /// and, with `font`, to Helvetica as `/F1`.
+    fn add_page(&mut self, size: (f64, f64), content: &str, images: &[usize], font: bool) {
+        let content_id = self.add_stream("", content.as_bytes());
+        let xobjects: Vec<String> = images
+            .iter()
+            .enumerate()
+            .map(|(i, id)| format!("/Im{} {} 0 R", i, id))
+            .collect();
+        let fonts = if font {
+            let id = match self.font {
+                Some(id) => id,
+                None => {
+                    let id = self.add_object(
+                        b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+                            .to_vec(),
+                    );
+                    self.font = Some(id);
+                    id
+                }
+            };
+            format!(" /Font << /F1 {} 0 R >>", id)
+        } else {
+            String::new()
+        };
+        let page = format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /XObject << {} >>{} >> /Contents {} 0 R >>",
+            num(size.0),
+            num(size.1),
+            xobjects.join(" "),
+            fonts,
+            content_id
+        );
+        let id = self.add_object(page.into_bytes());
+        self.pages.push(id);
+    }
+}
+
+/// The image as RGB with transparent pixels composited over white.
+fn over_white(image: &DynamicImage) -> RgbImage {
+    let rgba = image.to_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let blend = |c: u8| ((c as u32 * a as u32 + 255 * (255 - a as u32)) / 255) as u8;
+        image::Rgb([blend(r), blend(g), blend(b)])
+    })
+}
+
+/// The JPEG file behind a tile as `(size, color space, bytes)`, if the tile was
+/// read from or saved to a grayscale or RGB JPEG of the same size.
+fn jpeg_source(tile: &Tile) -> Option<((u32, u32), &'static str, Vec<u8>)> {
+    let path = tile.filename.as_ref()?;
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    if extension != "jpg" && extension != "jpeg" {
+        return None;
+    }
+    let decoder = ImageReader::open(path).ok()?.into_decoder().ok()?;
+    let size = decoder.dimensions();
+    if size != (tile.image.width(), tile.image.height()) {
+        return None;
+    }
+    let color_space = match decoder.color_type() {
+        ColorType::L8 => "DeviceGray",
+        ColorType::Rgb8 => "DeviceRGB",
+        _ => return None,
+    };
+    Some((size, color_space, fs::read(path).ok()?))
+}
+
+/// Escapes text for a PDF string literal. Characters outside ASCII become `?`.
+fn pdf_string(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '(' | ')' | '\\' => format!("\\{}", c),
+            ' '..='~' => c.to_string(),
+            _ => "?".to_string(),
+        })
+        .collect()
+}
+
+/// Formats a length with at most two decimals, as PDF readers expect no exponents.
+fn num(value: f64) -> String {
+    let text = format!("{:.2}", value);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    #[test]
+    fn test_document_structure() {
+        let mut pdf = PdfWriter::new();
+        let image = DynamicImage::ImageRgb8(RgbImage::new(4, 4));
+        pdf.add_image_page(&image, 595.28, 841.89).unwrap();
+        pdf.add_image_page(&image, 100.0, 100.0).unwrap();
+        assert_eq!(pdf.page_count(), 2);
+
+        let bytes = pdf.to_bytes();
+        let text = String::from_utf8_lossy(&bytes);
+        assert!(text.contains("/Count 2"));
+        assert!(text.contains("/MediaBox [0 0 595.28 841.89]"));
+        assert!(text.ends_with("%%EOF\n"));
+
+        // Every xref entry points at the start of its object.
+        let xref = text.rfind("xref\n").unwrap();
+        let entries: Vec<&str> = text[xref..]
+            .lines()
+            .skip(3)
+            .take_while(|l| l.ends_with(" n "))
+            .collect();
+        for (i, entry) in entries.iter().enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(bytes[offset..].starts_with(format!("{} 0 obj", i + 1).as_bytes()));
+        }
+    }
+
+    fn tiles(count: i32) -> Vec<Tile> {
+        (0..count)
+            .map(|i| {
+                let image = DynamicImage::ImageRgb8(RgbImage::new(30, 20));
+                Tile::new(image, i + 1, (i % 3 + 1, i / 3 + 1), (0, 0), None)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_n_up_layout_and_captions() {
+        let options = PdfOptions {
+            paper: Some(PaperSize::A4),
+            orientation: Orientation::Landscape,
+            columns: 2,
+            rows: 2,
+            captions: true,
+            order: PageOrder::ColumnMajor,
+            ..PdfOptions::default()
+        };
+        let pdf = tiles_to_pdf(&tiles(6), &options).unwrap();
+        assert_eq!(pdf.page_count(), 2);
+        let text = String::from_utf8_lossy(&pdf.to_bytes()).to_string();
+        assert!(text.contains("/MediaBox [0 0 841.89 595.28]"));
+        assert_eq!(text.matches("/BaseFont /Helvetica").count(), 1);
+        // Column-major order puts the tile at (1, 2) second.
+        let first = text.find("(Tile 1) Tj").unwrap();
+        let second = text.find("(Tile 4) Tj").unwrap();
+        assert!(first < second && second < text.find("(Tile 2) Tj").unwrap());
+
+        // Without paper, one 30x20 px tile at 72 dpi fills the page within the margins.
+        let single = PdfOptions {
+            dpi: 72.0,
+            margin_mm: 0.0,
+            ..PdfOptions::default()
+        };
+        let text = String::from_utf8_lossy(&tiles_to_pdf(&tiles(1), &single).unwrap().to_bytes())
+            .to_string();
+        assert!(text.contains("/MediaBox [0 0 30 20]"));
+        assert!(tiles_to_pdf(&[], &single).is_err());
+    }
+
+    #[test]
+    fn test_jpeg_passthrough() {
+        let path = std::env::temp_dir().join("papercut_pdf_passthrough.jpg");
+        let mut tile = tiles(1).remove(0);
+        tile.save(Some(path.clone()), "jpg").unwrap();
+        let original = fs::read(&path).unwrap();
+
+        let options = PdfOptions {
+            encoding: PdfImageEncoding::Jpeg(80),
+            ..PdfOptions::default()
+        };
+        let bytes = tiles_to_pdf(std::slice::from_ref(&tile), &options)
+            .unwrap()
+            .to_bytes();
+        fs::remove_file(&path).unwrap();
+        assert!(
+            bytes
+                .windows(original.len())
+                .any(|w| w == original.as_slice())
+        );
+        assert!(String::from_utf8_lossy(&bytes).contains("/Filter /DCTDecode"));
+
+        // Without a file the tile is encoded as a new JPEG.
+        tile.filename = None;
+        let bytes = tiles_to_pdf(&[tile], &options).unwrap().to_bytes();
+        assert!(String::from_utf8_lossy(&bytes).contains("/Filter /DCTDecode"));
+    }
+}
@@ -0,0 +1,437 @@
+//! The `poster` module splits an image into printable pages for a large poster.
+//! Given the poster's physical size, a paper size and the printer resolution, it
+//! works out the page grid, adds a glue overlap between neighbouring pages and
+//! keeps content inside printer-safe margins.
+//!
+//! # Features
+//!
+//! - **Paper Sizes**: ISO A3 to A5, US Letter and Legal or a custom size, in
+//!   portrait or landscape.
+//! - **Overlap**: Neighbouring pages repeat a strip of the poster to glue over.
+//! - **Crop Marks and Labels**: Marks at the corners of the printed area and a
+//!   `R<row>/C<column>` label in the bottom margin.
+//! - **Output**: Page images or a multi-page PDF.
+//!
+//! # Example Usage
+//!
+//! ```no_run
+//! use papercut::{save_poster_pdf, slice_poster, PaperSize, PosterOptions};
+//! use std::path::Path;
+//!
+//! let options = PosterOptions {
+//!     width_mm: 841.0,
+//!     paper: PaperSize::A4,
+//!     ..PosterOptions::default()
+//! };
+//! let pages = slice_poster("poster.png", &options, false).unwrap();
+//! save_poster_pdf(&pages, &options, Path::new("poster.pdf")).unwrap();
+//! ```
+
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::path::Path;
+
+use crate::pdf::{POINTS_PER_INCH, PdfWriter};
+use crate::tile::Tile;
+use crate::{get_basename, save_tiles, validate_grid};
+
+const MM_PER_INCH: f64 = 25.4;
+
+/// Standard paper sizes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PaperSize {
+    A3,
+    A4,
+    A5,
+    Letter,
+    Legal,
+    /// Width and height in millimetres, in portrait orientation.
+    Custom(f64, f64),
+}
+
+impl PaperSize {
+    /// Portrait width and height in millimetres.
+    pub fn dimensions_mm(&self) -> (f64, f64) {
+        match *self {
+            PaperSize::A3 => (297.0, 420.0),
+            PaperSize::A4 => (210.0, 297.0),
+            PaperSize::A5 => (148.0, 210.0),
+            PaperSize::Letter => (215.9, 279.4),
+            PaperSize::Legal => (215.9, 355.6),
+            PaperSize::Custom(width, height) => (width, height),
+        }
+    }
+}
+
+/// Page orientation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    Portrait,
+    Landscape,
+}
+
+/// Options for `poster_pages`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PosterOptions {
+    /// Width of the finished poster in millimetres.
+    pub width_mm: f64,
+    /// Height of the finished poster in millimetres, or `0.0` to follow the
+    /// image's aspect ratio. When both are set the image is fitted inside.
+    pub height_mm: f64,
+    /// The paper the printer takes.
+    pub paper: PaperSize,
+    /// Orientation of each sheet.
+    pub orientation: Orientation,
+    /// Printer resolution in dots per inch.
+    pub dpi: f64,
+    /// Width of the strip repeated on neighbouring pages, in millimetres.
+    pub overlap_mm: f64,
+    /// Unprintable border on every side of the sheet, in millimetres.
+    pub margin_mm: f64,
+    /// Whether to draw crop marks.
+    pub crop_marks: bool,
+    /// Whether to print the row and column label.
+    pub labels: bool,
+}
+
+impl Default for PosterOptions {
+    fn default() -> Self {
+        PosterOptions {
+            width_mm: 594.0,
+            height_mm: 0.0,
+            paper: PaperSize::A4,
+            orientation: Orientation::Portrait,
+            dpi: 150.0,
+            overlap_mm: 10.0,
+            margin_mm: 8.0,
+            crop_marks: true,
+            labels: true,
+        }
+    }
+}
+
+impl PosterOptions {
+    /// Sheet width and height in millimetres after orientation.
+    pub fn sheet_mm(&self) -> (f64, f64) {
+        let (w, h) = self.paper.dimensions_mm();
+        match self.orientation {
+            Orientation::Portrait => (w, h),
+            Orientation::Landscape => (h, w),
+        }
+    }
+
+    fn px(&self, mm: f64) -> u32 {
+        (mm / MM_PER_INCH * self.dpi).round().max(0.0) as u32
+    }
+}
+
+/// Split an image file into poster pages.
+///
+/// # Arguments
+///
+/// * `filename` - The filename of the image to print.
+/// * `options` - Poster size, paper and print settings.
+/// * `save` - Whether or not to save the pages to disk.
+///
+/// # Returns
+///
+/// A vector of pages as `Tile` instances in row order.
+pub fn slice_poster(
+    filename: &str,
+    options: &PosterOptions,
+    save: bool,
+) -> Result<Vec<Tile>, String> {
+    let im =
+        image::open(Path::new(filename)).map_err(|_| format!("can not open image {}", filename))?;
+    let mut tiles = poster_pages(&im, options)?;
+
+    if save {
+        let prefix = get_basename(filename);
+        let directory = Path::new(filename)
+            .parent()
+            .unwrap_or_else(|| Path::new("."));
+        save_tiles(&mut tiles, &prefix, Some(directory), "png")
+            .map_err(|_| "can not save tiles")?;
+    }
+
+    Ok(tiles)
+}
+
+/// Split an in-memory image into poster pages.
+///
+/// Each page is a full sheet at `dpi` with the poster content inside the
+/// margins. Neighbouring pages share `overlap_mm` of content. A page's `coords`
+/// are its top-left in the poster, in pixels at `dpi`.
+///
+/// # Errors
+///
+/// Returns an error if the sizes are not positive, the margins or overlap leave
+/// no room for content, or the grid exceeds the limits of `slice`.
+pub fn poster_pages(image: &DynamicImage, options: &PosterOptions) -> Result<Vec<Tile>, String> {
+    let (sheet_w, sheet_h) = options.sheet_mm();
+    let valid = |v: f64| v.is_finite() && v > 0.0;
+    if !valid(options.width_mm) || !valid(options.dpi) || !valid(sheet_w) || !valid(sheet_h) {
+        return Err("Poster width, DPI and paper size must be positive.".to_string());
+    }
+    if options.overlap_mm < 0.0 || options.margin_mm < 0.0 {
+        return Err("Overlap and margins can not be negative.".to_string());
+    }
+
+    // Poster size in pixels, fitted to the image's aspect ratio.
+    let aspect = image.height() as f64 / image.width() as f64;
+    let mut poster_mm = (options.width_mm, options.width_mm * aspect);
+    if options.height_mm > 0.0 && poster_mm.1 > options.height_mm {
+        poster_mm = (options.height_mm / aspect, options.height_mm);
+    }
+    let poster = (
+        options.px(poster_mm.0).max(1),
+        options.px(poster_mm.1).max(1),
+    );
+
+    let sheet = (options.px(sheet_w), options.px(sheet_h));
+    let margin = options.px(options.margin_mm);
+    let overlap = options.px(options.overlap_mm);
+    let printable = (
+        sheet.0.saturating_sub(2 * margin),
+        sheet.1.saturating_sub(2 * margin),
+    );
+    if printable.0 <= overlap || printable.1 <= overlap {
+        return Err("Margins and overlap leave no room for content on the page.".to_string());
+    }
+    let step = (printable.0 - overlap, printable.1 - overlap);
+    let count = |length: u32, printable: u32, step: u32| {
+        1 + length.saturating_sub(printable).div_ceil(step)
+    };
+    let (columns, rows) = validate_grid(
+        count(poster.0, printable.0, step.0),
+        count(poster.1, printable.1, step.1),
+    )?;
+
+    // Scale once so the overlaps of neighbouring pages hold identical pixels.
+    let scaled = if (image.width(), image.height()) == poster {
+        image.to_rgba8()
+    } else {
+        image
+            .resize_exact(poster.0, poster.1, FilterType::CatmullRom)
+            .to_rgba8()
+    };
+
+    let mut tiles = Vec::new();
+    for row in 0..rows {
+        for column in 0..columns {
+            let (x, y) = (column * step.0, row * step.1);
+            let (w, h) = (printable.0.min(poster.0 - x), printable.1.min(poster.1 - y));
+            let content = imageops::crop_imm(&scaled, x, y, w, h).to_image();
+
+            let mut page = RgbaImage::from_pixel(sheet.0, sheet.1, Rgba([255, 255, 255, 255]));
+            imageops::overlay(&mut page, &content, margin as i64, margin as i64);
+            if options.crop_marks {
+                draw_crop_marks(&mut page, (margin, margin, w, h), margin);
+            }
+            if options.labels {
+                let label = format!("R{}/C{}", row + 1, column + 1);
+                draw_label(&mut page, &label, margin, sheet.1 - margin, margin);
+            }
+
+            tiles.push(Tile::new(
+                DynamicImage::ImageRgba8(page),
+                tiles.len() as i32 + 1,
+                (column as i32 + 1, row as i32 + 1),
+                (x as i32, y as i32),
+                None,
+            ));
+        }
+    }
+    Ok(tiles)
+}
+
+/// Write poster pages as a multi-page PDF with one sheet per page.
+///
+/// # Errors
+///
+/// Returns an error if the PDF can not be written.
+pub fn save_poster_pdf(
+    pages: &[Tile],
+    options: &PosterOptions,
+    path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (w, h) = options.sheet_mm();
+    let to_points = |mm: f64| mm / MM_PER_INCH * POINTS_PER_INCH;
+    let mut pdf = PdfWriter::new();
+    for page in pages {
+        pdf.add_image_page(&page.image, to_points(w), to_points(h))?;
+    }
+    pdf.save(path)
+}
+
+/// Draws L-shaped marks in the margin around the corners of `rect`.
+fn draw_crop_marks(page: &mut RgbaImage, rect: (u32, u32, u32, u32), margin: u32) {
+    let black = Rgba([0, 0, 0, 255]);
+    let (x, y, w, h) = rect;
+    let length = margin * 3 / 4;
+    let gap = margin / 8;
+    for &cx in &[x, x + w - 1] {
+        for (y0, y1) in [
+            (y.saturating_sub(gap + length), y.saturating_sub(gap)),
+            (y + h + gap, (y + h + gap + length).min(page.height())),
+        ] {
+            for py in y0..y1 {
+                page.put_pixel(cx, py, black);
+            }
+        }
+    }
+    for &cy in &[y, y + h - 1] {
+        for (x0, x1) in [
+            (x.saturating_sub(gap + length), x.saturating_sub(gap)),
+            (x + w + gap, (x + w + gap + length).min(page.width())),
+        ] {
+            for px in x0..x1 {
+                page.put_pixel(px, cy, black);
+            }
+        }
+    }
+}
+
+/// 5x7 glyphs for the label characters, one row per byte, high bit on the left.
+fn glyph(c: char) -> [u8; 7] {
+    match c {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        '/' => [0x01, 0x01, 0x02, 0x04, 0x08, 0x10, 0x10],
+        _ => [0; 7],
+    }
+}
+
+/// Draws `text` in the bottom margin starting at `x`, below the content edge `top`.
+/// The text is scaled to about half the margin and skipped if it would not fit.
+fn draw_label(page: &mut RgbaImage, text: &str, x: u32, top: u32, margin: u32) {
+    let scale = margin / 14;
+    if scale == 0 {
+        return;
+    }
+    let y = top + (margin - 7 * scale) / 2;
+    let black = Rgba([0, 0, 0, 255]);
+    for (i, c) in text.chars().enumerate() {
+        let gx = x + i as u32 * 6 * scale;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in 0..5 {
+                if bits & (0x10 >> column) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let (px, py) = (gx + column * scale + dx, y + row as u32 * scale + dy);
+                        if px < page.width() && py < page.height() {
+                            page.put_pixel(px, py, black);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+
+    fn artwork() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(400, 300, |x, y| {
+            Rgba([(x % 256) as u8, (y % 256) as u8, 128, 255])
+        }))
+    }
+
+    #[test]
+    fn test_poster_grid() {
+        // A 400 mm wide poster at 25.4 dpi is 400x300 px; an A4 sheet is 210x297
+        // px with 180x267 px printable after 15 mm margins.
+        let options = PosterOptions {
+            width_mm: 400.0,
+            dpi: 25.4,
+            margin_mm: 15.0,
+            overlap_mm: 20.0,
+            ..PosterOptions::default()
+        };
+        let pages = poster_pages(&artwork(), &options).unwrap();
+        // Columns step by 160 px: 0, 160, 320. Rows step by 247 px: 0, 247.
+        assert_eq!(pages.len(), 6);
+        assert_eq!(pages[2].position, (3, 1));
+        assert_eq!(pages[2].coords, (320, 0));
+        assert_eq!(pages[4].coords, (160, 247));
+        assert!(pages.iter().all(|p| p.image.dimensions() == (210, 297)));
+        // The overlap repeats content: x = 165 appears on the first two pages.
+        let first = pages[0].image.get_pixel(15 + 165, 15 + 10);
+        let second = pages[1].image.get_pixel(15 + 5, 15 + 10);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_marks_labels_and_pdf() {
+        let options = PosterOptions {
+            width_mm: 300.0,
+            dpi: 25.4,
+            margin_mm: 30.0,
+            orientation: Orientation::Landscape,
+            ..PosterOptions::default()
+        };
+        let pages = poster_pages(&artwork(), &options).unwrap();
+        let page = &pages[0].image;
+        assert_eq!(page.dimensions(), (297, 210));
+        // Crop mark above the top-left corner and label ink in the bottom margin.
+        assert_eq!(page.get_pixel(30, 25), Rgba([0, 0, 0, 255]));
+        let label_ink = (180..210).any(|y| (30..80).any(|x| page.get_pixel(x, y)[0] == 0));
+        assert!(label_ink);
+
+        let path = std::env::temp_dir().join("papercut_poster_test.pdf");
+        save_poster_pdf(&pages, &options, &path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let text = String::from_utf8_lossy(&bytes);
+        assert!(text.contains(&format!("/Count {}", pages.len())));
+        assert!(text.contains("/MediaBox [0 0 841.89 595.28]"));
+    }
+
+    #[test]
+    fn test_scaled_overlap_and_single_page() {
+        // 500 mm at 25.4 dpi scales the 400 px artwork by 1.25.
+        let options = PosterOptions {
+            width_mm: 500.0,
+            dpi: 25.4,
+            margin_mm: 15.0,
+            overlap_mm: 20.0,
+            ..PosterOptions::default()
+        };
+        let pages = poster_pages(&artwork(), &options).unwrap();
+        assert_eq!(pages[1].coords, (160, 0));
+        // Every pixel of the overlap matches between neighbouring pages.
+        for y in 0..267 {
+            for x in 160..180 {
+                assert_eq!(
+                    pages[0].image.get_pixel(15 + x, 15 + y),
+                    pages[1].image.get_pixel(15 + x - 160, 15 + y)
+                );
+            }
+        }
+
+        // A poster that fits on one sheet is a single page.
+        let small = PosterOptions {
+            width_mm: 100.0,
+            dpi: 25.4,
+            ..PosterOptions::default()
+        };
+        let pages = poster_pages(&artwork(), &small).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].position, (1, 1));
+    }
+}
@@ -0,0 +1,350 @@
+//! The `presets` module cuts images into tiles of a fixed pixel size for social
+//! media, such as carousel slides or a profile grid. A preset names the slide
+//! size and grid, how the image is fitted to it and which area is safe from the
+//! platform's own cropping.
+//!
+//! # Features
+//!
+//! - **Built-in Presets**: Instagram carousels and profile grids.
+//! - **Fit Policies**: Letterbox the whole image, fill the grid or cut the
+//!   center at 1:1 without scaling.
+//! - **Safe Zones**: Preview overlays that shade what a platform may hide.
+//! - **User Presets**: Load more presets from a JSON config file; they replace
+//!   built-ins of the same name.
+//!
+//! # Example Usage
+//!
+//! ```no_run
+//! use papercut::{find_preset, safe_zone_overlay, slice_preset};
+//! use std::path::Path;
+//!
+//! let preset = find_preset("instagram-carousel", Some(Path::new("presets.json"))).unwrap();
+//! let slides = slice_preset("panorama.jpg", &preset, true).unwrap();
+//! let previews = safe_zone_overlay(&slides, &preset);
+//! ```
+//!
+//! A config file holds a list of presets:
+//!
+//! ```json
+//! { "presets": [
+//!     { "name": "story-strip", "tile_width": 1080, "tile_height": 1920,
+//!       "columns": 0, "rows": 1, "fit": "fill",
+//!       "safe_zone": { "top": 250, "right": 0, "bottom": 340, "left": 0 } }
+//! ] }
+//! ```
+
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::grid::slice_image_by_size;
+use crate::tile::Tile;
+use crate::{get_basename, save_tiles, validate_grid};
+
+/// How the image is fitted to the preset's grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FitPolicy {
+    /// Scale the whole image into the grid and fill the rest with `background`.
+    Fit,
+    /// Scale the image to cover the grid and cut off what overflows.
+    Fill,
+    /// Cut the center of the image at its own resolution, padding with
+    /// `background` if it is smaller than the grid.
+    Crop,
+}
+
+/// Margins of a tile a platform may cover or crop, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SafeZone {
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+    pub left: u32,
+}
+
+/// A named slide size and grid.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Preset {
+    /// Name used to look the preset up.
+    pub name: String,
+    /// Width of each tile in pixels.
+    pub tile_width: u32,
+    /// Height of each tile in pixels.
+    pub tile_height: u32,
+    /// Tiles across, or `0` to follow the image's aspect ratio.
+    pub columns: u32,
+    /// Tiles down, or `0` to follow the image's aspect ratio.
+    pub rows: u32,
+    /// How the image is fitted to the grid.
+    pub fit: FitPolicy,
+    /// Colour for areas the image does not cover.
+    #[serde(default = "default_background")]
+    pub background: [u8; 4],
+    /// The area of each tile that is always visible.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub safe_zone: Option<SafeZone>,
+}
+
+fn default_background() -> [u8; 4] {
+    [255, 255, 255, 255]
+}
+
+#[derive(Deserialize)]
+struct PresetFile {
+    presets: Vec<Preset>,
+}
+
+impl Preset {
+    /// The grid for an image of `width x height`, filling in a zero column or
+    /// row count from the image's aspect ratio.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tile size is zero or the grid is too large.
+    pub fn grid(&self, width: u32, height: u32) -> Result<(u32, u32), String> {
+        if self.tile_width == 0 || self.tile_height == 0 {
+            return Err(format!("Preset {} has an empty tile size.", self.name));
+        }
+        // Tiles that fit across for each tile down, for an image of this shape.
+        let ratio =
+            (width as f64 / height as f64) * self.tile_height as f64 / self.tile_width as f64;
+        let (columns, rows) = match (self.columns, self.rows) {
+            (0, 0) => ((ratio.round() as u32).max(1), 1),
+            (0, rows) => (((ratio * rows as f64).round() as u32).max(1), rows),
+            (columns, 0) => (columns, ((columns as f64 / ratio).round() as u32).max(1)),
+            (columns, rows) => (columns, rows),
+        };
+        validate_grid(columns, rows)
+    }
+}
+
+/// The presets that ship with papercut.
+pub fn builtin_presets() -> Vec<Preset> {
+    let preset = |name: &str, size: (u32, u32), grid: (u32, u32), safe_zone| Preset {
+        name: name.to_string(),
+        tile_width: size.0,
+        tile_height: size.1,
+        columns: grid.0,
+        rows: grid.1,
+        fit: FitPolicy::Fill,
+        background: default_background(),
+        safe_zone,
+    };
+    vec![
+        preset("instagram-carousel", (1080, 1350), (0, 1), None),
+        preset("instagram-carousel-square", (1080, 1080), (0, 1), None),
+        // The profile grid shows posts cropped to 3:4.
+        preset(
+            "instagram-grid",
+            (1080, 1350),
+            (3, 0),
+            Some(SafeZone {
+                top: 0,
+                right: 34,
+                bottom: 0,
+                left: 34,
+            }),
+        ),
+        preset("instagram-grid-square", (1080, 1080), (3, 0), None),
+    ]
+}
+
+/// Load presets from a JSON config file with a `presets` list.
+///
+/// # Errors
+///
+/// Returns an error if the file can not be read or parsed.
+pub fn load_presets(path: &Path) -> Result<Vec<Preset>, Box<dyn std::error::Error>> {
+    let file: PresetFile = serde_json::from_str(&fs::read_to_string(path)?)?;
+    Ok(file.presets)
+}
+
+/// Look a preset up by name, first in `config` if given, then in the built-ins.
+///
+/// # Errors
+///
+/// Returns an error if the config can not be loaded or no preset has the name.
+pub fn find_preset(name: &str, config: Option<&Path>) -> Result<Preset, String> {
+    let mut presets = match config {
+        Some(path) => load_presets(path)
+            .map_err(|e| format!("can not load presets {}: {}", path.display(), e))?,
+        None => Vec::new(),
+    };
+    presets.extend(builtin_presets());
+    presets
+        .into_iter()
+        .find(|p| p.name == name)
+        .ok_or_else(|| format!("Unknown preset {}.", name))
+}
+
+/// Cut an image file with a preset.
+///
+/// # Arguments
+///
+/// * `filename` - The filename of the image to cut.
+/// * `preset` - The slide size, grid and fit policy.
+/// * `save` - Whether or not to save the tiles to disk.
+///
+/// # Returns
+///
+/// The tiles in row order, each `tile_width x tile_height`.
+pub fn slice_preset(filename: &str, preset: &Preset, save: bool) -> Result<Vec<Tile>, String> {
+    let im =
+        image::open(Path::new(filename)).map_err(|_| format!("can not open image {}", filename))?;
+    let mut tiles = apply_preset(&im, preset)?;
+
+    if save {
+        let prefix = get_basename(filename);
+        let directory = Path::new(filename)
+            .parent()
+            .unwrap_or_else(|| Path::new("."));
+        save_tiles(&mut tiles, &prefix, Some(directory), "png")
+            .map_err(|_| "can not save tiles")?;
+    }
+
+    Ok(tiles)
+}
+
+/// Cut an in-memory image with a preset.
+///
+/// # Errors
+///
+/// Returns an error if the preset's grid is invalid. See `Preset::grid`.
+pub fn apply_preset(image: &DynamicImage, preset: &Preset) -> Result<Vec<Tile>, String> {
+    let (columns, rows) = preset.grid(image.width(), image.height())?;
+    let (width, height) = (columns * preset.tile_width, rows * preset.tile_height);
+
+    let canvas = match preset.fit {
+        FitPolicy::Fill => image.resize_to_fill(width, height, FilterType::Lanczos3),
+        FitPolicy::Fit => {
+            let scaled = image.resize(width, height, FilterType::Lanczos3);
+            centered(&scaled, width, height, preset.background)
+        }
+        FitPolicy::Crop => centered(image, width, height, preset.background),
+    };
+    slice_image_by_size(&canvas, preset.tile_width, preset.tile_height)
+}
+
+/// Copies of `tiles` with the area outside the preset's safe zone shaded, for
+/// previewing. Tiles are returned unchanged if the preset has no safe zone.
+pub fn safe_zone_overlay(tiles: &[Tile], preset: &Preset) -> Vec<Tile> {
+    let Some(zone) = preset.safe_zone else {
+        return tiles.to_vec();
+    };
+    tiles
+        .iter()
+        .map(|tile| {
+            let mut image = tile.image.to_rgba8();
+            let (width, height) = image.dimensions();
+            for (x, y, pixel) in image.enumerate_pixels_mut() {
+                let inside = x >= zone.left
+                    && y >= zone.top
+                    && x + zone.right < width
+                    && y + zone.bottom < height;
+                if !inside {
+                    // Blend half way towards red.
+                    let [r, g, b, a] = pixel.0;
+                    *pixel = Rgba([r / 2 + 128, g / 2, b / 2, a]);
+                }
+            }
+            let mut overlay = tile.clone();
+            overlay.image = DynamicImage::ImageRgba8(image);
+            overlay
+        })
+        .collect()
+}
+
+/// `image` centered on a `width x height` canvas of `background`, cut to fit.
+fn centered(image: &DynamicImage, width: u32, height: u32, background: [u8; 4]) -> DynamicImage {
+    let mut canvas = RgbaImage::from_pixel(width, height, Rgba(background));
+    let (w, h) = image.dimensions();
+    let x = (width as i64 - w as i64) / 2;
+    let y = (height as i64 - h as i64) / 2;
+    imageops::overlay(&mut canvas, &image.to_rgba8(), x, y);
+    DynamicImage::ImageRgba8(canvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn carousel(fit: FitPolicy) -> Preset {
+        Preset {
+            name: "test".to_string(),
+            tile_width: 40,
+            tile_height: 50,
+            columns: 0,
+            rows: 1,
+            fit,
+            background: [0, 0, 0, 255],
+            safe_zone: Some(SafeZone {
+                top: 0,
+                right: 5,
+                bottom: 0,
+                left: 5,
+            }),
+        }
+    }
+
+    fn panorama() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(300, 100, Rgba([0, 200, 0, 255])))
+    }
+
+    #[test]
+    fn test_grid_and_policies() {
+        // 300x100 at 40x50 slides: 3 x 0.5 x 1.25 = 3.75, rounded to 4 slides.
+        let preset = carousel(FitPolicy::Fill);
+        assert_eq!(preset.grid(300, 100).unwrap(), (4, 1));
+        let tiles = apply_preset(&panorama(), &preset).unwrap();
+        assert_eq!(tiles.len(), 4);
+        assert!(tiles.iter().all(|t| t.image.dimensions() == (40, 50)));
+        assert_eq!(tiles[0].image.get_pixel(0, 0), Rgba([0, 200, 0, 255]));
+
+        // Fitting 300x100 into 160x50 letterboxes it at 150x50.
+        let fit = apply_preset(&panorama(), &carousel(FitPolicy::Fit)).unwrap();
+        assert_eq!(fit[0].image.get_pixel(2, 25), Rgba([0, 0, 0, 255]));
+        assert_eq!(fit[0].image.get_pixel(10, 25), Rgba([0, 200, 0, 255]));
+
+        // Cropping takes the center 160x50 of the image unscaled.
+        let crop = apply_preset(&panorama(), &carousel(FitPolicy::Crop)).unwrap();
+        assert_eq!(crop[3].image.get_pixel(39, 0), Rgba([0, 200, 0, 255]));
+
+        let grid = find_preset("instagram-grid", None).unwrap();
+        assert_eq!(grid.grid(1080, 1080).unwrap(), (3, 2));
+        assert!(find_preset("missing", None).is_err());
+    }
+
+    #[test]
+    fn test_single_slide() {
+        let preset = find_preset("instagram-carousel", None).unwrap();
+        assert_eq!(preset.grid(1080, 1350).unwrap(), (1, 1));
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(1080, 1350));
+        let tiles = apply_preset(&image, &preset).unwrap();
+        assert_eq!(tiles.len(), 1);
+        assert_eq!(tiles[0].image.dimensions(), (1080, 1350));
+    }
+
+    #[test]
+    fn test_config_and_overlay() {
+        let path = std::env::temp_dir().join("papercut_presets_test.json");
+        let json = r#"{ "presets": [
+            { "name": "instagram-grid", "tile_width": 10, "tile_height": 10,
+              "columns": 2, "rows": 2, "fit": "crop" }
+        ] }"#;
+        fs::write(&path, json).unwrap();
+        let preset = find_preset("instagram-grid", Some(&path)).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(preset.fit, FitPolicy::Crop);
+        assert_eq!(preset.background, [255, 255, 255, 255]);
+        assert_eq!(preset.safe_zone, None);
+
+        let preset = carousel(FitPolicy::Fill);
+        let tiles = apply_preset(&panorama(), &preset).unwrap();
+        let preview = safe_zone_overlay(&tiles, &preset);
+        assert_eq!(preview[0].image.get_pixel(2, 10), Rgba([128, 100, 0, 255]));
+        assert_eq!(preview[0].image.get_pixel(20, 10), Rgba([0, 200, 0, 255]));
+    }
+}
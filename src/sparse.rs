@@ -0,0 +1,208 @@
+//! The `sparse` module finds tiles that hold nothing but background. Scanned
+//! maps and microscopy slides are mostly empty, and leaving those tiles out of a
+//! saved tile set saves both disk space and decoding time.
+//!
+//! # Features
+//!
+//! - **Emptiness Detection**: Fully transparent tiles, tiles of a single colour
+//!   within a tolerance, and tiles whose brightness entropy is below a threshold.
+//! - **Sparse Tile Sets**: Marked tiles are skipped by `save_tiles` and recorded
+//!   in a `Manifest` with their colour, which `Manifest::open_tiles` and
+//!   `join_streaming` fill back in.
+//!
+//! # Example Usage
+//!
+//! ```no_run
+//! use papercut::{mark_empty_tiles, save_tiles, slice, EmptyTileOptions, Manifest};
+//! use std::path::Path;
+//!
+//! let mut tiles = slice("slide.png", None, Some(16), Some(16), false).unwrap();
+//! let options = EmptyTileOptions { tolerance: 8, ..EmptyTileOptions::default() };
+//! let empty = mark_empty_tiles(&mut tiles, &options);
+//! println!("{} of {} tiles are empty", empty, tiles.len());
+//! let tiles = save_tiles(&mut tiles, "slide", Some(Path::new("out")), "png").unwrap();
+//! Manifest::from_tiles(&tiles).save(Path::new("out/manifest.json")).unwrap();
+//! ```
+
+use image::DynamicImage;
+
+use crate::tile::Tile;
+
+/// Thresholds for `detect_empty`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EmptyTileOptions {
+    /// Largest alpha value still counted as fully transparent.
+    pub alpha_threshold: u8,
+    /// Largest difference of any channel from the first pixel for a tile to
+    /// count as a single colour.
+    pub tolerance: u8,
+    /// Tiles whose brightness histogram has less entropy than this, in bits,
+    /// are empty. `0.0` turns the check off.
+    pub min_entropy: f64,
+}
+
+impl Default for EmptyTileOptions {
+    fn default() -> Self {
+        EmptyTileOptions {
+            alpha_threshold: 0,
+            tolerance: 0,
+            min_entropy: 0.0,
+        }
+    }
+}
+
+/// Why a tile counts as empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emptiness {
+    /// Every pixel is transparent.
+    Transparent,
+    /// Every pixel is close to this colour, the tile's mean.
+    Uniform([u8; 4]),
+    /// The tile carries little information; its mean colour.
+    LowEntropy([u8; 4]),
+}
+
+impl Emptiness {
+    /// The colour that stands in for the tile.
+    pub fn color(&self) -> [u8; 4] {
+        match *self {
+            Emptiness::Transparent => [0, 0, 0, 0],
+            Emptiness::Uniform(color) | Emptiness::LowEntropy(color) => color,
+        }
+    }
+}
+
+/// Decide whether `image` is empty, checking transparency, then a single colour,
+/// then entropy.
+pub fn detect_empty(image: &DynamicImage, options: &EmptyTileOptions) -> Option<Emptiness> {
+    let rgba = image.to_rgba8();
+    let first = rgba.pixels().next()?.0;
+    if rgba.pixels().all(|p| p[3] <= options.alpha_threshold) {
+        return Some(Emptiness::Transparent);
+    }
+
+    let count = rgba.pixels().len() as u64;
+    let mut sums = [0u64; 4];
+    for pixel in rgba.pixels() {
+        for (sum, &value) in sums.iter_mut().zip(&pixel.0) {
+            *sum += value as u64;
+        }
+    }
+    let mean = sums.map(|sum| (sum as f64 / count as f64).round() as u8);
+
+    let uniform = rgba.pixels().all(|p| {
+        p.0.iter()
+            .zip(&first)
+            .all(|(&a, &b)| a.abs_diff(b) <= options.tolerance)
+    });
+    if uniform {
+        return Some(Emptiness::Uniform(mean));
+    }
+
+    if options.min_entropy > 0.0 {
+        let mut histogram = [0u64; 256];
+        for pixel in image.to_luma8().pixels() {
+            histogram[pixel[0] as usize] += 1;
+        }
+        let entropy: f64 = histogram
+            .iter()
+            .filter(|&&n| n > 0)
+            .map(|&n| {
+                let p = n as f64 / count as f64;
+                -p * p.log2()
+            })
+            .sum();
+        if entropy < options.min_entropy {
+            return Some(Emptiness::LowEntropy(mean));
+        }
+    }
+    None
+}
+
+/// Set `background` on every empty tile and clear it on the others. Returns the
+/// number of empty tiles.
+pub fn mark_empty_tiles(tiles: &mut [Tile], options: &EmptyTileOptions) -> usize {
+    let mut empty = 0;
+    for tile in tiles.iter_mut() {
+        tile.background = detect_empty(&tile.image, options).map(|e| e.color());
+        if tile.background.is_some() {
+            empty += 1;
+        }
+    }
+    empty
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Manifest, join, save_tiles, slice_image_by_size};
+    use image::{Rgba, RgbaImage};
+    use std::fs;
+
+    #[test]
+    fn test_detection() {
+        let options = EmptyTileOptions {
+            tolerance: 4,
+            ..EmptyTileOptions::default()
+        };
+        let clear = DynamicImage::ImageRgba8(RgbaImage::new(4, 4));
+        assert_eq!(detect_empty(&clear, &options), Some(Emptiness::Transparent));
+
+        let paper = DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 4, |x, _| {
+            Rgba([250 - x as u8, 250, 250, 255])
+        }));
+        assert_eq!(
+            detect_empty(&paper, &options),
+            Some(Emptiness::Uniform([249, 250, 250, 255]))
+        );
+
+        // A single dark speck passes the entropy check but not the colour check.
+        let speck = DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 8, |x, y| {
+            if (x, y) == (3, 3) {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        }));
+        assert_eq!(detect_empty(&speck, &options), None);
+        let entropy = EmptyTileOptions {
+            min_entropy: 0.5,
+            ..options
+        };
+        assert_eq!(
+            detect_empty(&speck, &entropy),
+            Some(Emptiness::LowEntropy([251, 251, 251, 255]))
+        );
+    }
+
+    #[test]
+    fn test_sparse_round_trip() {
+        // Left half white background, right half a gradient.
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 4, |x, y| {
+            if x < 4 {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([x as u8 * 20, y as u8 * 50, 0, 255])
+            }
+        }));
+        let mut tiles = slice_image_by_size(&image, 4, 4).unwrap();
+        assert_eq!(
+            mark_empty_tiles(&mut tiles, &EmptyTileOptions::default()),
+            1
+        );
+
+        let dir = std::env::temp_dir().join("papercut_sparse_test");
+        let saved = save_tiles(&mut tiles, "sparse", Some(&dir), "png").unwrap();
+        assert!(saved[0].filename.is_none());
+        let manifest_path = dir.join("manifest.json");
+        Manifest::from_tiles(&saved).save(&manifest_path).unwrap();
+
+        let manifest = Manifest::load(&manifest_path).unwrap();
+        assert_eq!(manifest.tiles[0].background, Some([255, 255, 255, 255]));
+        let reopened = manifest.open_tiles().unwrap();
+        let files = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, 2);
+        assert_eq!(join(&reopened, 8, 4).unwrap().to_rgba8(), image.to_rgba8());
+    }
+}
@@ -0,0 +1,466 @@
+//! The `sprite` module extracts irregularly placed sprites from a sprite sheet.
+//! Foreground pixels are grouped into connected blobs and each blob becomes a
+//! `Tile` cropped to its tight bounding box. This complements the grid-based
+//! `slice` for sheets that were not laid out on a grid.
+//!
+//! # Features
+//!
+//! - **Background Detection**: Transparent backgrounds or a solid colour, given
+//!   explicitly or sampled from the top-left pixel.
+//! - **Blob Merging**: Blobs closer than a merge distance are combined, so sprites
+//!   made of several disconnected parts stay together.
+//! - **Filtering and Ordering**: Blobs below a minimum area are dropped and the rest
+//!   are sorted in reading order, column order or by size.
+//!
+//! Tiles keep their `coords` in the sheet, so `Manifest::from_tiles` and `join`
+//! can put them back.
+//!
+//! # Example Usage
+//!
+//! ```no_run
+//! use papercut::{slice_sprites, Manifest, SpriteOptions};
+//! use std::path::Path;
+//!
+//! let sprites = slice_sprites("sheet.png", &SpriteOptions::default(), true).unwrap();
+//! Manifest::from_tiles(&sprites).save(Path::new("sprites.json")).unwrap();
+//! ```
+
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::path::Path;
+
+use crate::tile::Tile;
+use crate::{get_basename, save_tiles};
+
+/// What counts as background on a sprite sheet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Background {
+    /// Pixels whose alpha is at most the tolerance.
+    Transparent,
+    /// Pixels within the tolerance of this colour on every channel.
+    Color(Rgba<u8>),
+    /// `Transparent` if the top-left pixel is transparent, otherwise its colour.
+    Auto,
+}
+
+/// Order in which `extract_sprites` returns sprites.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpriteOrder {
+    /// Rows from top to bottom, left to right within a row.
+    RowMajor,
+    /// Columns from left to right, top to bottom within a column.
+    ColumnMajor,
+    /// Largest foreground area first.
+    AreaDescending,
+}
+
+/// Options for `extract_sprites`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpriteOptions {
+    /// How background pixels are recognised.
+    pub background: Background,
+    /// Per-channel tolerance used when matching the background.
+    pub tolerance: u8,
+    /// Blobs whose bounding boxes are at most this many pixels apart are merged.
+    pub merge_distance: u32,
+    /// Sprites with fewer foreground pixels than this are dropped.
+    pub min_area: u32,
+    /// Order of the returned sprites.
+    pub order: SpriteOrder,
+}
+
+impl Default for SpriteOptions {
+    fn default() -> Self {
+        SpriteOptions {
+            background: Background::Auto,
+            tolerance: 0,
+            merge_distance: 0,
+            min_area: 4,
+            order: SpriteOrder::RowMajor,
+        }
+    }
+}
+
+/// Bounding box and foreground pixel count of a blob.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Blob {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    area: u32,
+}
+
+impl Blob {
+    fn gap(&self, other: &Blob) -> u32 {
+        let dx = other
+            .x0
+            .saturating_sub(self.x1 + 1)
+            .max(self.x0.saturating_sub(other.x1 + 1));
+        let dy = other
+            .y0
+            .saturating_sub(self.y1 + 1)
+            .max(self.y0.saturating_sub(other.y1 + 1));
+        dx.max(dy)
+    }
+
+    fn merge(&self, other: &Blob) -> Blob {
+        Blob {
+            x0: self.x0.min(other.x0),
+            y0: self.y0.min(other.y0),
+            x1: self.x1.max(other.x1),
+            y1: self.y1.max(other.y1),
+            area: self.area + other.area,
+        }
+    }
+}
+
+/// Extract the sprites from a sprite sheet file.
+///
+/// # Arguments
+///
+/// * `filename` - The filename of the sprite sheet.
+/// * `options` - Background, merge distance, minimum area and ordering.
+/// * `save` - Whether or not to save tiles to disk.
+///
+/// # Returns
+///
+/// A vector of `Tile` instances, one per sprite.
+pub fn slice_sprites(
+    filename: &str,
+    options: &SpriteOptions,
+    save: bool,
+) -> Result<Vec<Tile>, String> {
+    let im =
+        image::open(Path::new(filename)).map_err(|_| format!("can not open image {}", filename))?;
+    let mut tiles = extract_sprites(&im, options);
+
+    if save {
+        let prefix = get_basename(filename);
+        let directory = Path::new(filename)
+            .parent()
+            .unwrap_or_else(|| Path::new("."));
+        save_tiles(&mut tiles, &prefix, Some(directory), "png")
+            .map_err(|_| "can not save tiles")?;
+    }
+
+    Ok(tiles)
+}
+
+/// Extract the sprites from an in-memory sprite sheet.
+///
+/// Foreground pixels are grouped into 8-connected blobs, nearby blobs are merged
+/// and each remaining blob is cropped to its bounding box. The `n`-th sprite gets
+/// `number` and `position.0` equal to `n`.
+pub fn extract_sprites(image: &DynamicImage, options: &SpriteOptions) -> Vec<Tile> {
+    let sheet = image.to_rgba8();
+    let mut blobs = merge_nearby(connected_blobs(&sheet, options), options.merge_distance);
+
+    blobs.retain(|blob| blob.area >= options.min_area);
+    sort_blobs(&mut blobs, options.order);
+
+    blobs
+        .iter()
+        .enumerate()
+        .map(|(i, blob)| {
+            Tile::new(
+                image.crop_imm(
+                    blob.x0,
+                    blob.y0,
+                    blob.x1 - blob.x0 + 1,
+                    blob.y1 - blob.y0 + 1,
+                ),
+                i as i32 + 1,
+                (i as i32 + 1, 1),
+                (blob.x0 as i32, blob.y0 as i32),
+                None,
+            )
+        })
+        .collect()
+}
+
+/// Merges blobs until no two are within `distance` of each other.
+///
+/// Each pass joins every group of blobs linked by close pairs with a union-find.
+/// Pairs are found with a sweep over the blobs sorted by left edge, which stops
+/// once the left edges are too far right to be close. Merged boxes can grow
+/// close to further blobs, so passes repeat until nothing merges.
+fn merge_nearby(mut blobs: Vec<Blob>, distance: u32) -> Vec<Blob> {
+    loop {
+        blobs.sort_by_key(|blob| blob.x0);
+        let mut parent: Vec<usize> = (0..blobs.len()).collect();
+        let mut merged = false;
+        for i in 0..blobs.len() {
+            let reach = blobs[i].x1 as u64 + 1 + distance as u64;
+            for j in i + 1..blobs.len() {
+                if blobs[j].x0 as u64 > reach {
+                    break;
+                }
+                if blobs[i].gap(&blobs[j]) <= distance {
+                    let (a, b) = (find(&mut parent, i), find(&mut parent, j));
+                    if a != b {
+                        parent[b] = a;
+                        merged = true;
+                    }
+                }
+            }
+        }
+        if !merged {
+            return blobs;
+        }
+
+        let mut groups: Vec<Option<Blob>> = vec![None; blobs.len()];
+        for (i, blob) in blobs.iter().enumerate() {
+            let root = find(&mut parent, i);
+            groups[root] = Some(match &groups[root] {
+                Some(group) => group.merge(blob),
+                None => *blob,
+            });
+        }
+        blobs = groups.into_iter().flatten().collect();
+    }
+}
+
+/// The root of `i` in a union-find forest, halving paths on the way.
+fn find(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+/// Finds 8-connected foreground components with a union-find over pixels.
+fn connected_blobs(sheet: &RgbaImage, options: &SpriteOptions) -> Vec<Blob> {
+    let (width, height) = sheet.dimensions();
+    let background = match options.background {
+        Background::Auto if width > 0 && height > 0 => {
+            let corner = *sheet.get_pixel(0, 0);
+            if corner.0[3] <= options.tolerance {
+                Background::Transparent
+            } else {
+                Background::Color(corner)
+            }
+        }
+        other => other,
+    };
+    let is_foreground = |pixel: &Rgba<u8>| match background {
+        Background::Transparent | Background::Auto => pixel.0[3] > options.tolerance,
+        Background::Color(color) => pixel
+            .0
+            .iter()
+            .zip(color.0.iter())
+            .any(|(a, b)| a.abs_diff(*b) > options.tolerance),
+    };
+
+    let index = |x: u32, y: u32| (y * width + x) as usize;
+    let mut parent: Vec<usize> = (0..(width * height) as usize).collect();
+
+    let foreground: Vec<bool> = sheet.pixels().map(is_foreground).collect();
+    for y in 0..height {
+        for x in 0..width {
+            if !foreground[index(x, y)] {
+                continue;
+            }
+            // Previously visited neighbours: left, and the three above.
+            let neighbours = [(-1, 0), (-1, -1), (0, -1), (1, -1)];
+            for (dx, dy) in neighbours {
+                let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                if nx < 0 || ny < 0 || nx >= i64::from(width) {
+                    continue;
+                }
+                let neighbour = index(nx as u32, ny as u32);
+                if foreground[neighbour] {
+                    let (a, b) = (find(&mut parent, index(x, y)), find(&mut parent, neighbour));
+                    parent[a.max(b)] = a.min(b);
+                }
+            }
+        }
+    }
+
+    let mut blobs: Vec<Blob> = Vec::new();
+    let mut slot = std::collections::HashMap::new();
+    for y in 0..height {
+        for x in 0..width {
+            if !foreground[index(x, y)] {
+                continue;
+            }
+            let root = find(&mut parent, index(x, y));
+            let i = *slot.entry(root).or_insert_with(|| {
+                blobs.push(Blob {
+                    x0: x,
+                    y0: y,
+                    x1: x,
+                    y1: y,
+                    area: 0,
+                });
+                blobs.len() - 1
+            });
+            let blob = &mut blobs[i];
+            blob.x0 = blob.x0.min(x);
+            blob.x1 = blob.x1.max(x);
+            blob.y1 = blob.y1.max(y);
+            blob.area += 1;
+        }
+    }
+    blobs
+}
+
+fn sort_blobs(blobs: &mut [Blob], order: SpriteOrder) {
+    match order {
+        SpriteOrder::AreaDescending => {
+            blobs.sort_by_key(|b| (std::cmp::Reverse(b.area), b.y0, b.x0));
+        }
+        SpriteOrder::RowMajor | SpriteOrder::ColumnMajor => {
+            let row_major = order == SpriteOrder::RowMajor;
+            // Along the primary axis, a blob joins the current line if it starts
+            // before the line ends; lines are then ordered along the other axis.
+            let primary = |b: &Blob| {
+                if row_major {
+                    (b.y0, b.y1)
+                } else {
+                    (b.x0, b.x1)
+                }
+            };
+            let secondary = |b: &Blob| if row_major { b.x0 } else { b.y0 };
+            blobs.sort_by_key(|b| primary(b).0);
+            let mut line_start = 0;
+            let mut line_end = 0;
+            for i in 0..blobs.len() {
+                let (start, end) = primary(&blobs[i]);
+                if i > 0 && start > line_end {
+                    blobs[line_start..i].sort_by_key(secondary);
+                    line_start = i;
+                    line_end = end;
+                } else {
+                    line_end = line_end.max(end);
+                }
+            }
+            blobs[line_start..].sort_by_key(secondary);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::join;
+
+    fn sheet(background: Rgba<u8>) -> RgbaImage {
+        let mut sheet = RgbaImage::from_pixel(64, 40, background);
+        let sprites = [
+            (40, 4, 10, 12),
+            (4, 6, 8, 8),
+            (20, 26, 14, 10),
+            (2, 30, 6, 6),
+        ];
+        for (x, y, w, h) in sprites {
+            for dy in 0..h {
+                for dx in 0..w {
+                    sheet.put_pixel(
+                        x + dx,
+                        y + dy,
+                        Rgba([200, (dx * 10) as u8, (dy * 10) as u8, 255]),
+                    );
+                }
+            }
+        }
+        // A two-part sprite: the parts are 2 pixels apart.
+        for dy in 0..4 {
+            sheet.put_pixel(56, 28 + dy, Rgba([10, 10, 10, 255]));
+            sheet.put_pixel(59, 28 + dy, Rgba([10, 10, 10, 255]));
+        }
+        sheet
+    }
+
+    #[test]
+    fn test_extract_sprites_row_major() {
+        let image = DynamicImage::ImageRgba8(sheet(Rgba([0, 0, 0, 0])));
+        let sprites = extract_sprites(&image, &SpriteOptions::default());
+        let boxes: Vec<_> = sprites
+            .iter()
+            .map(|s| (s.coords, s.image.width(), s.image.height()))
+            .collect();
+        assert_eq!(
+            boxes,
+            vec![
+                ((4, 6), 8, 8),
+                ((40, 4), 10, 12),
+                ((2, 30), 6, 6),
+                ((20, 26), 14, 10),
+                ((56, 28), 1, 4),
+                ((59, 28), 1, 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_distance_and_min_area_on_solid_background() {
+        let image = DynamicImage::ImageRgba8(sheet(Rgba([255, 0, 255, 255])));
+        let options = SpriteOptions {
+            merge_distance: 2,
+            min_area: 40,
+            order: SpriteOrder::AreaDescending,
+            ..SpriteOptions::default()
+        };
+        let sprites = extract_sprites(&image, &options);
+        let coords: Vec<_> = sprites.iter().map(|s| s.coords).collect();
+        assert_eq!(coords, vec![(20, 26), (40, 4), (4, 6)]);
+
+        let options = SpriteOptions {
+            merge_distance: 2,
+            ..SpriteOptions::default()
+        };
+        let sprites = extract_sprites(&image, &options);
+        assert!(
+            sprites
+                .iter()
+                .any(|s| s.coords == (56, 28) && s.image.width() == 4)
+        );
+    }
+
+    #[test]
+    fn test_sprites_join_back_in_place() {
+        let source = sheet(Rgba([0, 0, 0, 0]));
+        let sprites = extract_sprites(
+            &DynamicImage::ImageRgba8(source.clone()),
+            &SpriteOptions::default(),
+        );
+        let joined = join(&sprites, 64, 40).unwrap().to_rgba8();
+        assert_eq!(joined, source);
+    }
+
+    #[test]
+    fn test_merge_many_blobs() {
+        // 3000 single-pixel particles, 2 pixels apart from their neighbours.
+        let dots: Vec<Blob> = (0..3000u32)
+            .map(|i| {
+                let (x, y) = ((i % 60) * 3, (i / 60) * 3);
+                Blob {
+                    x0: x,
+                    y0: y,
+                    x1: x,
+                    y1: y,
+                    area: 1,
+                }
+            })
+            .collect();
+        assert_eq!(merge_nearby(dots.clone(), 1).len(), 3000);
+        let all = merge_nearby(dots, 2);
+        assert_eq!(all.len(), 1);
+        assert_eq!((all[0].x1, all[0].y1, all[0].area), (177, 147, 3000));
+
+        // Merging the first two grows a box that reaches the third.
+        let blob = |x0, y0, x1, y1| Blob {
+            x0,
+            y0,
+            x1,
+            y1,
+            area: 1,
+        };
+        let chained = merge_nearby(
+            vec![blob(0, 0, 0, 10), blob(2, 0, 2, 0), blob(4, 10, 4, 10)],
+            1,
+        );
+        assert_eq!(chained.len(), 1);
+    }
+}
@@ -0,0 +1,306 @@
+//! The `stack` module cuts aligned images on one shared grid. Registered layers
+//! such as RGB, near-infrared and depth captures, or the frames of a time
+//! series, must split into tiles covering exactly the same pixels, so the grid
+//! is computed once and applied to every layer.
+//!
+//! # Features
+//!
+//! - **Shared Layout**: The `slice` grid is worked out once for all layers,
+//!   which must have the same size.
+//! - **Grouped Tiles**: Each grid cell holds one tile per layer with a shared
+//!   number, position and coordinates.
+//! - **Layer Suffixes**: Saved tiles carry a channel or frame suffix, such as
+//!   `scene_01_02_nir.png`.
+//!
+//! # Example Usage
+//!
+//! ```no_run
+//! use papercut::slice_many;
+//!
+//! let groups = slice_many(&["scene_rgb.png", "scene_nir.png"], Some(4), None, None, true).unwrap();
+//! assert_eq!(groups[0].tiles.len(), 2);
+//! // Saves scene_01_01_rgb.png, scene_01_01_nir.png, ...
+//! ```
+
+use image::DynamicImage;
+use std::fs;
+use std::path::Path;
+
+use crate::tile::Tile;
+use crate::{get_basename, resolve_grid};
+
+/// The tiles of every layer for one grid cell.
+#[derive(Debug, Clone)]
+pub struct TileGroup {
+    /// The cell's number, shared by its tiles.
+    pub number: i32,
+    /// The cell's 1-based `(column, row)`, shared by its tiles.
+    pub position: (i32, i32),
+    /// The cell's top-left pixel, shared by its tiles.
+    pub coords: (i32, i32),
+    /// One tile per layer, in the order the layers were given.
+    pub tiles: Vec<Tile>,
+}
+
+/// Split aligned image files on one grid.
+///
+/// # Arguments
+///
+/// * `filenames` - The layers, all the same size.
+/// * `number_tiles` - The number of tiles required.
+/// * `col` - Number of columns (optional).
+/// * `row` - Number of rows (optional).
+/// * `save` - Whether or not to save tiles next to the first layer.
+///
+/// When saving, the part the filenames share becomes the prefix and the rest
+/// of each name the layer suffix: `scene_rgb.png` and `scene_nir.png` give
+/// `scene_01_01_rgb.png` and `scene_01_01_nir.png`. Layers whose names share
+/// nothing are numbered from `0`.
+///
+/// # Returns
+///
+/// One group per grid cell in row order.
+pub fn slice_many(
+    filenames: &[&str],
+    number_tiles: Option<u32>,
+    col: Option<u32>,
+    row: Option<u32>,
+    save: bool,
+) -> Result<Vec<TileGroup>, String> {
+    let images = filenames
+        .iter()
+        .map(|name| {
+            image::open(Path::new(name)).map_err(|_| format!("can not open image {}", name))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut groups = slice_stack(&images, number_tiles, col, row)?;
+
+    if save {
+        let (prefix, suffixes) = layer_names(filenames);
+        let suffixes: Vec<&str> = suffixes.iter().map(String::as_str).collect();
+        let directory = Path::new(filenames[0])
+            .parent()
+            .unwrap_or_else(|| Path::new("."));
+        save_stack(&mut groups, &prefix, Some(directory), &suffixes, "png")
+            .map_err(|_| "can not save tiles")?;
+    }
+
+    Ok(groups)
+}
+
+/// Split aligned in-memory images on one grid.
+///
+/// The grid is the one `slice` uses: equal cells, with any remainder on the
+/// right and bottom edges left out.
+///
+/// # Errors
+///
+/// Returns an error if there are no images, their sizes differ, or the grid is
+/// invalid or finer than the image.
+pub fn slice_stack(
+    images: &[DynamicImage],
+    number_tiles: Option<u32>,
+    col: Option<u32>,
+    row: Option<u32>,
+) -> Result<Vec<TileGroup>, String> {
+    let first = images
+        .first()
+        .ok_or_else(|| "There are no images to slice.".to_string())?;
+    let (width, height) = (first.width(), first.height());
+    if let Some((i, image)) = images
+        .iter()
+        .enumerate()
+        .find(|(_, im)| (im.width(), im.height()) != (width, height))
+    {
+        return Err(format!(
+            "Layer {} is {}x{} but layer 0 is {}x{}.",
+            i,
+            image.width(),
+            image.height(),
+            width,
+            height
+        ));
+    }
+
+    let (columns, rows) = resolve_grid(number_tiles, col, row)?;
+    let (tile_w, tile_h) = (width / columns, height / rows);
+    if tile_w == 0 || tile_h == 0 {
+        return Err(format!(
+            "A {}x{} image can not be split into {} columns and {} rows.",
+            width, height, columns, rows
+        ));
+    }
+
+    let mut groups = Vec::new();
+    for r in 0..rows {
+        for c in 0..columns {
+            let (x, y) = (c * tile_w, r * tile_h);
+            let number = groups.len() as i32 + 1;
+            let position = (c as i32 + 1, r as i32 + 1);
+            let coords = (x as i32, y as i32);
+            let tiles = images
+                .iter()
+                .map(|im| {
+                    Tile::new(
+                        im.crop_imm(x, y, tile_w, tile_h),
+                        number,
+                        position,
+                        coords,
+                        None,
+                    )
+                })
+                .collect();
+            groups.push(TileGroup {
+                number,
+                position,
+                coords,
+                tiles,
+            });
+        }
+    }
+    Ok(groups)
+}
+
+/// Write grouped tiles to disk as `{prefix}_{column}_{row}_{suffix}.{format}`.
+///
+/// # Arguments
+///
+/// * `groups` - The groups to save. Each tile's `filename` is set.
+/// * `prefix` - Filename prefix of saved tiles.
+/// * `directory` - Directory to save tiles. Created if non-existent.
+/// * `suffixes` - One suffix per layer, or empty to number the layers.
+/// * `format` - Format of the saved tiles.
+///
+/// # Errors
+///
+/// Returns an error if the number of suffixes does not match the layers or a
+/// tile can not be saved.
+pub fn save_stack(
+    groups: &mut [TileGroup],
+    prefix: &str,
+    directory: Option<&Path>,
+    suffixes: &[&str],
+    format: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let current_dir = std::env::current_dir()?;
+    let dir = directory.unwrap_or(current_dir.as_path());
+    fs::create_dir_all(dir)?;
+
+    for group in groups.iter_mut() {
+        if !suffixes.is_empty() && suffixes.len() != group.tiles.len() {
+            return Err(format!(
+                "{} suffixes for {} layers.",
+                suffixes.len(),
+                group.tiles.len()
+            )
+            .into());
+        }
+        for (i, tile) in group.tiles.iter_mut().enumerate() {
+            let suffix = match suffixes.get(i) {
+                Some(suffix) => suffix.to_string(),
+                None => i.to_string(),
+            };
+            let name = tile.generate_filename(None, prefix, format, false);
+            let stem = name.file_stem().and_then(|s| s.to_str()).unwrap_or(prefix);
+            let extension = name.extension().and_then(|s| s.to_str()).unwrap_or(format);
+            let filename = dir.join(format!("{}_{}.{}", stem, suffix, extension));
+            tile.save(Some(filename), format)?;
+        }
+    }
+    Ok(())
+}
+
+/// Splits layer filenames into a shared prefix and one suffix per layer.
+fn layer_names(filenames: &[&str]) -> (String, Vec<String>) {
+    let names: Vec<String> = filenames.iter().map(|f| get_basename(f)).collect();
+    let first = &names[0];
+    let common = names
+        .iter()
+        .map(|name| {
+            first
+                .chars()
+                .zip(name.chars())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a.len_utf8())
+                .sum::<usize>()
+        })
+        .min()
+        .unwrap_or(0);
+    // Cut at the last separator so `scene_rgb` and `scene_red` share `scene`.
+    let shared = first[..common].rfind(['_', '-', '.']).unwrap_or(0);
+    let prefix = first[..shared].to_string();
+    let suffixes: Vec<String> = names
+        .iter()
+        .map(|name| {
+            name[shared..]
+                .trim_start_matches(['_', '-', '.'])
+                .to_string()
+        })
+        .collect();
+    let distinct = suffixes.iter().all(|s| !s.is_empty())
+        && suffixes
+            .iter()
+            .enumerate()
+            .all(|(i, s)| !suffixes[..i].contains(s));
+    if prefix.is_empty() || !distinct {
+        (
+            first.clone(),
+            (0..names.len()).map(|i| i.to_string()).collect(),
+        )
+    } else {
+        (prefix, suffixes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Luma, Rgba, RgbaImage};
+
+    #[test]
+    fn test_shared_grid() {
+        let rgb = DynamicImage::ImageRgba8(RgbaImage::from_fn(10, 6, |x, y| {
+            Rgba([x as u8, y as u8, 0, 255])
+        }));
+        let depth = DynamicImage::ImageLuma8(image::GrayImage::from_fn(10, 6, |x, y| {
+            Luma([(x + y * 10) as u8])
+        }));
+        let groups = slice_stack(&[rgb, depth], None, Some(3), Some(2)).unwrap();
+        assert_eq!(groups.len(), 6);
+        let group = &groups[4];
+        assert_eq!((group.position, group.coords), ((2, 2), (3, 3)));
+        assert_eq!(group.tiles[0].image.dimensions(), (3, 3));
+        assert_eq!(group.tiles[0].image.get_pixel(0, 0), Rgba([3, 3, 0, 255]));
+        assert_eq!(group.tiles[1].image.get_pixel(0, 0)[0], 33);
+        assert!(group.tiles.iter().all(|t| t.number == 5));
+
+        let small = DynamicImage::ImageRgba8(RgbaImage::new(9, 6));
+        let other = DynamicImage::ImageRgba8(RgbaImage::new(10, 6));
+        assert!(slice_stack(&[small, other], Some(4), None, None).is_err());
+    }
+
+    #[test]
+    fn test_layer_names_and_save() {
+        assert_eq!(
+            layer_names(&["a/scene_rgb.png", "a/scene_nir.png", "a/scene_red.tif"]),
+            (
+                "scene".to_string(),
+                vec!["rgb".to_string(), "nir".to_string(), "red".to_string()]
+            )
+        );
+        assert_eq!(
+            layer_names(&["first.png", "second.png"]),
+            ("first".to_string(), vec!["0".to_string(), "1".to_string()])
+        );
+
+        let directory = std::env::temp_dir().join("papercut_stack_test");
+        let layers = vec![DynamicImage::ImageRgba8(RgbaImage::new(4, 4)); 2];
+        let mut groups = slice_stack(&layers, Some(4), None, None).unwrap();
+        save_stack(&mut groups, "frame", Some(&directory), &["t0", "t1"], "png").unwrap();
+        let saved = groups[1].tiles[1].filename.clone().unwrap();
+        assert!(saved.exists());
+        assert!(save_stack(&mut groups, "frame", Some(&directory), &["t0"], "png").is_err());
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(saved.file_name().unwrap(), "frame_01_02_t1.png");
+    }
+}
@@ -0,0 +1,402 @@
+//! The `stats` module computes statistics for triaging tiles: colour averages,
+//! histograms, entropy, edge density and perceptual hashes. Busy tiles can be
+//! sent to annotators first, and near-duplicates found by comparing hashes.
+//!
+//! # Features
+//!
+//! - **Colour**: Per-channel mean, median and 256-bin histograms, gathered in a
+//!   single pass over the pixels.
+//! - **Content Measures**: Shannon entropy of brightness and the share of edge
+//!   pixels.
+//! - **Perceptual Hashes**: 64-bit difference hash (dHash) and DCT hash (pHash).
+//! - **Export**: CSV or JSON, to keep next to the manifest.
+//!
+//! # Example Usage
+//!
+//! ```no_run
+//! use papercut::{save_stats, slice_with_stats, StatsFormat};
+//! use std::path::Path;
+//!
+//! let (tiles, stats) = slice_with_stats("slide.png", None, Some(8), Some(8), true).unwrap();
+//! save_stats(&stats, Path::new("stats.csv"), StatsFormat::Csv).unwrap();
+//! let busiest = stats.iter().max_by(|a, b| a.entropy.total_cmp(&b.entropy)).unwrap();
+//! ```
+
+use image::GrayImage;
+use image::imageops::{self, FilterType};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::tile::Tile;
+use crate::{get_basename, resolve_grid, save_tiles};
+
+/// Brightness change between neighbouring pixels that counts as an edge.
+const EDGE_THRESHOLD: i32 = 32;
+
+/// Statistics of one tile. Channels are in RGBA order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TileStats {
+    /// The tile's number.
+    pub number: i32,
+    /// The tile's grid position.
+    pub position: (i32, i32),
+    /// The tile's pixel coordinates.
+    pub coords: (i32, i32),
+    /// Width and height in pixels.
+    pub size: (u32, u32),
+    /// Mean of each channel.
+    pub mean: [f64; 4],
+    /// Median of each channel.
+    pub median: [u8; 4],
+    /// 256-bin histogram of each channel.
+    pub histograms: Vec<Vec<u32>>,
+    /// Shannon entropy of the brightness histogram, in bits (0 to 8).
+    pub entropy: f64,
+    /// Share of pixels on an edge, from 0 to 1.
+    pub edge_density: f64,
+    /// Difference hash of the brightness.
+    pub dhash: u64,
+    /// DCT-based perceptual hash of the brightness.
+    pub phash: u64,
+}
+
+impl TileStats {
+    /// Computes the statistics of `tile`.
+    pub fn from_tile(tile: &Tile) -> Self {
+        let rgba = tile.image.to_rgba8();
+        let luma = tile.image.to_luma8();
+        let count = (rgba.width() * rgba.height()).max(1) as f64;
+
+        let mut histograms = vec![vec![0u32; 256]; 4];
+        let mut sums = [0u64; 4];
+        for pixel in rgba.pixels() {
+            for channel in 0..4 {
+                let value = pixel[channel];
+                histograms[channel][value as usize] += 1;
+                sums[channel] += value as u64;
+            }
+        }
+        let mean = sums.map(|sum| sum as f64 / count);
+        let mut median = [0u8; 4];
+        for (channel, histogram) in histograms.iter().enumerate() {
+            median[channel] = histogram_median(histogram);
+        }
+
+        let mut brightness = [0u32; 256];
+        for pixel in luma.pixels() {
+            brightness[pixel[0] as usize] += 1;
+        }
+        let entropy = brightness
+            .iter()
+            .filter(|&&n| n > 0)
+            .map(|&n| {
+                let p = n as f64 / count;
+                -p * p.log2()
+            })
+            .sum();
+
+        let (width, height) = luma.dimensions();
+        let mut edges = 0;
+        for y in 0..height {
+            for x in 0..width {
+                let at = |x: u32, y: u32| luma.get_pixel(x, y)[0] as i32;
+                let gx = at((x + 1).min(width - 1), y) - at(x.saturating_sub(1), y);
+                let gy = at(x, (y + 1).min(height - 1)) - at(x, y.saturating_sub(1));
+                if gx.abs() + gy.abs() > EDGE_THRESHOLD {
+                    edges += 1;
+                }
+            }
+        }
+
+        TileStats {
+            number: tile.number,
+            position: tile.position,
+            coords: tile.coords,
+            size: (width, height),
+            mean,
+            median,
+            histograms,
+            entropy,
+            edge_density: edges as f64 / count,
+            dhash: dhash(&luma),
+            phash: phash(&luma),
+        }
+    }
+
+    /// Number of differing bits between this tile's pHash and `other`'s. Tiles
+    /// within about 10 bits look alike.
+    pub fn phash_distance(&self, other: &TileStats) -> u32 {
+        (self.phash ^ other.phash).count_ones()
+    }
+}
+
+/// The format written by `save_stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsFormat {
+    /// One row per tile without histograms, hashes in hexadecimal.
+    Csv,
+    /// A list of `TileStats`, histograms included.
+    Json,
+}
+
+/// Computes statistics for every tile.
+pub fn tile_stats(tiles: &[Tile]) -> Vec<TileStats> {
+    tiles.iter().map(TileStats::from_tile).collect()
+}
+
+/// Split an image like `slice` and compute each tile's statistics.
+///
+/// Statistics are taken from each tile as it is cut, in the same pass, and
+/// tiles are saved only afterwards.
+///
+/// # Returns
+///
+/// The tiles and their statistics, in the same order.
+pub fn slice_with_stats(
+    filename: &str,
+    number_tiles: Option<u32>,
+    col: Option<u32>,
+    row: Option<u32>,
+    save: bool,
+) -> Result<(Vec<Tile>, Vec<TileStats>), String> {
+    let im =
+        image::open(Path::new(filename)).map_err(|_| format!("can not open image {}", filename))?;
+    let (columns, rows) = resolve_grid(number_tiles, col, row)?;
+    let (tile_w, tile_h) = (im.width() / columns, im.height() / rows);
+    if tile_w == 0 || tile_h == 0 {
+        return Err(format!(
+            "A {}x{} image can not be split into {} columns and {} rows.",
+            im.width(),
+            im.height(),
+            columns,
+            rows
+        ));
+    }
+
+    let mut tiles = Vec::with_capacity((columns * rows) as usize);
+    let mut stats = Vec::with_capacity(tiles.capacity());
+    for r in 0..rows {
+        for c in 0..columns {
+            let (x, y) = (c * tile_w, r * tile_h);
+            let tile = Tile::new(
+                im.crop_imm(x, y, tile_w, tile_h),
+                tiles.len() as i32 + 1,
+                (c as i32 + 1, r as i32 + 1),
+                (x as i32, y as i32),
+                None,
+            );
+            stats.push(TileStats::from_tile(&tile));
+            tiles.push(tile);
+        }
+    }
+
+    if save {
+        let prefix = get_basename(filename);
+        let directory = Path::new(filename)
+            .parent()
+            .unwrap_or_else(|| Path::new("."));
+        save_tiles(&mut tiles, &prefix, Some(directory), "png")
+            .map_err(|_| "can not save tiles")?;
+    }
+
+    Ok((tiles, stats))
+}
+
+/// Formats statistics as CSV with a header row.
+pub fn stats_to_csv(stats: &[TileStats]) -> String {
+    let mut csv = String::from(
+        "number,column,row,x,y,width,height,mean_r,mean_g,mean_b,mean_a,\
+         median_r,median_g,median_b,median_a,entropy,edge_density,dhash,phash\n",
+    );
+    for s in stats {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{:.3},{:.3},{:.3},{:.3},{},{},{},{},{:.4},{:.4},{:016x},{:016x}\n",
+            s.number,
+            s.position.0,
+            s.position.1,
+            s.coords.0,
+            s.coords.1,
+            s.size.0,
+            s.size.1,
+            s.mean[0],
+            s.mean[1],
+            s.mean[2],
+            s.mean[3],
+            s.median[0],
+            s.median[1],
+            s.median[2],
+            s.median[3],
+            s.entropy,
+            s.edge_density,
+            s.dhash,
+            s.phash
+        ));
+    }
+    csv
+}
+
+/// Writes statistics to `path`.
+///
+/// # Errors
+///
+/// Returns an error if the file can not be written.
+pub fn save_stats(
+    stats: &[TileStats],
+    path: &Path,
+    format: StatsFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let text = match format {
+        StatsFormat::Csv => stats_to_csv(stats),
+        StatsFormat::Json => serde_json::to_string_pretty(stats)?,
+    };
+    fs::write(path, text)?;
+    Ok(())
+}
+
+/// The lower median of the values counted in `histogram`.
+fn histogram_median(histogram: &[u32]) -> u8 {
+    let total: u64 = histogram.iter().map(|&n| n as u64).sum();
+    let mut seen = 0;
+    for (value, &n) in histogram.iter().enumerate() {
+        seen += n as u64;
+        if seen * 2 >= total && n > 0 {
+            return value as u8;
+        }
+    }
+    0
+}
+
+/// Difference hash: whether each of 8x8 brightness samples is brighter than its
+/// right neighbour.
+fn dhash(luma: &GrayImage) -> u64 {
+    let small = imageops::resize(luma, 9, 8, FilterType::Triangle);
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}
+
+/// Perceptual hash: whether each of the 8x8 lowest DCT frequencies of a 32x32
+/// brightness image is above their median.
+fn phash(luma: &GrayImage) -> u64 {
+    const N: usize = 32;
+    let small = imageops::resize(luma, N as u32, N as u32, FilterType::Triangle);
+    let cosines: Vec<f64> = (0..8 * N)
+        .map(|i| {
+            let (k, n) = (i / N, i % N);
+            (std::f64::consts::PI * k as f64 * (2 * n + 1) as f64 / (2 * N) as f64).cos()
+        })
+        .collect();
+    // Separable DCT-II, rows then columns, keeping the 8 lowest frequencies.
+    let mut rows = [[0.0; 8]; N];
+    for (y, row) in rows.iter_mut().enumerate() {
+        for (k, value) in row.iter_mut().enumerate() {
+            *value = (0..N)
+                .map(|x| small.get_pixel(x as u32, y as u32)[0] as f64 * cosines[k * N + x])
+                .sum();
+        }
+    }
+    let mut coefficients = [0.0; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            coefficients[v * 8 + u] = (0..N).map(|y| rows[y][u] * cosines[v * N + y]).sum();
+        }
+    }
+    // The DC term only measures overall brightness, so it is left out of the median.
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let median = sorted[sorted.len() / 2];
+    coefficients
+        .iter()
+        .fold(0, |hash, &c| (hash << 1) | (c > median) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, Rgba, RgbaImage};
+
+    fn tile(image: RgbaImage) -> Tile {
+        Tile::new(DynamicImage::ImageRgba8(image), 1, (1, 1), (0, 0), None)
+    }
+
+    #[test]
+    fn test_colour_and_content() {
+        // Left half black, right half white.
+        let halves = tile(RgbaImage::from_fn(8, 8, |x, _| {
+            let v = if x < 4 { 0 } else { 255 };
+            Rgba([v, v, v, 255])
+        }));
+        let stats = TileStats::from_tile(&halves);
+        assert_eq!(stats.mean, [127.5, 127.5, 127.5, 255.0]);
+        assert_eq!(stats.median, [0, 0, 0, 255]);
+        assert_eq!(stats.histograms[0][255], 32);
+        assert!((stats.entropy - 1.0).abs() < 1e-9);
+        // Columns 3 and 4 straddle the edge.
+        assert_eq!(stats.edge_density, 0.25);
+
+        let flat = TileStats::from_tile(&tile(RgbaImage::from_pixel(8, 8, Rgba([9, 9, 9, 255]))));
+        assert_eq!((flat.entropy, flat.edge_density), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_hashes_and_export() {
+        let gradient = |scale: u32| {
+            tile(RgbaImage::from_fn(64 * scale, 64 * scale, |x, y| {
+                let v = ((x / scale + 2 * (y / scale)) % 256) as u8;
+                Rgba([v, v, v, 255])
+            }))
+        };
+        let small = TileStats::from_tile(&gradient(1));
+        let large = TileStats::from_tile(&gradient(2));
+        let other = TileStats::from_tile(&tile(RgbaImage::from_fn(64, 64, |x, y| {
+            let v = if (x / 8 + y / 8) % 2 == 0 { 0 } else { 255 };
+            Rgba([v, v, v, 255])
+        })));
+        // Resizing keeps the hashes close; different content does not.
+        assert!(small.phash_distance(&large) <= 4);
+        assert!((small.dhash ^ large.dhash).count_ones() <= 4);
+        assert!(small.phash_distance(&other) > 10);
+
+        let csv = stats_to_csv(std::slice::from_ref(&small));
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].split(',').count(), lines[1].split(',').count());
+        assert!(lines[1].ends_with(&format!("{:016x}", small.phash)));
+
+        let json = serde_json::to_string(std::slice::from_ref(&small)).unwrap();
+        let parsed: Vec<TileStats> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed[0], small);
+    }
+
+    #[test]
+    fn test_slice_with_stats_matches_slice() {
+        let directory = std::env::temp_dir().join("papercut_stats_test");
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("slide.png");
+        RgbaImage::from_fn(10, 7, |x, y| Rgba([x as u8 * 20, y as u8 * 30, 0, 255]))
+            .save(&path)
+            .unwrap();
+        let filename = path.to_str().unwrap();
+
+        let (tiles, stats) = slice_with_stats(filename, None, Some(3), Some(2), false).unwrap();
+        let sliced = crate::slice(filename, None, Some(3), Some(2), false).unwrap();
+        assert_eq!(tiles.len(), sliced.len());
+        for (tile, expected) in tiles.iter().zip(&sliced) {
+            assert_eq!(
+                (tile.position, tile.coords),
+                (expected.position, expected.coords)
+            );
+            assert_eq!(tile.image, expected.image);
+        }
+        assert_eq!(stats, tile_stats(&sliced));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
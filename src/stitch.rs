@@ -0,0 +1,316 @@
+//! The `stitch` module reassembles tiles that carry no naming information.
+//! Given a bag of equally sized tiles, it recovers their grid arrangement by
+//! comparing the pixel strips along their borders.
+//!
+//! # Features
+//!
+//! - **Edge Compatibility**: Scores how well two tiles fit side by side or on top
+//!   of each other using the predicted continuation of their border pixels.
+//! - **Grid Solver**: Tries every grid shape that fits the number of tiles and
+//!   greedily places tiles from every possible top-left corner.
+//! - **Confidence**: Reports how clearly each chosen neighbour beat the runner-up.
+//!
+//! # Example Usage
+//!
+//! ```no_run
+//! use papercut::{join, open_images, stitch_tiles};
+//! use std::path::Path;
+//!
+//! let images = open_images(Path::new("./scrambled")).unwrap();
+//! let stitched = stitch_tiles(&images).unwrap();
+//! println!("confidence: {:.2}", stitched.confidence);
+//! let (width, height) = stitched.size();
+//! let combined = join(&stitched.tiles, width, height).unwrap();
+//! ```
+
+use image::{DynamicImage, RgbaImage};
+
+use crate::tile::Tile;
+
+/// The result of `stitch_tiles`: positioned tiles and how sure the solver is about them.
+#[derive(Debug, Clone)]
+pub struct Stitched {
+    /// Tiles with `position` and `coords` set from the recovered grid, in row order.
+    pub tiles: Vec<Tile>,
+    /// Number of columns in the recovered grid.
+    pub columns: u32,
+    /// Number of rows in the recovered grid.
+    pub rows: u32,
+    /// Mean margin between each chosen neighbour and the next best candidate, in `0.0..=1.0`.
+    pub confidence: f64,
+}
+
+impl Stitched {
+    /// Returns the size `(width, height)` of the reassembled image.
+    pub fn size(&self) -> (u32, u32) {
+        let (w, h) = self
+            .tiles
+            .first()
+            .map(|t| (t.image.width(), t.image.height()))
+            .unwrap_or((0, 0));
+        (w * self.columns, h * self.rows)
+    }
+}
+
+/// Pairwise edge dissimilarities between tiles. `right[a][b]` is the cost of
+/// placing `b` to the right of `a`, `below[a][b]` the cost of placing `b` below `a`.
+struct Compatibility {
+    right: Vec<Vec<f64>>,
+    below: Vec<Vec<f64>>,
+}
+
+/// Find the grid arrangement of tiles with no naming information.
+///
+/// Every grid shape `columns x rows` with `columns * rows == images.len()` is
+/// considered. For each shape and each tile as the top-left corner, the grid is
+/// filled in row order, always picking the unused tile that best continues its
+/// left and top neighbours. The arrangement with the lowest mean edge cost wins.
+///
+/// # Arguments
+///
+/// * `images` - The tiles to arrange, all of the same size.
+///
+/// # Returns
+///
+/// A `Stitched` holding `Tile`s ready for `join`.
+///
+/// # Errors
+///
+/// Returns an error if `images` is empty, the tiles differ in size, or they have
+/// no pixels.
+pub fn stitch_tiles(images: &[DynamicImage]) -> Result<Stitched, String> {
+    let first = images.first().ok_or("There are no tiles to stitch.")?;
+    let (tile_w, tile_h) = (first.width(), first.height());
+    if images
+        .iter()
+        .any(|im| im.width() != tile_w || im.height() != tile_h)
+    {
+        return Err("All tiles must have the same size to be stitched.".to_string());
+    }
+    if tile_w == 0 || tile_h == 0 {
+        return Err("Empty tiles can not be stitched.".to_string());
+    }
+
+    let pixels: Vec<RgbaImage> = images.iter().map(|im| im.to_rgba8()).collect();
+    let compatibility = compatibility(&pixels);
+    let n = images.len();
+
+    let mut best: Option<(f64, u32, u32, Vec<usize>)> = None;
+    for columns in (1..=n).filter(|c| n.is_multiple_of(*c)) {
+        let rows = n / columns;
+        for start in 0..n {
+            let (cost, order) = place_greedy(&compatibility, columns, rows, start);
+            let edges = columns * (rows - 1) + rows * (columns - 1);
+            let mean = if edges > 0 { cost / edges as f64 } else { 0.0 };
+            if best.as_ref().is_none_or(|b| mean < b.0) {
+                best = Some((mean, columns as u32, rows as u32, order));
+            }
+        }
+    }
+    let (_, columns, rows, order) = best.ok_or("Could not find a grid arrangement.")?;
+
+    let confidence = confidence(&compatibility, &order, columns as usize);
+    let tiles = order
+        .iter()
+        .enumerate()
+        .map(|(i, &index)| {
+            let (column, row) = (i as u32 % columns, i as u32 / columns);
+            Tile::new(
+                images[index].clone(),
+                i as i32 + 1,
+                (column as i32 + 1, row as i32 + 1),
+                ((column * tile_w) as i32, (row * tile_h) as i32),
+                None,
+            )
+        })
+        .collect();
+
+    Ok(Stitched {
+        tiles,
+        columns,
+        rows,
+        confidence,
+    })
+}
+
+/// Fills a `columns x rows` grid in row order starting from `start`. Returns the
+/// summed edge cost and the tile index placed in each cell.
+fn place_greedy(
+    compatibility: &Compatibility,
+    columns: usize,
+    rows: usize,
+    start: usize,
+) -> (f64, Vec<usize>) {
+    let n = compatibility.right.len();
+    let mut used = vec![false; n];
+    let mut order = Vec::with_capacity(n);
+    let mut total = 0.0;
+    used[start] = true;
+    order.push(start);
+
+    for cell in 1..columns * rows {
+        let (column, row) = (cell % columns, cell / columns);
+        let cost_of = |candidate: usize| {
+            let mut cost = 0.0;
+            if column > 0 {
+                cost += compatibility.right[order[cell - 1]][candidate];
+            }
+            if row > 0 {
+                cost += compatibility.below[order[cell - columns]][candidate];
+            }
+            cost
+        };
+        let (choice, cost) = (0..n)
+            .filter(|&candidate| !used[candidate])
+            .map(|candidate| (candidate, cost_of(candidate)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .expect("a free tile remains for every cell");
+        used[choice] = true;
+        order.push(choice);
+        total += cost;
+    }
+
+    (total, order)
+}
+
+/// Mean, over every adjacency in the arrangement, of how much better the chosen
+/// neighbour scores than the best alternative.
+fn confidence(compatibility: &Compatibility, order: &[usize], columns: usize) -> f64 {
+    let margin = |costs: &[f64], chosen: usize| {
+        let runner_up = costs
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| i != chosen)
+            .map(|(_, &c)| c)
+            .fold(f64::INFINITY, f64::min);
+        if !runner_up.is_finite() || runner_up <= 0.0 {
+            return 0.0;
+        }
+        (1.0 - costs[chosen] / runner_up).clamp(0.0, 1.0)
+    };
+
+    let mut sum = 0.0;
+    let mut count = 0;
+    for (cell, &tile) in order.iter().enumerate() {
+        if cell % columns + 1 < columns {
+            sum += margin(&compatibility.right[tile], order[cell + 1]);
+            count += 1;
+        }
+        if cell + columns < order.len() {
+            sum += margin(&compatibility.below[tile], order[cell + columns]);
+            count += 1;
+        }
+    }
+    if count == 0 { 1.0 } else { sum / count as f64 }
+}
+
+fn compatibility(pixels: &[RgbaImage]) -> Compatibility {
+    let n = pixels.len();
+    let mut right = vec![vec![f64::INFINITY; n]; n];
+    let mut below = vec![vec![f64::INFINITY; n]; n];
+    let strips: Vec<Strips> = pixels.iter().map(Strips::new).collect();
+    for a in 0..n {
+        for b in 0..n {
+            if a != b {
+                right[a][b] = strip_cost(&strips[a].right, &strips[b].left);
+                below[a][b] = strip_cost(&strips[a].bottom, &strips[b].top);
+            }
+        }
+    }
+    Compatibility { right, below }
+}
+
+/// The two outermost pixel lines on each side of a tile, outermost first.
+struct Strips {
+    left: [Vec<[f64; 4]>; 2],
+    right: [Vec<[f64; 4]>; 2],
+    top: [Vec<[f64; 4]>; 2],
+    bottom: [Vec<[f64; 4]>; 2],
+}
+
+impl Strips {
+    fn new(image: &RgbaImage) -> Self {
+        let (w, h) = image.dimensions();
+        let px = |x: u32, y: u32| image.get_pixel(x, y).0.map(f64::from);
+        let column = |x: u32| (0..h).map(|y| px(x, y)).collect::<Vec<_>>();
+        let row = |y: u32| (0..w).map(|x| px(x, y)).collect::<Vec<_>>();
+        Strips {
+            left: [column(0), column(1.min(w - 1))],
+            right: [column(w - 1), column(w.saturating_sub(2))],
+            top: [row(0), row(1.min(h - 1))],
+            bottom: [row(h - 1), row(h.saturating_sub(2))],
+        }
+    }
+}
+
+/// Prediction-based dissimilarity between two facing edges: each side's border
+/// is extrapolated one pixel across the seam and compared with the other side.
+fn strip_cost(a: &[Vec<[f64; 4]>; 2], b: &[Vec<[f64; 4]>; 2]) -> f64 {
+    let mut cost = 0.0;
+    for i in 0..a[0].len() {
+        for c in 0..4 {
+            let predicted_b = 2.0 * a[0][i][c] - a[1][i][c];
+            let predicted_a = 2.0 * b[0][i][c] - b[1][i][c];
+            cost += (predicted_b - b[0][i][c]).powi(2) + (predicted_a - a[0][i][c]).powi(2);
+        }
+    }
+    cost / a[0].len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::join;
+    use image::Rgba;
+
+    fn pattern(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+            let (fx, fy) = (x as f64, y as f64);
+            Rgba([
+                (128.0 + 100.0 * (fx / 9.0).sin()) as u8,
+                (128.0 + 100.0 * (fy / 7.0).cos()) as u8,
+                ((fx * fy) / 20.0) as u8,
+                255,
+            ])
+        }))
+    }
+
+    #[test]
+    fn test_stitch_recovers_shuffled_grid() {
+        let source = pattern(60, 40);
+        let mut images = Vec::new();
+        for row in 0..2 {
+            for column in 0..3 {
+                images.push(source.crop_imm(column * 20, row * 20, 20, 20));
+            }
+        }
+        // Scramble the order the tiles are handed over in.
+        let shuffled: Vec<DynamicImage> = [4, 0, 5, 2, 1, 3]
+            .iter()
+            .map(|&i| images[i].clone())
+            .collect();
+
+        let stitched = stitch_tiles(&shuffled).unwrap();
+        assert_eq!((stitched.columns, stitched.rows), (3, 2));
+        assert!(stitched.confidence > 0.5);
+
+        let (width, height) = stitched.size();
+        let joined = join(&stitched.tiles, width, height).unwrap();
+        assert_eq!(joined.to_rgba8(), source.to_rgba8());
+    }
+
+    #[test]
+    fn test_stitch_rejects_mixed_sizes() {
+        let images = vec![pattern(10, 10), pattern(10, 12)];
+        assert!(stitch_tiles(&images).is_err());
+        assert!(stitch_tiles(&[]).is_err());
+    }
+
+    #[test]
+    fn test_stitch_rejects_empty_tiles() {
+        let images = vec![pattern(0, 10), pattern(0, 10)];
+        assert!(stitch_tiles(&images).is_err());
+        let images = vec![pattern(10, 0)];
+        assert!(stitch_tiles(&images).is_err());
+    }
+}
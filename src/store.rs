@@ -0,0 +1,427 @@
+//! The `store` module keeps tiles in a content-addressed directory. Each tile is
+//! stored once under the hash of its content, so identical tiles shared between
+//! images or between versions of one image take no extra space. Manifests map
+//! grid positions to hashes, and reference counts tell which tiles are no longer
+//! used by any manifest.
+//!
+//! # Features
+//!
+//! - **Content Hashing**: BLAKE3 or SHA-256 of the encoded file or of the raw
+//!   pixels.
+//! - **Deduplicated Saving**: Tiles already in the store are not written again.
+//! - **Garbage Collection**: References are recorded per manifest id, so
+//!   releasing a manifest drops exactly its references, and `gc` deletes tiles
+//!   nothing refers to.
+//!
+//! # Example Usage
+//!
+//! ```no_run
+//! use papercut::{slice, HashAlgorithm, HashInput, TileStore};
+//! use std::path::Path;
+//!
+//! let mut store = TileStore::open(Path::new("tiles"), HashAlgorithm::Blake3, HashInput::Pixels, "png").unwrap();
+//! let mut tiles = slice("map_v2.png", None, Some(8), Some(8), false).unwrap();
+//! let manifest = store.save_tiles("map_v2", &mut tiles).unwrap();
+//! manifest.save(Path::new("tiles/map_v2.json")).unwrap();
+//!
+//! store.release("map_v1").unwrap();
+//! let removed = store.gc().unwrap();
+//! ```
+
+use image::{DynamicImage, ImageFormat};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use crate::manifest::{Manifest, ManifestEntry};
+use crate::tile::Tile;
+
+/// Name of the reference count index inside a store.
+const INDEX_FILE: &str = "refs.json";
+
+/// The hash function used to name tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HashAlgorithm {
+    Blake3,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// The lowercase hexadecimal digest of `bytes`.
+    pub fn digest(&self, bytes: &[u8]) -> String {
+        match self {
+            HashAlgorithm::Blake3 => blake3::hash(bytes).to_hex().to_string(),
+            HashAlgorithm::Sha256 => Sha256::digest(bytes)
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+        }
+    }
+}
+
+/// What a tile's hash is computed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HashInput {
+    /// The encoded file. Identical pixels saved with other encoder settings
+    /// hash differently.
+    Encoded,
+    /// The size and RGBA pixels, independent of the file format.
+    Pixels,
+}
+
+/// The hash of a tile's content.
+///
+/// # Errors
+///
+/// Returns an error if `input` is `Encoded` and the tile can not be encoded as
+/// `format`.
+pub fn tile_hash(
+    tile: &Tile,
+    algorithm: HashAlgorithm,
+    input: HashInput,
+    format: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    Ok(match input {
+        HashInput::Encoded => algorithm.digest(&encode(&tile.image, format)?),
+        HashInput::Pixels => algorithm.digest(&pixel_bytes(&tile.image)),
+    })
+}
+
+/// Where a tile with `hash` is stored under `directory`: in a subdirectory named
+/// after the first two hex digits, so no directory grows too large.
+pub fn hash_path(directory: &Path, hash: &str, format: &str) -> PathBuf {
+    let ext = format.to_lowercase().replace("jpeg", "jpg");
+    directory
+        .join(&hash[..2.min(hash.len())])
+        .join(format!("{}.{}", hash, ext))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct StoreIndex {
+    algorithm: HashAlgorithm,
+    input: HashInput,
+    format: String,
+    /// Hashes of the stored tiles.
+    objects: BTreeSet<String>,
+    /// The hashes each manifest id refers to, one per tile.
+    refs: BTreeMap<String, Vec<String>>,
+}
+
+/// A directory of tiles named by content hash. References are held by manifest
+/// ids, such as a version name or the manifest's path.
+#[derive(Debug, Clone)]
+pub struct TileStore {
+    root: PathBuf,
+    index: StoreIndex,
+}
+
+impl TileStore {
+    /// Opens the store at `root`, creating it if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index can not be read, or the store was created
+    /// with a different algorithm, hash input or format.
+    pub fn open(
+        root: &Path,
+        algorithm: HashAlgorithm,
+        input: HashInput,
+        format: &str,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = root.join(INDEX_FILE);
+        let index = if path.exists() {
+            let index: StoreIndex = serde_json::from_str(&fs::read_to_string(&path)?)?;
+            if (index.algorithm, index.input, index.format.as_str()) != (algorithm, input, format) {
+                return Err(format!(
+                    "store {} uses {:?} of {:?} as {}",
+                    root.display(),
+                    index.algorithm,
+                    index.input,
+                    index.format
+                )
+                .into());
+            }
+            index
+        } else {
+            fs::create_dir_all(root)?;
+            StoreIndex {
+                algorithm,
+                input,
+                format: format.to_string(),
+                objects: BTreeSet::new(),
+                refs: BTreeMap::new(),
+            }
+        };
+        Ok(TileStore {
+            root: root.to_path_buf(),
+            index,
+        })
+    }
+
+    /// Number of tiles, over all manifests, referring to `hash`.
+    pub fn ref_count(&self, hash: &str) -> usize {
+        self.index
+            .refs
+            .values()
+            .map(|hashes| hashes.iter().filter(|h| *h == hash).count())
+            .sum()
+    }
+
+    /// Whether references are held under `id`.
+    pub fn holds(&self, id: &str) -> bool {
+        self.index.refs.contains_key(id)
+    }
+
+    /// Number of distinct tiles in the store.
+    pub fn len(&self) -> usize {
+        self.index.objects.len()
+    }
+
+    /// Whether the store holds no tiles.
+    pub fn is_empty(&self) -> bool {
+        self.index.objects.is_empty()
+    }
+
+    /// Stores `tiles`, writing only those not already present, and records one
+    /// reference per tile under `id`. References previously held by `id` are
+    /// replaced. Each tile's `filename` is set to its stored file. Empty tiles
+    /// marked with a `background` are recorded but not stored.
+    ///
+    /// # Returns
+    ///
+    /// A manifest mapping each tile's position to its hash.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a tile can not be encoded or written.
+    pub fn save_tiles(
+        &mut self,
+        id: &str,
+        tiles: &mut [Tile],
+    ) -> Result<Manifest, Box<dyn std::error::Error>> {
+        let mut hashes = Vec::with_capacity(tiles.len());
+        for tile in tiles.iter_mut() {
+            if tile.background.is_some() {
+                hashes.push(None);
+                continue;
+            }
+            let encoded = encode(&tile.image, &self.index.format)?;
+            let hash = match self.index.input {
+                HashInput::Encoded => self.index.algorithm.digest(&encoded),
+                HashInput::Pixels => self.index.algorithm.digest(&pixel_bytes(&tile.image)),
+            };
+            let path = hash_path(&self.root, &hash, &self.index.format);
+            if !path.exists() {
+                fs::create_dir_all(path.parent().unwrap_or(&self.root))?;
+                fs::write(&path, encoded)?;
+            }
+            self.index.objects.insert(hash.clone());
+            tile.filename = Some(path);
+            hashes.push(Some(hash));
+        }
+        self.index
+            .refs
+            .insert(id.to_string(), hashes.iter().flatten().cloned().collect());
+        self.write_index()?;
+
+        let mut manifest = Manifest::from_tiles(tiles);
+        for (entry, hash) in manifest.tiles.iter_mut().zip(hashes) {
+            entry.hash = hash;
+        }
+        Ok(manifest)
+    }
+
+    /// Drops the references held under `id`. Releasing an id twice, or one this
+    /// store never recorded, changes nothing.
+    ///
+    /// # Returns
+    ///
+    /// Whether `id` held any references.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index can not be written.
+    pub fn release(&mut self, id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        if self.index.refs.remove(id).is_none() {
+            return Ok(false);
+        }
+        self.write_index()?;
+        Ok(true)
+    }
+
+    /// Deletes every tile without references. Returns the number deleted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a file can not be deleted or the index written.
+    pub fn gc(&mut self) -> Result<usize, Box<dyn std::error::Error>> {
+        let used: BTreeSet<&String> = self.index.refs.values().flatten().collect();
+        let unused: Vec<String> = self
+            .index
+            .objects
+            .iter()
+            .filter(|hash| !used.contains(hash))
+            .cloned()
+            .collect();
+        for hash in &unused {
+            let path = hash_path(&self.root, hash, &self.index.format);
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
+            // Drop the subdirectory once it is empty
+            if let Some(parent) = path.parent() {
+                let _ = fs::remove_dir(parent);
+            }
+            self.index.objects.remove(hash);
+        }
+        self.write_index()?;
+        Ok(unused.len())
+    }
+
+    fn write_index(&self) -> Result<(), Box<dyn std::error::Error>> {
+        fs::write(
+            self.root.join(INDEX_FILE),
+            serde_json::to_string_pretty(&self.index)?,
+        )?;
+        Ok(())
+    }
+}
+
+impl ManifestEntry {
+    /// The stored file of a content-addressed entry in the store at `root`.
+    pub fn hash_path(&self, root: &Path, format: &str) -> Option<PathBuf> {
+        self.hash.as_ref().map(|hash| hash_path(root, hash, format))
+    }
+}
+
+/// Encodes `image` in the format named by the extension `format`.
+pub(crate) fn encode(
+    image: &DynamicImage,
+    format: &str,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let format = ImageFormat::from_extension(format)
+        .ok_or_else(|| format!("unknown image format {}", format))?;
+    let mut bytes = Vec::new();
+    image.write_to(&mut Cursor::new(&mut bytes), format)?;
+    Ok(bytes)
+}
+
+/// The size followed by the RGBA pixels.
+fn pixel_bytes(image: &DynamicImage) -> Vec<u8> {
+    let rgba = image.to_rgba8();
+    let mut bytes = Vec::with_capacity(8 + rgba.as_raw().len());
+    bytes.extend_from_slice(&rgba.width().to_le_bytes());
+    bytes.extend_from_slice(&rgba.height().to_le_bytes());
+    bytes.extend_from_slice(rgba.as_raw());
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::slice_image_by_size;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn test_digests() {
+        assert_eq!(
+            HashAlgorithm::Sha256.digest(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            HashAlgorithm::Blake3.digest(b"abc"),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        let tile = Tile::new(
+            DynamicImage::ImageRgba8(RgbaImage::new(2, 2)),
+            1,
+            (1, 1),
+            (0, 0),
+            None,
+        );
+        let hash = tile_hash(&tile, HashAlgorithm::Blake3, HashInput::Pixels, "png").unwrap();
+        assert_eq!(
+            hash_path(Path::new("store"), &hash, "png"),
+            Path::new("store")
+                .join(&hash[..2])
+                .join(format!("{}.png", hash))
+        );
+    }
+
+    #[test]
+    fn test_dedup_and_gc() {
+        let root = std::env::temp_dir().join("papercut_store_test");
+        let _ = fs::remove_dir_all(&root);
+        let open = || TileStore::open(&root, HashAlgorithm::Sha256, HashInput::Encoded, "png");
+
+        // Version 1: four tiles, three of them identical.
+        let v1 = DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 8, |x, y| {
+            if x >= 4 && y >= 4 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 255, 255])
+            }
+        }));
+        let mut store = open().unwrap();
+        let mut tiles = slice_image_by_size(&v1, 4, 4).unwrap();
+        let first = store.save_tiles("v1", &mut tiles).unwrap();
+        assert_eq!(store.len(), 2);
+        let blue = first.tiles[0].hash.clone().unwrap();
+        assert_eq!(store.ref_count(&blue), 3);
+        assert_eq!(tiles[1].filename, first.tiles[0].hash_path(&root, "png"));
+
+        // Version 2 is all blue and shares its tiles with version 1.
+        let v2 = DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 8, Rgba([0, 0, 255, 255])));
+        let mut store = open().unwrap();
+        let second = store
+            .save_tiles("v2", &mut slice_image_by_size(&v2, 4, 4).unwrap())
+            .unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.ref_count(&blue), 7);
+
+        assert!(store.release("v1").unwrap());
+        assert_eq!(store.gc().unwrap(), 1);
+        assert_eq!(store.len(), 1);
+        let reopened = open().unwrap();
+        assert_eq!(reopened.ref_count(&blue), 4);
+        assert!(second.tiles[3].hash_path(&root, "png").unwrap().exists());
+        assert!(TileStore::open(&root, HashAlgorithm::Blake3, HashInput::Encoded, "png").is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_release_is_idempotent() {
+        let root = std::env::temp_dir().join("papercut_store_release_test");
+        let _ = fs::remove_dir_all(&root);
+        let mut store =
+            TileStore::open(&root, HashAlgorithm::Blake3, HashInput::Pixels, "png").unwrap();
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 4, Rgba([9, 9, 9, 255])));
+        let a = store
+            .save_tiles("a", &mut slice_image_by_size(&image, 4, 4).unwrap())
+            .unwrap();
+        store
+            .save_tiles("b", &mut slice_image_by_size(&image, 4, 4).unwrap())
+            .unwrap();
+        let hash = a.tiles[0].hash.clone().unwrap();
+        assert_eq!(store.ref_count(&hash), 4);
+
+        // Releasing A twice, or an id this store never saw, leaves B's tiles alone.
+        assert!(store.release("a").unwrap());
+        assert!(!store.release("a").unwrap());
+        assert!(!store.release("elsewhere").unwrap());
+        assert_eq!(store.ref_count(&hash), 2);
+        assert_eq!(store.gc().unwrap(), 0);
+        assert!(a.tiles[0].hash_path(&root, "png").unwrap().exists());
+
+        // Saving under an existing id replaces its references.
+        store
+            .save_tiles("b", &mut slice_image_by_size(&image, 8, 4).unwrap())
+            .unwrap();
+        assert_eq!((store.ref_count(&hash), store.gc().unwrap()), (0, 1));
+        assert!(store.holds("b") && !store.holds("a"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,489 @@
+//! The `stream` module reassembles tiles into very large images without allocating
+//! the full canvas. Tiles are walked in row order and the output is encoded one
+//! scanline at a time, so only the tiles overlapping the current scanline are kept
+//! in memory.
+//!
+//! # Features
+//!
+//! - **Streaming Join**: Combine tiles into a PNG or TIFF of arbitrary size.
+//! - **Lazy Loading**: Tiles read from a directory or manifest are opened only when
+//!   their first row is reached and dropped after their last one.
+//!
+//! # Example Usage
+//!
+//! ```no_run
+//! use papercut::{join_streaming, StreamFormat, TileSource};
+//! use std::path::{Path, PathBuf};
+//!
+//! join_streaming(
+//!     TileSource::Directory(PathBuf::from("./output")),
+//!     Path::new("combined.tif"),
+//!     StreamFormat::Tiff,
+//! )
+//! .unwrap();
+//! ```
+
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tiff::encoder::{TiffEncoder, TiffKind, colortype};
+
+use crate::get_image_column_row;
+use crate::manifest::Manifest;
+use crate::tile::Tile;
+
+/// Where `join_streaming` reads its tiles from.
+pub enum TileSource {
+    /// A directory of tiles named like the output of `save_tiles`.
+    Directory(PathBuf),
+    /// A manifest written by `Manifest::save`.
+    Manifest(PathBuf),
+    /// Tiles already in memory.
+    Tiles(Vec<Tile>),
+}
+
+/// Output encodings supported by `join_streaming`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFormat {
+    /// 8-bit RGBA PNG.
+    Png,
+    /// 8-bit RGBA TIFF, written as BigTIFF when the pixel data exceeds 4 GiB.
+    Tiff,
+}
+
+impl StreamFormat {
+    /// Guesses the format from a file extension (`png`, `tif` or `tiff`).
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "png" => Some(StreamFormat::Png),
+            "tif" | "tiff" => Some(StreamFormat::Tiff),
+            _ => None,
+        }
+    }
+}
+
+/// A tile whose pixels are loaded on demand.
+struct PendingTile {
+    coords: (u32, u32),
+    size: (u32, u32),
+    /// Content rectangle of a padded tile; only this part is written.
+    inner: Option<(u32, u32, u32, u32)>,
+    source: PendingSource,
+}
+
+enum PendingSource {
+    File(PathBuf),
+    Image(DynamicImage),
+    /// A sparse manifest entry of a single colour.
+    Fill([u8; 4]),
+}
+
+impl PendingTile {
+    fn load(self) -> Result<(PendingTile, RgbaImage), Box<dyn std::error::Error>> {
+        let image = match &self.source {
+            PendingSource::File(path) => image::open(path)?.to_rgba8(),
+            PendingSource::Image(image) => image.to_rgba8(),
+            PendingSource::Fill(color) => {
+                RgbaImage::from_pixel(self.size.0, self.size.1, Rgba(*color))
+            }
+        };
+        let (x, y, w, h) = self.inner.unwrap_or((0, 0, self.size.0, self.size.1));
+        if x + w > image.width() || y + h > image.height() || (w, h) != self.size {
+            return Err(format!(
+                "tile at {:?} is {:?}, expected {:?}",
+                self.coords,
+                image.dimensions(),
+                self.size
+            )
+            .into());
+        }
+        let image = match self.inner {
+            Some(_) => image::imageops::crop_imm(&image, x, y, w, h).to_image(),
+            None => image,
+        };
+        Ok((self, image))
+    }
+
+    fn bottom(&self) -> u32 {
+        self.coords.1 + self.size.1
+    }
+}
+
+/// Combine tiles into a single image file, encoding it scanline by scanline.
+///
+/// Unlike `join`, the combined image is never held in memory. Tiles are sorted by
+/// their `coords` and each one is opened when the first scanline it covers is
+/// written and released after its last one; for a regular grid this means only one
+/// row of tiles is resident at a time. Pixels not covered by any tile are left
+/// transparent.
+///
+/// # Arguments
+///
+/// * `source` - Where to read the tiles from.
+/// * `output` - Path of the file to write.
+/// * `format` - Encoding of the output file.
+///
+/// # Errors
+///
+/// Returns an error if the tiles can not be read, do not match their recorded
+/// size, or the output can not be written.
+pub fn join_streaming(
+    source: TileSource,
+    output: &Path,
+    format: StreamFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut pending = pending_tiles(source)?;
+    if pending.is_empty() {
+        return Err("no tiles to join".into());
+    }
+    pending.sort_by_key(|tile| (tile.coords.1, tile.coords.0));
+
+    let width = pending
+        .iter()
+        .map(|t| t.coords.0 + t.size.0)
+        .max()
+        .unwrap_or(0);
+    let height = pending.iter().map(PendingTile::bottom).max().unwrap_or(0);
+
+    let mut scanlines = Scanlines {
+        pending: pending.into_iter().peekable(),
+        active: Vec::new(),
+        width,
+        y: 0,
+    };
+
+    let writer = BufWriter::new(File::create(output)?);
+    match format {
+        StreamFormat::Png => write_png(writer, width, height, &mut scanlines),
+        StreamFormat::Tiff => {
+            let bytes = u64::from(width) * u64::from(height) * 4;
+            if bytes > u64::from(u32::MAX) {
+                write_tiff(TiffEncoder::new_big(writer)?, width, height, &mut scanlines)
+            } else {
+                write_tiff(TiffEncoder::new(writer)?, width, height, &mut scanlines)
+            }
+        }
+    }
+}
+
+/// Produces the output image one RGBA scanline at a time.
+struct Scanlines<I: Iterator<Item = PendingTile>> {
+    pending: std::iter::Peekable<I>,
+    active: Vec<(PendingTile, RgbaImage)>,
+    width: u32,
+    y: u32,
+}
+
+impl<I: Iterator<Item = PendingTile>> Scanlines<I> {
+    fn next_into(&mut self, row: &mut [u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let y = self.y;
+        self.active.retain(|(tile, _)| tile.bottom() > y);
+        while self.pending.peek().is_some_and(|t| t.coords.1 <= y) {
+            let tile = self.pending.next().unwrap();
+            if tile.bottom() > y {
+                self.active.push(tile.load()?);
+            }
+        }
+
+        row.fill(0);
+        for (tile, image) in &self.active {
+            let line = image.as_raw();
+            let stride = tile.size.0 as usize * 4;
+            let start = (y - tile.coords.1) as usize * stride;
+            let x = tile.coords.0 as usize * 4;
+            let end = (x + stride).min(self.width as usize * 4);
+            row[x..end].copy_from_slice(&line[start..start + end - x]);
+        }
+        self.y += 1;
+        Ok(())
+    }
+}
+
+fn write_png<W: Write, I: Iterator<Item = PendingTile>>(
+    writer: W,
+    width: u32,
+    height: u32,
+    scanlines: &mut Scanlines<I>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    let mut stream = writer.stream_writer()?;
+
+    let mut row = vec![0u8; width as usize * 4];
+    for _ in 0..height {
+        scanlines.next_into(&mut row)?;
+        stream.write_all(&row)?;
+    }
+    stream.finish()?;
+    writer.finish()?;
+    Ok(())
+}
+
+fn write_tiff<W: Write + std::io::Seek, K: TiffKind, I: Iterator<Item = PendingTile>>(
+    mut encoder: TiffEncoder<W, K>,
+    width: u32,
+    height: u32,
+    scanlines: &mut Scanlines<I>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut image = encoder.new_image::<colortype::RGBA8>(width, height)?;
+    image.rows_per_strip(1)?;
+
+    let mut row = vec![0u8; width as usize * 4];
+    for _ in 0..height {
+        scanlines.next_into(&mut row)?;
+        image.write_strip(&row)?;
+    }
+    image.finish()?;
+    Ok(())
+}
+
+/// Collects tile placements from `source` without decoding directory or manifest tiles.
+fn pending_tiles(source: TileSource) -> Result<Vec<PendingTile>, Box<dyn std::error::Error>> {
+    let pending = match source {
+        TileSource::Tiles(tiles) => tiles
+            .into_iter()
+            .map(|tile| {
+                let (x, y, w, h) = tile.content_rect();
+                PendingTile {
+                    coords: (x.max(0) as u32, y.max(0) as u32),
+                    size: (w, h),
+                    inner: tile.inner,
+                    source: PendingSource::Image(tile.image),
+                }
+            })
+            .collect(),
+        TileSource::Manifest(path) => Manifest::load(&path)?
+            .tiles
+            .into_iter()
+            .map(|entry| {
+                let (x, y, w, h) = entry.content_rect();
+                let source = match (entry.filename, entry.background) {
+                    (Some(filename), _) => PendingSource::File(filename),
+                    (None, Some(color)) => PendingSource::Fill(color),
+                    (None, None) => {
+                        return Err(format!("tile #{} has no filename", entry.number).into());
+                    }
+                };
+                Ok(PendingTile {
+                    coords: (x.max(0) as u32, y.max(0) as u32),
+                    size: (w, h),
+                    inner: if entry.background.is_some() {
+                        None
+                    } else {
+                        entry.inner
+                    },
+                    source,
+                })
+            })
+            .collect::<Result<_, Box<dyn std::error::Error>>>()?,
+        TileSource::Directory(directory) => scan_tile_directory(&directory)?
+            .into_iter()
+            .map(|tile| PendingTile {
+                coords: tile.coords,
+                size: tile.size,
+                inner: None,
+                source: PendingSource::File(tile.path),
+            })
+            .collect(),
+    };
+    Ok(pending)
+}
+
+/// A tile file found by `scan_tile_directory`.
+pub(crate) struct DirectoryTile {
+    pub(crate) path: PathBuf,
+    /// Zero-based `(column, row)` parsed from the filename.
+    pub(crate) position: (u32, u32),
+    pub(crate) coords: (u32, u32),
+    pub(crate) size: (u32, u32),
+}
+
+/// Lists the tiles in a directory written by `save_tiles`, in row order, without
+/// decoding them.
+///
+/// Tiles of a column must share a width and tiles of a row a height, as with the
+/// smaller edge tiles of `slice_image_by_size`. Offsets are the sums of the
+/// widths of the columns to the left and the heights of the rows above.
+///
+/// # Errors
+///
+/// Returns an error if the directory can not be read, or the tiles do not form
+/// such a grid. Those tile sets need a manifest to be placed.
+pub(crate) fn scan_tile_directory(
+    directory: &Path,
+) -> Result<Vec<DirectoryTile>, Box<dyn std::error::Error>> {
+    let mut tiles = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        let Some(filename) = path.file_name().and_then(|f| f.to_str()) else {
+            continue;
+        };
+        if !filename.contains('_') || filename.starts_with("joined") {
+            continue;
+        }
+        let Ok(pos) = get_image_column_row(filename) else {
+            continue;
+        };
+        if pos.0 < 0 || pos.1 < 0 || image::ImageFormat::from_path(&path).is_err() {
+            continue;
+        }
+        let size = image::image_dimensions(&path)?;
+        tiles.push(DirectoryTile {
+            path,
+            position: (pos.0 as u32, pos.1 as u32),
+            coords: (0, 0),
+            size,
+        });
+    }
+    tiles.sort_by_key(|t| (t.position.1, t.position.0));
+
+    let columns = tiles.iter().map(|t| t.position.0 + 1).max().unwrap_or(0);
+    let rows = tiles.iter().map(|t| t.position.1 + 1).max().unwrap_or(0);
+    let mut widths = vec![None; columns as usize];
+    let mut heights = vec![None; rows as usize];
+    for tile in &tiles {
+        for (span, index, length, axis) in [
+            (&mut widths, tile.position.0, tile.size.0, "column"),
+            (&mut heights, tile.position.1, tile.size.1, "row"),
+        ] {
+            match span[index as usize] {
+                None => span[index as usize] = Some(length),
+                Some(known) if known != length => {
+                    return Err(format!(
+                        "tiles in {} {} of {} differ in size; use a manifest to place them",
+                        axis,
+                        index + 1,
+                        directory.display()
+                    )
+                    .into());
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    let offsets = |spans: &[Option<u32>], axis: &str| {
+        let mut offsets = Vec::with_capacity(spans.len());
+        let mut sum = 0;
+        for (index, span) in spans.iter().enumerate() {
+            offsets.push(sum);
+            sum += span.ok_or_else(|| {
+                format!(
+                    "{} has no tile in {} {}; use a manifest to place the tiles",
+                    directory.display(),
+                    axis,
+                    index + 1
+                )
+            })?;
+        }
+        Ok::<_, String>(offsets)
+    };
+    let (xs, ys) = (offsets(&widths, "column")?, offsets(&heights, "row")?);
+    for tile in tiles.iter_mut() {
+        tile.coords = (xs[tile.position.0 as usize], ys[tile.position.1 as usize]);
+    }
+    Ok(tiles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::join;
+    use image::Rgba;
+
+    fn gradient(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([x as u8, y as u8, (x + y) as u8, 255])
+        }))
+    }
+
+    fn grid_tiles(image: &DynamicImage, columns: u32, rows: u32) -> Vec<Tile> {
+        let (tile_w, tile_h) = (image.width() / columns, image.height() / rows);
+        let mut tiles = Vec::new();
+        for row in 0..rows {
+            for column in 0..columns {
+                let (x, y) = (column * tile_w, row * tile_h);
+                tiles.push(Tile::new(
+                    image.crop_imm(x, y, tile_w, tile_h),
+                    (tiles.len() + 1) as i32,
+                    (column as i32 + 1, row as i32 + 1),
+                    (x as i32, y as i32),
+                    None,
+                ));
+            }
+        }
+        tiles
+    }
+
+    #[test]
+    fn test_join_streaming_matches_join() {
+        let dir = Path::new("test_join_streaming");
+        fs::create_dir_all(dir).unwrap();
+        let source = gradient(30, 20);
+        let tiles = grid_tiles(&source, 3, 2);
+        let expected = join(&tiles, 30, 20).unwrap().to_rgba8();
+
+        for (name, format) in [
+            ("out.png", StreamFormat::Png),
+            ("out.tif", StreamFormat::Tiff),
+        ] {
+            let output = dir.join(name);
+            join_streaming(TileSource::Tiles(tiles.clone()), &output, format).unwrap();
+            assert_eq!(image::open(&output).unwrap().to_rgba8(), expected);
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_join_streaming_from_manifest() {
+        let dir = Path::new("test_join_streaming_manifest");
+        let source = gradient(20, 20);
+        let mut tiles = grid_tiles(&source, 2, 2);
+        crate::save_tiles(&mut tiles, "tile", Some(dir), "png").unwrap();
+        let manifest_path = dir.join("manifest.json");
+        Manifest::from_tiles(&tiles).save(&manifest_path).unwrap();
+
+        let output = dir.join("joined.png");
+        join_streaming(
+            TileSource::Manifest(manifest_path),
+            &output,
+            StreamFormat::Png,
+        )
+        .unwrap();
+        assert_eq!(image::open(&output).unwrap().to_rgba8(), source.to_rgba8());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_join_streaming_uneven_directory() {
+        let dir = Path::new("test_join_streaming_uneven");
+        let _ = fs::remove_dir_all(dir);
+        // 5x5 tiles of a 16x12 image leave 1-pixel columns and 2-pixel rows.
+        let source = gradient(16, 12);
+        let mut tiles = crate::slice_image_by_size(&source, 5, 5).unwrap();
+        crate::save_tiles(&mut tiles, "tile", Some(dir), "png").unwrap();
+
+        let scanned = scan_tile_directory(dir).unwrap();
+        assert_eq!(scanned.len(), 12);
+        assert_eq!((scanned[11].coords, scanned[11].size), ((15, 10), (1, 2)));
+
+        let output = dir.join("joined.png");
+        join_streaming(
+            TileSource::Directory(dir.to_path_buf()),
+            &output,
+            StreamFormat::Png,
+        )
+        .unwrap();
+        assert_eq!(image::open(&output).unwrap().to_rgba8(), source.to_rgba8());
+
+        // A tile of another width in the first column can not be placed.
+        let odd = DynamicImage::ImageRgba8(RgbaImage::new(4, 5));
+        odd.save(dir.join("tile_02_01.png")).unwrap();
+        assert!(scan_tile_directory(dir).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
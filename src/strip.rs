@@ -0,0 +1,190 @@
+//! The `strip` module splits tall images, such as webtoon episodes, into vertical
+//! strips no taller than a maximum height. Rather than cutting blindly at the
+//! limit, it searches back from each limit for the flattest row so cuts avoid
+//! speech bubbles and artwork.
+//!
+//! # Example Usage
+//!
+//! ```no_run
+//! use papercut::{join, slice_webtoon, StripOptions};
+//!
+//! let options = StripOptions {
+//!     max_height: 1280,
+//!     ..StripOptions::default()
+//! };
+//! let strips = slice_webtoon("episode.png", &options, false).unwrap();
+//! let rebuilt = join(&strips, 0, 0).unwrap();
+//! ```
+
+use image::{DynamicImage, GrayImage};
+use std::path::Path;
+
+use crate::tile::Tile;
+use crate::{get_basename, save_tiles};
+
+/// Options for `slice_strips`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StripOptions {
+    /// Maximum height of a strip in pixels.
+    pub max_height: u32,
+    /// How many rows above each limit are searched for a flat cut.
+    pub search_range: u32,
+}
+
+impl Default for StripOptions {
+    fn default() -> Self {
+        StripOptions {
+            max_height: 1280,
+            search_range: 320,
+        }
+    }
+}
+
+/// Split an image file into vertical strips.
+///
+/// # Arguments
+///
+/// * `filename` - The filename of the image to split.
+/// * `options` - Maximum strip height and search range.
+/// * `save` - Whether or not to save tiles to disk.
+///
+/// # Returns
+///
+/// A vector of `Tile` instances from top to bottom.
+pub fn slice_webtoon(
+    filename: &str,
+    options: &StripOptions,
+    save: bool,
+) -> Result<Vec<Tile>, String> {
+    let im =
+        image::open(Path::new(filename)).map_err(|_| format!("can not open image {}", filename))?;
+    let mut tiles = slice_strips(&im, options)?;
+
+    if save {
+        let prefix = get_basename(filename);
+        let directory = Path::new(filename)
+            .parent()
+            .unwrap_or_else(|| Path::new("."));
+        save_tiles(&mut tiles, &prefix, Some(directory), "png")
+            .map_err(|_| "can not save tiles")?;
+    }
+
+    Ok(tiles)
+}
+
+/// Split an in-memory image into full-width strips no taller than `max_height`.
+///
+/// Each strip's `coords` holds its vertical offset, so `join` rebuilds the
+/// original image.
+///
+/// # Errors
+///
+/// Returns an error if `max_height` is zero.
+pub fn slice_strips(image: &DynamicImage, options: &StripOptions) -> Result<Vec<Tile>, String> {
+    let cuts = strip_cuts(image, options)?;
+    let width = image.width();
+    Ok(cuts
+        .windows(2)
+        .enumerate()
+        .map(|(i, span)| {
+            Tile::new(
+                image.crop_imm(0, span[0], width, span[1] - span[0]),
+                i as i32 + 1,
+                (1, i as i32 + 1),
+                (0, span[0] as i32),
+                None,
+            )
+        })
+        .collect())
+}
+
+/// Compute the rows at which `slice_strips` cuts the image.
+///
+/// Returns the strip boundaries, starting with `0` and ending with the image
+/// height. From each limit `start + max_height` the search walks back up to
+/// `search_range` rows and picks the row with the lowest gradient energy; ties go
+/// to the row closest to the limit.
+///
+/// # Errors
+///
+/// Returns an error if `max_height` is zero.
+pub fn strip_cuts(image: &DynamicImage, options: &StripOptions) -> Result<Vec<u32>, String> {
+    if options.max_height == 0 {
+        return Err("Maximum strip height must be at least 1 pixel.".to_string());
+    }
+    let luma = image.to_luma8();
+    let height = luma.height();
+    let search = options.search_range.min(options.max_height - 1);
+
+    let mut cuts = vec![0];
+    let mut start = 0;
+    while height - start > options.max_height {
+        let limit = start + options.max_height;
+        let mut best = (limit, cut_energy(&luma, limit));
+        for candidate in (limit - search..limit).rev() {
+            let energy = cut_energy(&luma, candidate);
+            if energy < best.1 {
+                best = (candidate, energy);
+            }
+        }
+        cuts.push(best.0);
+        start = best.0;
+    }
+    cuts.push(height);
+    Ok(cuts)
+}
+
+/// Gradient energy of a cut between rows `y - 1` and `y`: the detail within both
+/// rows plus the change across the seam.
+fn cut_energy(luma: &GrayImage, y: u32) -> u64 {
+    let px = |x: u32, y: u32| i32::from(luma.get_pixel(x, y).0[0]);
+    let row_detail = |y: u32| -> u64 {
+        (1..luma.width())
+            .map(|x| px(x, y).abs_diff(px(x - 1, y)) as u64)
+            .sum()
+    };
+    let across: u64 = (0..luma.width())
+        .map(|x| px(x, y).abs_diff(px(x, y - 1)) as u64)
+        .sum();
+    row_detail(y - 1) + row_detail(y) + across
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::join;
+    use image::{Rgba, RgbaImage};
+
+    /// Busy artwork everywhere except flat bands at rows 70..74 and 150..152.
+    fn episode() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(40, 230, |x, y| {
+            if (70..74).contains(&y) || (150..152).contains(&y) {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([((x * 31 + y * 17) % 256) as u8, (y % 256) as u8, 0, 255])
+            }
+        }))
+    }
+
+    #[test]
+    fn test_strip_cuts_land_in_flat_bands() {
+        let options = StripOptions {
+            max_height: 100,
+            search_range: 40,
+        };
+        let cuts = strip_cuts(&episode(), &options).unwrap();
+        assert_eq!(cuts, vec![0, 73, 151, 230]);
+    }
+
+    #[test]
+    fn test_strips_respect_max_height_and_join_back() {
+        let source = episode();
+        let options = StripOptions {
+            max_height: 60,
+            search_range: 20,
+        };
+        let strips = slice_strips(&source, &options).unwrap();
+        assert!(strips.iter().all(|s| s.image.height() <= 60));
+        assert_eq!(join(&strips, 0, 0).unwrap().to_rgba8(), source.to_rgba8());
+    }
+}
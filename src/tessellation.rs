@@ -0,0 +1,308 @@
+//! The `tessellation` module cuts an image into non-rectangular cells: hexagons
+//! (pointy- or flat-top) or equilateral triangles. Each cell becomes a `Tile` whose
+//! `mask` marks the pixels it covers.
+//!
+//! # Features
+//!
+//! - **Hexagonal Grids**: Pointy-top cells in "odd-r" offset layout and flat-top
+//!   cells in "odd-q" layout, with conversion to axial coordinates.
+//! - **Triangular Grids**: Rows of alternating up- and down-pointing triangles.
+//! - **Exact Coverage**: Every pixel belongs to exactly one cell, so `join` rebuilds
+//!   the source pixel for pixel.
+//!
+//! # Coordinates
+//!
+//! A cell's `position` is its 1-based `(column, row)` offset coordinate. The
+//! hexagon at `(1, 1)` is centred on the image origin, so cells along the top and
+//! left borders are only partially covered by the image. Triangles point up when
+//! `column + row` is odd.
+//!
+//! # Example Usage
+//!
+//! ```rust
+//! use papercut::{join, tessellate, HexOrientation, Tessellation};
+//! use image::{DynamicImage, RgbaImage};
+//!
+//! let image = DynamicImage::ImageRgba8(RgbaImage::new(100, 80));
+//! let hexes = tessellate(
+//!     &image,
+//!     Tessellation::Hex { size: 12.0, orientation: HexOrientation::PointyTop },
+//! )
+//! .unwrap();
+//! let axial = HexOrientation::PointyTop.offset_to_axial((
+//!     hexes[0].position.0 - 1,
+//!     hexes[0].position.1 - 1,
+//! ));
+//! let rebuilt = join(&hexes, 100, 80).unwrap();
+//! ```
+
+use image::{DynamicImage, GrayImage, Luma, RgbaImage};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::tile::Tile;
+
+/// Orientation of a hexagonal grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HexOrientation {
+    /// Hexagons with a vertex at the top; odd rows are shifted right by half a cell.
+    PointyTop,
+    /// Hexagons with an edge at the top; odd columns are shifted down by half a cell.
+    FlatTop,
+}
+
+/// The shape of the cells produced by `tessellate`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tessellation {
+    /// Regular hexagons; `size` is the distance from the centre to a vertex in pixels.
+    Hex {
+        size: f64,
+        orientation: HexOrientation,
+    },
+    /// Equilateral triangles with sides of `side` pixels, arranged in horizontal rows.
+    Triangle { side: f64 },
+}
+
+impl HexOrientation {
+    /// Converts 0-based offset coordinates `(column, row)` to axial `(q, r)`.
+    pub fn offset_to_axial(self, offset: (i32, i32)) -> (i32, i32) {
+        let (column, row) = offset;
+        match self {
+            HexOrientation::PointyTop => (column - (row - (row & 1)) / 2, row),
+            HexOrientation::FlatTop => (column, row - (column - (column & 1)) / 2),
+        }
+    }
+
+    /// Converts axial `(q, r)` to 0-based offset coordinates `(column, row)`.
+    pub fn axial_to_offset(self, axial: (i32, i32)) -> (i32, i32) {
+        let (q, r) = axial;
+        match self {
+            HexOrientation::PointyTop => (q + (r - (r & 1)) / 2, r),
+            HexOrientation::FlatTop => (q, r + (q - (q & 1)) / 2),
+        }
+    }
+
+    /// Offset coordinates of the hexagon containing the point `(x, y)`.
+    fn cell_at(self, size: f64, x: f64, y: f64) -> (i32, i32) {
+        let sqrt3 = 3f64.sqrt();
+        let (q, r) = match self {
+            HexOrientation::PointyTop => ((sqrt3 / 3.0 * x - y / 3.0) / size, 2.0 / 3.0 * y / size),
+            HexOrientation::FlatTop => (2.0 / 3.0 * x / size, (-x / 3.0 + sqrt3 / 3.0 * y) / size),
+        };
+        self.axial_to_offset(cube_round(q, r))
+    }
+}
+
+/// Split an image file into hexagonal or triangular cells.
+///
+/// # Arguments
+///
+/// * `filename` - The filename of the image to split.
+/// * `tessellation` - The cell shape and size.
+///
+/// # Returns
+///
+/// A vector of masked `Tile` instances in row order.
+pub fn slice_tessellated(filename: &str, tessellation: Tessellation) -> Result<Vec<Tile>, String> {
+    let im =
+        image::open(Path::new(filename)).map_err(|_| format!("can not open image {}", filename))?;
+    tessellate(&im, tessellation)
+}
+
+/// Cut an in-memory image into hexagonal or triangular cells.
+///
+/// Each cell's `coords` is the top-left corner of the part of the cell inside the
+/// image, and its `mask` marks the pixels whose centres fall inside the cell.
+///
+/// # Errors
+///
+/// Returns an error if the cell size is smaller than two pixels.
+pub fn tessellate(image: &DynamicImage, tessellation: Tessellation) -> Result<Vec<Tile>, String> {
+    let cell_size = match tessellation {
+        Tessellation::Hex { size, .. } => size,
+        Tessellation::Triangle { side } => side,
+    };
+    if cell_size.is_nan() || cell_size < 2.0 {
+        return Err(format!(
+            "Cell size must be at least 2 pixels (you asked for {}).",
+            cell_size
+        ));
+    }
+
+    let (width, height) = (image.width(), image.height());
+    let cell_at = |x: u32, y: u32| {
+        let (px, py) = (f64::from(x) + 0.5, f64::from(y) + 0.5);
+        match tessellation {
+            Tessellation::Hex { size, orientation } => orientation.cell_at(size, px, py),
+            Tessellation::Triangle { side } => triangle_at(side, px, py),
+        }
+    };
+
+    // Label every pixel and collect the bounding box of each cell, keyed by (row, column).
+    let mut labels = Vec::with_capacity(width as usize * height as usize);
+    let mut bounds: BTreeMap<(i32, i32), (u32, u32, u32, u32)> = BTreeMap::new();
+    for y in 0..height {
+        for x in 0..width {
+            let (column, row) = cell_at(x, y);
+            labels.push((column, row));
+            bounds
+                .entry((row, column))
+                .and_modify(|b| *b = (b.0.min(x), b.1.min(y), b.2.max(x), b.3.max(y)))
+                .or_insert((x, y, x, y));
+        }
+    }
+
+    let source = image.to_rgba8();
+    let tiles = bounds
+        .into_iter()
+        .enumerate()
+        .map(|(i, ((row, column), (x0, y0, x1, y1)))| {
+            let (w, h) = (x1 - x0 + 1, y1 - y0 + 1);
+            let mut mask = GrayImage::new(w, h);
+            let mut cell = RgbaImage::new(w, h);
+            for y in 0..h {
+                for x in 0..w {
+                    let (sx, sy) = (x0 + x, y0 + y);
+                    if labels[(sy * width + sx) as usize] == (column, row) {
+                        mask.put_pixel(x, y, Luma([255]));
+                        cell.put_pixel(x, y, *source.get_pixel(sx, sy));
+                    }
+                }
+            }
+            let mut tile = Tile::new(
+                DynamicImage::ImageRgba8(cell),
+                i as i32 + 1,
+                (column + 1, row + 1),
+                (x0 as i32, y0 as i32),
+                None,
+            );
+            tile.mask = Some(mask);
+            tile
+        })
+        .collect();
+
+    Ok(tiles)
+}
+
+/// Rounds fractional axial coordinates to the nearest hexagon.
+fn cube_round(q: f64, r: f64) -> (i32, i32) {
+    let s = -q - r;
+    let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+    let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+    if dq > dr && dq > ds {
+        rq = -rr - rs;
+    } else if dr > ds {
+        rr = -rq - rs;
+    }
+    (rq as i32, rr as i32)
+}
+
+/// Offset coordinates `(column, row)` of the triangle containing `(x, y)`.
+///
+/// Row `r` spans `r * h..(r + 1) * h` where `h` is the triangle height. Odd rows are
+/// shifted by half a side so that the rows interlock. Columns count triangles from
+/// the left edge, starting at the partial triangle touching `x = 0`.
+fn triangle_at(side: f64, x: f64, y: f64) -> (i32, i32) {
+    let h = side * 3f64.sqrt() / 2.0;
+    let row = (y / h).floor();
+    let fy = y / h - row;
+    let offset = if row as i64 % 2 == 1 { side / 2.0 } else { 0.0 };
+    let u = (x - offset) / side;
+    let k = u.floor();
+    let fu = u - k;
+
+    // Up-pointing triangle `k` has its apex at the top of the row and its base at the bottom.
+    let index = if fu < (1.0 - fy) / 2.0 {
+        2.0 * k - 1.0
+    } else if fu < (1.0 + fy) / 2.0 {
+        2.0 * k
+    } else {
+        2.0 * k + 1.0
+    };
+    let column = if offset > 0.0 {
+        index + 2.0
+    } else {
+        index + 1.0
+    };
+    (column as i32, row as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::join;
+    use image::Rgba;
+
+    fn gradient(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([x as u8, y as u8, (x ^ y) as u8, 255])
+        }))
+    }
+
+    fn assert_rebuilds(tessellation: Tessellation) {
+        let source = gradient(97, 61);
+        let tiles = tessellate(&source, tessellation).unwrap();
+        let covered: usize = tiles
+            .iter()
+            .map(|t| {
+                t.mask
+                    .as_ref()
+                    .unwrap()
+                    .pixels()
+                    .filter(|m| m.0[0] > 0)
+                    .count()
+            })
+            .sum();
+        assert_eq!(covered, 97 * 61);
+        assert!(tiles.iter().all(|t| t.position.0 >= 1 && t.position.1 >= 1));
+        assert_eq!(join(&tiles, 97, 61).unwrap().to_rgba8(), source.to_rgba8());
+    }
+
+    #[test]
+    fn test_tessellations_rebuild_source() {
+        for orientation in [HexOrientation::PointyTop, HexOrientation::FlatTop] {
+            assert_rebuilds(Tessellation::Hex {
+                size: 10.0,
+                orientation,
+            });
+        }
+        assert_rebuilds(Tessellation::Triangle { side: 14.0 });
+    }
+
+    #[test]
+    fn test_axial_offset_round_trip() {
+        for orientation in [HexOrientation::PointyTop, HexOrientation::FlatTop] {
+            for column in 0..5 {
+                for row in 0..5 {
+                    let axial = orientation.offset_to_axial((column, row));
+                    assert_eq!(orientation.axial_to_offset(axial), (column, row));
+                }
+            }
+        }
+        assert_eq!(HexOrientation::PointyTop.offset_to_axial((2, 3)), (1, 3));
+    }
+
+    #[test]
+    fn test_hex_centre_cells() {
+        let size = 10.0;
+        let width = 3f64.sqrt() * size;
+        // Odd rows are shifted right by half a hexagon.
+        assert_eq!(HexOrientation::PointyTop.cell_at(size, width, 0.0), (1, 0));
+        assert_eq!(
+            HexOrientation::PointyTop.cell_at(size, width / 2.0, 15.0),
+            (0, 1)
+        );
+    }
+
+    #[test]
+    fn test_triangle_orientation() {
+        let side = 10.0;
+        let h = side * 3f64.sqrt() / 2.0;
+        // Row 0: the up triangle has its apex at (5, 0), so its centre column is odd.
+        assert_eq!(triangle_at(side, 5.0, h * 0.9), (1, 0));
+        // Just left of the apex near the top belongs to the partial down triangle.
+        assert_eq!(triangle_at(side, 1.0, h * 0.1), (0, 0));
+        // Row 1 is shifted: the up triangle's apex sits at x = 10.
+        assert_eq!(triangle_at(side, 10.0, h * 1.9), (2, 1));
+    }
+}
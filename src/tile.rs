@@ -19,10 +19,23 @@
 //! println!("{:?}", tile);
 //! ```
 
-use image::DynamicImage;
+use image::{DynamicImage, GrayImage};
 use std::env;
 use std::path::{Path, PathBuf};
 
+use crate::store::{HashAlgorithm, encode};
+
+/// How saved tiles are named.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TileNaming {
+    /// `{prefix}_{row}_{column}`, see `Tile::generate_filename`.
+    #[default]
+    Grid,
+    /// The hash of the encoded tile, see `Tile::hashed_filename`. Identical
+    /// tiles share one file.
+    Hash(HashAlgorithm),
+}
+
 /// Represents a single tile of an image.
 ///
 /// A `Tile` contains the image data, its position in the grid, pixel coordinates, and optional
@@ -38,6 +51,16 @@ pub struct Tile {
     pub coords: (i32, i32),
     /// The filename of the tile, if it has been saved to disk.
     pub filename: Option<PathBuf>,
+    /// Alpha mask for non-rectangular tiles. Pixels where the mask is zero do not
+    /// belong to the tile and are skipped by `join`.
+    pub mask: Option<GrayImage>,
+    /// Rectangle `(x, y, width, height)` of the real content within a padded
+    /// `image`, i.e. the UV bounds for texture sampling. `None` when the whole image
+    /// is content.
+    pub inner: Option<(u32, u32, u32, u32)>,
+    /// Colour of a tile found to be empty by `mark_empty_tiles`. Such tiles are
+    /// not written by `save_tiles` and are recorded as sparse in a `Manifest`.
+    pub background: Option<[u8; 4]>,
 }
 
 impl Tile {
@@ -67,6 +90,9 @@ impl Tile {
             position,
             coords,
             filename,
+            mask: None,
+            inner: None,
+            background: None,
         }
     }
 
@@ -132,6 +158,62 @@ impl Tile {
         }
     }
 
+    /// Generates a content-addressed filename: the hex digest of the tile encoded
+    /// as `format`, so identical tiles get the same name.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The directory to save the tile. If `None`, the current working directory is used.
+    /// * `format` - The format of the tile (e.g., `"png"`, `"jpg"`).
+    /// * `algorithm` - The hash function.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tile can not be encoded as `format`.
+    pub fn hashed_filename(
+        &self,
+        directory: Option<&Path>,
+        format: &str,
+        algorithm: HashAlgorithm,
+    ) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let encoded = encode(&self.image, format)?;
+        Ok(self.hashed_path(directory, format, algorithm, &encoded))
+    }
+
+    fn hashed_path(
+        &self,
+        directory: Option<&Path>,
+        format: &str,
+        algorithm: HashAlgorithm,
+        encoded: &[u8],
+    ) -> PathBuf {
+        let current_dir = env::current_dir().unwrap();
+        let dir = directory.unwrap_or(current_dir.as_path());
+        let ext = format.to_lowercase().replace("jpeg", "jpg");
+        dir.join(format!("{}.{}", algorithm.digest(encoded), ext))
+    }
+
+    /// Saves the tile under a content-addressed filename, see `hashed_filename`.
+    /// Nothing is written when the file already exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tile can not be encoded or written.
+    pub fn save_hashed(
+        &mut self,
+        directory: Option<&Path>,
+        format: &str,
+        algorithm: HashAlgorithm,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let encoded = encode(&self.image, format)?;
+        let file_path = self.hashed_path(directory, format, algorithm, &encoded);
+        if !file_path.exists() {
+            std::fs::write(&file_path, encoded)?;
+        }
+        self.filename = Some(file_path);
+        Ok(())
+    }
+
     /// Saves the tile to disk.
     ///
     /// # Arguments
@@ -153,6 +235,20 @@ impl Tile {
         self.filename = Some(file_path);
         Ok(())
     }
+
+    /// Returns the content of the tile in combined image coordinates as
+    /// `(x, y, width, height)`, leaving out any padding outside `inner`.
+    pub fn content_rect(&self) -> (i32, i32, u32, u32) {
+        match self.inner {
+            Some((x, y, w, h)) => (self.coords.0 + x as i32, self.coords.1 + y as i32, w, h),
+            None => (
+                self.coords.0,
+                self.coords.1,
+                self.image.width(),
+                self.image.height(),
+            ),
+        }
+    }
 }
 
 impl std::fmt::Debug for Tile {
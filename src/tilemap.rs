@@ -0,0 +1,498 @@
+//! The `tilemap` module turns a large map image into a tileset of unique tiles and
+//! a tilemap that indexes into it, as used by retro and pixel-art engines. The map
+//! is cut with `slice_image_by_size` and every cell that repeats an earlier one,
+//! optionally flipped or rotated, is stored only once.
+//!
+//! # Features
+//!
+//! - **Deduplication**: Identical tiles share one tileset entry.
+//! - **Flips and Rotations**: Tiles can also match under horizontal and vertical
+//!   flips and, for square tiles, 90 degree rotations. Each map cell records the
+//!   flags needed to draw it, using Tiled's flip flag convention.
+//! - **Export**: The tilemap can be written as CSV, JSON or a Tiled TMX map next to
+//!   the tileset image.
+//! - **Statistics**: How many cells were exact or transformed duplicates.
+//!
+//! # Example Usage
+//!
+//! ```no_run
+//! use papercut::{slice_tilemap, TilemapFormat, TilemapOptions};
+//! use std::path::Path;
+//!
+//! let tilemap = slice_tilemap("level.png", &TilemapOptions::default()).unwrap();
+//! println!("{} unique tiles", tilemap.stats.unique);
+//! tilemap
+//!     .save(Path::new("tileset.png"), Path::new("level.tmx"), TilemapFormat::Tmx)
+//!     .unwrap();
+//! ```
+
+use image::{DynamicImage, RgbaImage, imageops};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::grid::slice_image_by_size;
+use crate::join;
+use crate::tile::Tile;
+
+const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+
+/// Options for `build_tilemap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TilemapOptions {
+    /// Width of a map cell in pixels.
+    pub tile_width: u32,
+    /// Height of a map cell in pixels.
+    pub tile_height: u32,
+    /// Whether tiles may match under horizontal and vertical flips.
+    pub flips: bool,
+    /// Whether square tiles may match under 90 degree rotations.
+    pub rotations: bool,
+    /// Number of tiles per row in the tileset image.
+    pub tileset_columns: u32,
+}
+
+impl Default for TilemapOptions {
+    fn default() -> Self {
+        TilemapOptions {
+            tile_width: 16,
+            tile_height: 16,
+            flips: true,
+            rotations: false,
+            tileset_columns: 16,
+        }
+    }
+}
+
+/// File formats for `Tilemap::save`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TilemapFormat {
+    /// Comma separated global tile ids, one map row per line.
+    Csv,
+    /// JSON with the map size, global tile ids and statistics.
+    Json,
+    /// Tiled TMX map with an embedded tileset.
+    Tmx,
+}
+
+/// A map cell: which tileset entry to draw and how to transform it.
+///
+/// Flags follow Tiled: the diagonal flip (a transpose) is applied first, then the
+/// horizontal and vertical flips.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub struct TileRef {
+    /// 0-based index into the tileset.
+    pub tile: u32,
+    /// Mirror left to right.
+    pub flip_horizontal: bool,
+    /// Mirror top to bottom.
+    pub flip_vertical: bool,
+    /// Swap the x and y axes.
+    pub flip_diagonal: bool,
+}
+
+impl TileRef {
+    /// The Tiled global tile id: `first_gid + tile` with the flip flags in the top
+    /// three bits.
+    pub fn gid(&self, first_gid: u32) -> u32 {
+        let mut gid = first_gid + self.tile;
+        if self.flip_horizontal {
+            gid |= FLIPPED_HORIZONTALLY;
+        }
+        if self.flip_vertical {
+            gid |= FLIPPED_VERTICALLY;
+        }
+        if self.flip_diagonal {
+            gid |= FLIPPED_DIAGONALLY;
+        }
+        gid
+    }
+
+    /// Whether any flip flag is set.
+    pub fn is_transformed(&self) -> bool {
+        self.flip_horizontal || self.flip_vertical || self.flip_diagonal
+    }
+}
+
+/// Deduplication statistics.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DedupStats {
+    /// Number of map cells.
+    pub total: usize,
+    /// Number of tiles in the tileset.
+    pub unique: usize,
+    /// Cells that repeat a tileset entry exactly.
+    pub exact_duplicates: usize,
+    /// Cells that repeat a tileset entry flipped or rotated.
+    pub transformed_duplicates: usize,
+}
+
+impl DedupStats {
+    /// Fraction of cells that did not need a tileset entry of their own.
+    pub fn savings(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            1.0 - self.unique as f64 / self.total as f64
+        }
+    }
+}
+
+/// A deduplicated tileset and the map that indexes into it.
+#[derive(Debug, Clone)]
+pub struct Tilemap {
+    /// Width and height of a cell in pixels.
+    pub tile_size: (u32, u32),
+    /// Number of map columns.
+    pub columns: u32,
+    /// Number of map rows.
+    pub rows: u32,
+    /// The unique tiles. `coords` is the position in the tileset image.
+    pub tileset: Vec<Tile>,
+    /// Number of tiles per row in the tileset image.
+    pub tileset_columns: u32,
+    /// The map cells in row order.
+    pub cells: Vec<TileRef>,
+    /// Deduplication statistics.
+    pub stats: DedupStats,
+}
+
+/// Split a map image file into a deduplicated tileset and tilemap.
+pub fn slice_tilemap(filename: &str, options: &TilemapOptions) -> Result<Tilemap, String> {
+    let im =
+        image::open(Path::new(filename)).map_err(|_| format!("can not open image {}", filename))?;
+    build_tilemap(&im, options)
+}
+
+/// Split an in-memory map image into a deduplicated tileset and tilemap.
+///
+/// Rotations are only considered for square tiles.
+///
+/// # Errors
+///
+/// Returns an error if the tile size is zero, the image size is not a multiple
+/// of the tile size, or `tileset_columns` is zero.
+pub fn build_tilemap(image: &DynamicImage, options: &TilemapOptions) -> Result<Tilemap, String> {
+    let (w, h) = (options.tile_width, options.tile_height);
+    if w == 0 || h == 0 || options.tileset_columns == 0 {
+        return Err("Tile size and tileset columns must be at least 1.".to_string());
+    }
+    if !image.width().is_multiple_of(w) || !image.height().is_multiple_of(h) {
+        return Err(format!(
+            "Image of {}x{} is not a whole number of {}x{} tiles.",
+            image.width(),
+            image.height(),
+            w,
+            h
+        ));
+    }
+
+    let transforms = allowed_transforms(options);
+    let mut known: HashMap<Vec<u8>, TileRef> = HashMap::new();
+    let mut tileset = Vec::new();
+    let mut cells = Vec::new();
+    let mut stats = DedupStats::default();
+
+    for cell in slice_image_by_size(image, w, h)? {
+        let pixels = cell.image.to_rgba8();
+        stats.total += 1;
+        if let Some(found) = known.get(pixels.as_raw()) {
+            if found.is_transformed() {
+                stats.transformed_duplicates += 1;
+            } else {
+                stats.exact_duplicates += 1;
+            }
+            cells.push(*found);
+            continue;
+        }
+
+        let index = tileset.len() as u32;
+        for &(flip_horizontal, flip_vertical, flip_diagonal) in &transforms {
+            let reference = TileRef {
+                tile: index,
+                flip_horizontal,
+                flip_vertical,
+                flip_diagonal,
+            };
+            known
+                .entry(apply(&pixels, &reference).into_raw())
+                .or_insert(reference);
+        }
+        cells.push(known[pixels.as_raw()]);
+
+        let columns = options.tileset_columns;
+        tileset.push(Tile::new(
+            DynamicImage::ImageRgba8(pixels),
+            index as i32 + 1,
+            ((index % columns) as i32 + 1, (index / columns) as i32 + 1),
+            (
+                ((index % columns) * w) as i32,
+                ((index / columns) * h) as i32,
+            ),
+            None,
+        ));
+    }
+    stats.unique = tileset.len();
+
+    Ok(Tilemap {
+        tile_size: (w, h),
+        columns: image.width() / w,
+        rows: image.height() / h,
+        tileset,
+        tileset_columns: options.tileset_columns,
+        cells,
+        stats,
+    })
+}
+
+impl Tilemap {
+    /// Composite the tileset into a single image.
+    pub fn tileset_image(&self) -> Result<DynamicImage, String> {
+        let (width, height) = self.tileset_image_size();
+        join(&self.tileset, width, height)
+    }
+
+    /// Redraw the map from the tileset and cells.
+    pub fn render(&self) -> DynamicImage {
+        let (w, h) = self.tile_size;
+        let mut canvas = RgbaImage::new(self.columns * w, self.rows * h);
+        for (i, cell) in self.cells.iter().enumerate() {
+            let pixels = apply(&self.tileset[cell.tile as usize].image.to_rgba8(), cell);
+            let (x, y) = (i as u32 % self.columns * w, i as u32 / self.columns * h);
+            imageops::replace(&mut canvas, &pixels, i64::from(x), i64::from(y));
+        }
+        DynamicImage::ImageRgba8(canvas)
+    }
+
+    /// The map as CSV of Tiled global tile ids (first id 1), one row per line.
+    pub fn to_csv(&self) -> String {
+        self.cells
+            .chunks(self.columns.max(1) as usize)
+            .map(|row| {
+                row.iter()
+                    .map(|cell| cell.gid(1).to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+            + "\n"
+    }
+
+    /// The map as JSON with its size, Tiled global tile ids and statistics.
+    pub fn to_json(&self) -> String {
+        let document = serde_json::json!({
+            "width": self.columns,
+            "height": self.rows,
+            "tilewidth": self.tile_size.0,
+            "tileheight": self.tile_size.1,
+            "tilecount": self.tileset.len(),
+            "data": self.cells.iter().map(|cell| cell.gid(1)).collect::<Vec<_>>(),
+            "stats": self.stats,
+        });
+        serde_json::to_string_pretty(&document).unwrap_or_default()
+    }
+
+    /// The map as a Tiled TMX document whose tileset refers to `tileset_image`.
+    pub fn to_tmx(&self, tileset_image: &str) -> String {
+        let (w, h) = self.tile_size;
+        let image = self.tileset_image_size();
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <map version=\"1.10\" orientation=\"orthogonal\" renderorder=\"right-down\" width=\"{}\" height=\"{}\" tilewidth=\"{w}\" tileheight=\"{h}\" infinite=\"0\" nextlayerid=\"2\" nextobjectid=\"1\">\n \
+             <tileset firstgid=\"1\" name=\"tileset\" tilewidth=\"{w}\" tileheight=\"{h}\" tilecount=\"{}\" columns=\"{}\">\n  \
+             <image source=\"{}\" width=\"{}\" height=\"{}\"/>\n \
+             </tileset>\n \
+             <layer id=\"1\" name=\"map\" width=\"{}\" height=\"{}\">\n  \
+             <data encoding=\"csv\">\n{}</data>\n \
+             </layer>\n\
+             </map>\n",
+            self.columns,
+            self.rows,
+            self.tileset.len(),
+            image.0 / w,
+            tileset_image
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('"', "&quot;"),
+            image.0,
+            image.1,
+            self.columns,
+            self.rows,
+            self.to_csv().trim_end().replace('\n', ",\n") + "\n",
+        )
+    }
+
+    /// Save the tileset image and the map.
+    ///
+    /// For TMX the tileset is referenced relative to the map's directory when it
+    /// lies below it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either file can not be written.
+    pub fn save(
+        &self,
+        tileset_path: &Path,
+        map_path: &Path,
+        format: TilemapFormat,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.tileset_image()?.save(tileset_path)?;
+        let contents = match format {
+            TilemapFormat::Csv => self.to_csv(),
+            TilemapFormat::Json => self.to_json(),
+            TilemapFormat::Tmx => {
+                let base = map_path.parent().unwrap_or_else(|| Path::new(""));
+                let reference = tileset_path.strip_prefix(base).unwrap_or(tileset_path);
+                self.to_tmx(&reference.to_string_lossy())
+            }
+        };
+        fs::write(map_path, contents)?;
+        Ok(())
+    }
+
+    fn tileset_image_size(&self) -> (u32, u32) {
+        let columns = self.tileset_columns.min(self.tileset.len() as u32).max(1);
+        let rows = (self.tileset.len() as u32).div_ceil(columns).max(1);
+        (columns * self.tile_size.0, rows * self.tile_size.1)
+    }
+}
+
+/// The `(horizontal, vertical, diagonal)` flag combinations to match against,
+/// identity first so exact matches win.
+fn allowed_transforms(options: &TilemapOptions) -> Vec<(bool, bool, bool)> {
+    let square = options.tile_width == options.tile_height;
+    let mut transforms = vec![(false, false, false)];
+    if options.flips {
+        transforms.extend([
+            (true, false, false),
+            (false, true, false),
+            (true, true, false),
+        ]);
+    }
+    if options.rotations && square {
+        if options.flips {
+            transforms.extend([
+                (true, false, true),
+                (false, true, true),
+                (false, false, true),
+                (true, true, true),
+            ]);
+        } else {
+            // 90, 180 and 270 degrees clockwise.
+            transforms.extend([
+                (true, false, true),
+                (true, true, false),
+                (false, true, true),
+            ]);
+        }
+    }
+    transforms
+}
+
+/// Draw `pixels` transformed as described by `reference`.
+fn apply(pixels: &RgbaImage, reference: &TileRef) -> RgbaImage {
+    let mut out = pixels.clone();
+    if reference.flip_diagonal {
+        out = imageops::flip_horizontal(&imageops::rotate90(&out));
+    }
+    if reference.flip_horizontal {
+        out = imageops::flip_horizontal(&out);
+    }
+    if reference.flip_vertical {
+        out = imageops::flip_vertical(&out);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    /// A 4x4 tile with no symmetry.
+    fn motif() -> RgbaImage {
+        RgbaImage::from_fn(4, 4, |x, y| Rgba([(x * 60) as u8, (y * 60) as u8, 7, 255]))
+    }
+
+    /// A 3x1 map: the motif, its mirror image and the motif rotated 90 degrees.
+    fn map() -> DynamicImage {
+        let mut canvas = RgbaImage::new(12, 4);
+        imageops::replace(&mut canvas, &motif(), 0, 0);
+        imageops::replace(&mut canvas, &imageops::flip_horizontal(&motif()), 4, 0);
+        imageops::replace(&mut canvas, &imageops::rotate90(&motif()), 8, 0);
+        DynamicImage::ImageRgba8(canvas)
+    }
+
+    #[test]
+    fn test_dedup_with_flips_and_rotations() {
+        let options = TilemapOptions {
+            tile_width: 4,
+            tile_height: 4,
+            ..TilemapOptions::default()
+        };
+        let flips_only = build_tilemap(&map(), &options).unwrap();
+        assert_eq!(flips_only.stats.unique, 2);
+        assert!(flips_only.cells[1].flip_horizontal);
+        assert_eq!(flips_only.render().to_rgba8(), map().to_rgba8());
+
+        let all = build_tilemap(
+            &map(),
+            &TilemapOptions {
+                rotations: true,
+                ..options
+            },
+        )
+        .unwrap();
+        assert_eq!(all.stats.unique, 1);
+        assert_eq!(all.stats.transformed_duplicates, 2);
+        assert_eq!(all.render().to_rgba8(), map().to_rgba8());
+
+        let none = build_tilemap(
+            &map(),
+            &TilemapOptions {
+                flips: false,
+                ..options
+            },
+        )
+        .unwrap();
+        assert_eq!(none.stats.unique, 3);
+    }
+
+    #[test]
+    fn test_exports() {
+        let options = TilemapOptions {
+            tile_width: 4,
+            tile_height: 4,
+            rotations: true,
+            ..TilemapOptions::default()
+        };
+        let tilemap = build_tilemap(&map(), &options).unwrap();
+        let rotated = tilemap.cells[2].gid(1);
+        assert_eq!(rotated, 1 | FLIPPED_HORIZONTALLY | FLIPPED_DIAGONALLY);
+        assert_eq!(
+            tilemap.to_csv(),
+            format!("1,{},{}\n", 1 | FLIPPED_HORIZONTALLY, rotated)
+        );
+        assert!(
+            tilemap
+                .to_tmx("tiles.png")
+                .contains("<image source=\"tiles.png\" width=\"4\" height=\"4\"/>")
+        );
+        let json: serde_json::Value = serde_json::from_str(&tilemap.to_json()).unwrap();
+        assert_eq!(json["stats"]["unique"], 1);
+        assert!(
+            build_tilemap(
+                &map(),
+                &TilemapOptions {
+                    tile_width: 5,
+                    ..options
+                }
+            )
+            .is_err()
+        );
+    }
+}
@@ -75,12 +75,11 @@ pub fn get_columns_rows(filenames: &[String]) -> (i32, i32) {
             .file_stem()
             .and_then(|s| s.to_str())
             .unwrap_or("");
-        if let Some(last_part) = stem.get(stem.len().saturating_sub(5)..) {
-            if let Some((row, column)) = last_part.split_once('_') {
-                if let (Ok(row), Ok(column)) = (row.parse::<i32>(), column.parse::<i32>()) {
-                    tiles.push((row, column));
-                }
-            }
+        if let Some(last_part) = stem.get(stem.len().saturating_sub(5)..)
+            && let Some((row, column)) = last_part.split_once('_')
+            && let (Ok(row), Ok(column)) = (row.parse::<i32>(), column.parse::<i32>())
+        {
+            tiles.push((row, column));
         }
     }
 
@@ -0,0 +1,393 @@
+//! The `videowall` module splits content across a grid of monitors. Unlike
+//! `slice`, whose cells are equal and touch, a video wall has bezels between
+//! screens that hide part of the picture, and its screens may differ in size and
+//! resolution. The image is laid out over the whole wall, bezels included, and
+//! each screen receives the part behind it at its native resolution.
+//!
+//! # Features
+//!
+//! - **Bezel Compensation**: Pixels behind bezels are skipped so lines stay
+//!   straight across screens. Bezels are given in pixels or millimetres.
+//! - **Mixed Screens**: Every screen can have its own resolution and physical
+//!   size.
+//!
+//! # Example Usage
+//!
+//! ```no_run
+//! use papercut::{slice_video_wall, Bezel, Screen, VideoWallOptions};
+//!
+//! let options = VideoWallOptions {
+//!     columns: 3,
+//!     rows: 2,
+//!     screens: vec![Screen::with_size_mm(1920, 1080, 1210.0, 680.6)],
+//!     bezel_horizontal: Bezel::Millimetres(3.5),
+//!     bezel_vertical: Bezel::Millimetres(3.5),
+//! };
+//! let screens = slice_video_wall("signage.png", &options, true).unwrap();
+//! assert_eq!(screens.len(), 6);
+//! ```
+
+use image::DynamicImage;
+use image::imageops::FilterType;
+use std::path::Path;
+
+use crate::tile::Tile;
+use crate::{get_basename, save_tiles, validate_image_col_row};
+
+/// A monitor in the wall.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Screen {
+    /// Native horizontal resolution.
+    pub width: u32,
+    /// Native vertical resolution.
+    pub height: u32,
+    /// Width of the visible area in millimetres, or `0.0` if unknown.
+    pub width_mm: f64,
+    /// Height of the visible area in millimetres, or `0.0` if unknown.
+    pub height_mm: f64,
+}
+
+impl Screen {
+    /// A screen of unknown physical size, assumed to share the first screen's
+    /// pixel pitch.
+    pub fn new(width: u32, height: u32) -> Self {
+        Screen {
+            width,
+            height,
+            width_mm: 0.0,
+            height_mm: 0.0,
+        }
+    }
+
+    /// A screen with a known visible area.
+    pub fn with_size_mm(width: u32, height: u32, width_mm: f64, height_mm: f64) -> Self {
+        Screen {
+            width,
+            height,
+            width_mm,
+            height_mm,
+        }
+    }
+}
+
+/// The gap between the visible areas of two neighbouring screens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bezel {
+    /// Gap in pixels of the first screen.
+    Pixels(u32),
+    /// Gap in millimetres. Needs the first screen's physical size.
+    Millimetres(f64),
+}
+
+/// Options for `video_wall`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoWallOptions {
+    /// Screens across the wall.
+    pub columns: u32,
+    /// Screens down the wall.
+    pub rows: u32,
+    /// One screen per cell in row order, or a single screen used for every cell.
+    pub screens: Vec<Screen>,
+    /// Gap between neighbouring columns, both bezels together.
+    pub bezel_horizontal: Bezel,
+    /// Gap between neighbouring rows, both bezels together.
+    pub bezel_vertical: Bezel,
+}
+
+impl Default for VideoWallOptions {
+    fn default() -> Self {
+        VideoWallOptions {
+            columns: 2,
+            rows: 2,
+            screens: vec![Screen::new(1920, 1080)],
+            bezel_horizontal: Bezel::Pixels(0),
+            bezel_vertical: Bezel::Pixels(0),
+        }
+    }
+}
+
+/// Where the screens sit on the wall, in pixels of the first screen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WallLayout {
+    /// Width and height of the whole wall, bezels included.
+    pub size: (u32, u32),
+    /// `(x, y, width, height)` of each screen's visible area, in row order.
+    pub screens: Vec<(u32, u32, u32, u32)>,
+}
+
+/// Work out where each screen of the wall sits.
+///
+/// Screens are placed in a grid whose columns are as wide, and rows as tall, as
+/// their largest screen. Smaller screens are centered in their cell.
+///
+/// # Errors
+///
+/// Returns an error if the grid is empty or too large, the number of screens
+/// does not match it, a screen has no pixels, or a bezel is given in
+/// millimetres while the first screen's physical size is unknown.
+pub fn wall_layout(options: &VideoWallOptions) -> Result<WallLayout, String> {
+    let (columns, rows) = validate_image_col_row(options.columns, options.rows)?;
+    let cells = (columns * rows) as usize;
+    let screen = |i: usize| {
+        if options.screens.len() == 1 {
+            options.screens[0]
+        } else {
+            options.screens[i]
+        }
+    };
+    if options.screens.len() != 1 && options.screens.len() != cells {
+        return Err(format!(
+            "A {}x{} wall needs 1 or {} screens, got {}.",
+            columns,
+            rows,
+            cells,
+            options.screens.len()
+        ));
+    }
+    if options
+        .screens
+        .iter()
+        .any(|s| s.width == 0 || s.height == 0)
+    {
+        return Err("Screens must have a resolution of at least 1x1.".to_string());
+    }
+
+    // Millimetres per wall pixel, from the first screen.
+    let first = screen(0);
+    let pitch = (first.width_mm > 0.0 && first.height_mm > 0.0).then(|| {
+        (
+            first.width_mm / first.width as f64,
+            first.height_mm / first.height as f64,
+        )
+    });
+    let to_pixels = |bezel: Bezel, pitch: Option<f64>| match bezel {
+        Bezel::Pixels(px) => Ok(px),
+        Bezel::Millimetres(mm) => match pitch {
+            Some(pitch) if mm >= 0.0 => Ok((mm / pitch).round() as u32),
+            Some(_) => Err("Bezels can not be negative.".to_string()),
+            None => Err("Bezels in millimetres need the first screen's size in mm.".to_string()),
+        },
+    };
+    let gap = (
+        to_pixels(options.bezel_horizontal, pitch.map(|p| p.0))?,
+        to_pixels(options.bezel_vertical, pitch.map(|p| p.1))?,
+    );
+
+    // Each screen's visible area in wall pixels.
+    let extents: Vec<(u32, u32)> = (0..cells)
+        .map(|i| {
+            let s = screen(i);
+            match pitch {
+                Some((px, py)) if s.width_mm > 0.0 && s.height_mm > 0.0 => (
+                    ((s.width_mm / px).round() as u32).max(1),
+                    ((s.height_mm / py).round() as u32).max(1),
+                ),
+                _ => (s.width, s.height),
+            }
+        })
+        .collect();
+
+    let mut column_widths = vec![0; columns as usize];
+    let mut row_heights = vec![0; rows as usize];
+    for (i, &(w, h)) in extents.iter().enumerate() {
+        let (column, row) = (i % columns as usize, i / columns as usize);
+        column_widths[column] = column_widths[column].max(w);
+        row_heights[row] = row_heights[row].max(h);
+    }
+    let starts = |lengths: &[u32], gap: u32| {
+        let mut position = 0;
+        let mut starts = Vec::with_capacity(lengths.len());
+        for length in lengths {
+            starts.push(position);
+            position += length + gap;
+        }
+        (starts, position - gap)
+    };
+    let (xs, width) = starts(&column_widths, gap.0);
+    let (ys, height) = starts(&row_heights, gap.1);
+
+    let screens = extents
+        .iter()
+        .enumerate()
+        .map(|(i, &(w, h))| {
+            let (column, row) = (i % columns as usize, i / columns as usize);
+            (
+                xs[column] + (column_widths[column] - w) / 2,
+                ys[row] + (row_heights[row] - h) / 2,
+                w,
+                h,
+            )
+        })
+        .collect();
+    Ok(WallLayout {
+        size: (width, height),
+        screens,
+    })
+}
+
+/// Split an image file across a video wall.
+///
+/// # Arguments
+///
+/// * `filename` - The filename of the image to show on the wall.
+/// * `options` - The wall's screens and bezels.
+/// * `save` - Whether or not to save the screen images to disk.
+///
+/// # Returns
+///
+/// One tile per screen at its native resolution, in row order.
+pub fn slice_video_wall(
+    filename: &str,
+    options: &VideoWallOptions,
+    save: bool,
+) -> Result<Vec<Tile>, String> {
+    let im =
+        image::open(Path::new(filename)).map_err(|_| format!("can not open image {}", filename))?;
+    let mut tiles = video_wall(&im, options)?;
+
+    if save {
+        let prefix = get_basename(filename);
+        let directory = Path::new(filename)
+            .parent()
+            .unwrap_or_else(|| Path::new("."));
+        save_tiles(&mut tiles, &prefix, Some(directory), "png")
+            .map_err(|_| "can not save tiles")?;
+    }
+
+    Ok(tiles)
+}
+
+/// Split an in-memory image across a video wall.
+///
+/// The image is scaled to cover the whole wall, bezels included, and centered;
+/// whatever falls behind a bezel is not shown. Each tile's `coords` are its
+/// screen's position in `wall_layout`.
+///
+/// # Errors
+///
+/// Returns an error if the layout is invalid. See `wall_layout`.
+pub fn video_wall(image: &DynamicImage, options: &VideoWallOptions) -> Result<Vec<Tile>, String> {
+    let layout = wall_layout(options)?;
+    let (wall_w, wall_h) = layout.size;
+    let scale = (wall_w as f64 / image.width() as f64).max(wall_h as f64 / image.height() as f64);
+    // Resample once so the screens line up to the pixel across the bezels.
+    let scaled = image.resize_exact(
+        ((image.width() as f64 * scale).round() as u32).max(wall_w),
+        ((image.height() as f64 * scale).round() as u32).max(wall_h),
+        FilterType::CatmullRom,
+    );
+    let offset = (
+        (scaled.width() - wall_w) / 2,
+        (scaled.height() - wall_h) / 2,
+    );
+
+    let mut tiles = Vec::with_capacity(layout.screens.len());
+    for (i, &(x, y, w, h)) in layout.screens.iter().enumerate() {
+        let screen = if options.screens.len() == 1 {
+            options.screens[0]
+        } else {
+            options.screens[i]
+        };
+        let mut content = scaled.crop_imm(offset.0 + x, offset.1 + y, w, h);
+        // Screens denser than the wall's pixel grid show their part at native resolution
+        if (w, h) != (screen.width, screen.height) {
+            content = content.resize_exact(screen.width, screen.height, FilterType::CatmullRom);
+        }
+
+        let columns = options.columns as usize;
+        tiles.push(Tile::new(
+            content,
+            i as i32 + 1,
+            ((i % columns) as i32 + 1, (i / columns) as i32 + 1),
+            (x as i32, y as i32),
+            None,
+        ));
+    }
+    Ok(tiles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Rgba, RgbaImage};
+
+    #[test]
+    fn test_layout_with_bezels() {
+        let options = VideoWallOptions {
+            columns: 2,
+            rows: 1,
+            screens: vec![
+                Screen::with_size_mm(100, 50, 200.0, 100.0),
+                Screen::with_size_mm(200, 100, 200.0, 80.0),
+            ],
+            bezel_horizontal: Bezel::Millimetres(20.0),
+            bezel_vertical: Bezel::Pixels(0),
+        };
+        // 2 mm per pixel: the bezel is 10 px and the second screen 100x40 px.
+        let layout = wall_layout(&options).unwrap();
+        assert_eq!(layout.size, (210, 50));
+        assert_eq!(layout.screens, vec![(0, 0, 100, 50), (110, 5, 100, 40)]);
+
+        let unknown = VideoWallOptions {
+            screens: vec![Screen::new(100, 50)],
+            ..options.clone()
+        };
+        assert!(wall_layout(&unknown).is_err());
+        let mismatched = VideoWallOptions {
+            columns: 3,
+            ..options
+        };
+        assert!(wall_layout(&mismatched).is_err());
+    }
+
+    #[test]
+    fn test_bezel_pixels_are_skipped() {
+        // The wall is 40x20 with the bezel at x = 10..20, where the image is red.
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(40, 20, |x, _| {
+            if (10..20).contains(&x) {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 255, 255])
+            }
+        }));
+        let options = VideoWallOptions {
+            columns: 2,
+            rows: 1,
+            screens: vec![Screen::new(10, 10), Screen::new(20, 20)],
+            bezel_horizontal: Bezel::Pixels(10),
+            bezel_vertical: Bezel::Pixels(0),
+        };
+        let tiles = video_wall(&image, &options).unwrap();
+        assert_eq!(tiles[0].image.dimensions(), (10, 10));
+        assert_eq!(tiles[1].image.dimensions(), (20, 20));
+        assert_eq!(tiles[1].position, (2, 1));
+        // Neither screen shows the red band hidden behind the bezel.
+        for tile in &tiles {
+            assert!(tile.image.pixels().all(|(_, _, p)| p[0] < 128));
+        }
+    }
+
+    #[test]
+    fn test_screens_line_up() {
+        // 2x2 screens of 30x20 px cover a 60x40 wall from a 45x30 image.
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(45, 30, |x, y| {
+            Rgba([(x * 5) as u8, (y * 7) as u8, ((x * y) % 256) as u8, 255])
+        }));
+        let options = VideoWallOptions {
+            columns: 2,
+            rows: 2,
+            screens: vec![Screen::new(30, 20)],
+            bezel_horizontal: Bezel::Pixels(0),
+            bezel_vertical: Bezel::Pixels(0),
+        };
+        let tiles = video_wall(&image, &options).unwrap();
+        let whole = image.resize_exact(60, 40, FilterType::CatmullRom);
+        for tile in &tiles {
+            let (x, y) = (tile.coords.0 as u32, tile.coords.1 as u32);
+            assert_eq!(
+                tile.image.to_rgba8(),
+                whole.crop_imm(x, y, 30, 20).to_rgba8()
+            );
+        }
+    }
+}